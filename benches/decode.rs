@@ -0,0 +1,102 @@
+//! Decoding time and peak memory on a 20 megapixel carrier
+//!
+//! Run with `cargo bench --bench decode`. The peak allocation of each case is printed
+//! before it is timed, since that is what the header-bounded extraction keeps small.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use image::{Rgb, RgbImage};
+use mindbender::steganography::{header::PayloadInfo, layout::Layout, lsb};
+use std::alloc::{GlobalAlloc, Layout as AllocLayout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// System allocator that records the most memory ever held at once
+struct PeakAllocator;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAllocator {
+    unsafe fn alloc(&self, layout: AllocLayout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: AllocLayout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAllocator = PeakAllocator;
+
+/// Bytes allocated on top of what was already held, at the peak of running `f`
+fn peak_allocation(f: impl FnOnce()) -> usize {
+    let baseline = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    f();
+    PEAK.load(Ordering::Relaxed) - baseline
+}
+
+fn decode_benchmark(c: &mut Criterion) {
+    const WIDTH: u32 = 5472;
+    const HEIGHT: u32 = 3648;
+
+    // Any depth above 1 takes the serial extraction path
+    let deep = Layout {
+        depth: 2,
+        ..Layout::default()
+    };
+    let blank = RgbImage::from_pixel(WIDTH, HEIGHT, Rgb([128, 128, 128]));
+    let encoded = |data: &str, layout: &Layout| {
+        let mut image = blank.clone();
+        lsb::encode(data, &mut image, layout, PayloadInfo::default()).unwrap();
+        image
+    };
+    let small = "A short note. ".repeat(10);
+    let large = "A long document. ".repeat(100_000);
+
+    let cases = [
+        ("no message", blank.clone(), Layout::default()),
+        (
+            "small payload",
+            encoded(&small, &Layout::default()),
+            Layout::default(),
+        ),
+        (
+            "large payload",
+            encoded(&large, &Layout::default()),
+            Layout::default(),
+        ),
+        (
+            "small payload, depth 2",
+            encoded(&small, &deep),
+            deep.clone(),
+        ),
+        (
+            "large payload, depth 2",
+            encoded(&large, &deep),
+            deep.clone(),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("decode 20MP");
+    group.sample_size(10);
+    for (name, image, layout) in &cases {
+        let peak = peak_allocation(|| {
+            black_box(lsb::decode(image, layout).unwrap());
+        });
+        println!("{}: peak allocation {} bytes", name, peak);
+
+        group.bench_function(*name, |b| b.iter(|| lsb::decode(black_box(image), layout)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode_benchmark);
+criterion_main!(benches);
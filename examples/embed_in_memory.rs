@@ -0,0 +1,48 @@
+//! Hides a message in an image and reads it back without writing anything to disk
+//!
+//! Run with `cargo run --example embed_in_memory`.
+
+use mindbender::core::operations::{
+    decode_buffer, encode_buffer, inspect, CompressionOptions, DecodeOptions, EncodeOptions,
+};
+use mindbender::error::ApplicationError;
+
+const CARRIER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/example/carrier.png");
+
+fn main() -> Result<(), ApplicationError> {
+    let info = inspect(CARRIER_PATH)?;
+    println!(
+        "Carrier is a {}x{} {:?} holding up to {} bytes",
+        info.width, info.height, info.format, info.capacity_bytes
+    );
+
+    let carrier = image::open(CARRIER_PATH)?.to_rgb8();
+    let message = "Meet at the old mill at dawn";
+    let encode_options = EncodeOptions {
+        key: Some(b"correct horse battery staple".to_vec()),
+        compression: Some(CompressionOptions::default()),
+        ..EncodeOptions::default()
+    };
+    let encoded = encode_buffer(message.as_bytes(), &carrier, &encode_options)?;
+
+    let decode_options = DecodeOptions {
+        key: encode_options.key.clone(),
+        decompress: true,
+        ..DecodeOptions::default()
+    };
+    let decoded = decode_buffer(&encoded, &decode_options)?;
+    assert_eq!(decoded, message.as_bytes());
+    println!("Decoded: {}", String::from_utf8_lossy(&decoded));
+
+    // The wrong key is reported as a decryption failure rather than returning garbage
+    let wrong_key = DecodeOptions {
+        key: Some(b"wrong key".to_vec()),
+        ..decode_options
+    };
+    match decode_buffer(&encoded, &wrong_key) {
+        Err(ApplicationError::DecryptionError(e)) => println!("Wrong key rejected: {}", e),
+        other => panic!("Expected a decryption error, got {:?}", other),
+    }
+
+    Ok(())
+}
@@ -1,9 +1,23 @@
+use super::core::compression::Algorithm;
+use super::core::config::Config;
+use super::core::image::OutputImageFormat;
+use super::core::operations::{DecodeFormat, UnusedBits};
+use super::core::units::SizeUnits;
+use super::cryptography::cipher::Cipher;
+use super::cryptography::util::{KdfCost, KeyEncoding};
+use super::error::ApplicationError;
+use super::steganography::layout::Region;
 use super::ui::cli::ascii::splash;
-use clap::{Parser, Subcommand};
+use super::ui::cli::output::OutputFormat;
+use clap::parser::ValueSource;
+use clap::{ArgGroup, ArgMatches, Parser, Subcommand};
 use std::path::PathBuf;
 
 const DEFAULT_ENCODED_OUTPUT: &str = "output.png";
 const DEFAULT_DECODED_OUTPUT: &str = "decoded.txt";
+const DEFAULT_HEXDUMP_WIDTH: usize = 16;
+#[cfg(feature = "server")]
+const DEFAULT_LISTEN_ADDRESS: &str = "127.0.0.1:7878";
 
 #[derive(Parser)]
 #[command(
@@ -28,32 +42,117 @@ pub struct Cli {
     )]
     pub debug: u8,
 
+    #[arg(
+        short,
+        long,
+        conflicts_with = "debug",
+        help = "Print only the result: no progress spinner, warnings or encode summary"
+    )]
+    pub quiet: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "UNITS",
+        default_value_t = SizeUnits::Binary,
+        help = "Units used when reporting sizes and capacities"
+    )]
+    pub size_units: SizeUnits,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Text,
+        help = "Print results as text or as a single JSON object"
+    )]
+    pub format: OutputFormat,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Append a line describing each operation to this activity log"
+    )]
+    pub log_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write progress as JSON lines to this file, for monitoring without a terminal"
+    )]
+    pub progress_to_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Run encode, batch-encode or decode in full without writing any output file"
+    )]
+    pub dry_run: bool,
+
+    #[arg(
+        long,
+        help = "Print how long each phase of the operation took, in milliseconds, to stderr"
+    )]
+    pub timings: bool,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
-// @todo commands should support piping
 #[derive(Subcommand)]
 pub enum Commands {
     GenerateKey {
         #[arg(short, long, help = "Length of the key to generate")]
         length: Option<usize>,
 
+        #[arg(
+            short,
+            long,
+            value_enum,
+            value_name = "ENCODING",
+            default_value_t = KeyEncoding::Base64,
+            help = "Write the key as base64, hex, or raw bytes (raw needs --output or a pipe)"
+        )]
+        encoding: KeyEncoding,
+
         #[arg(short, long, value_name = "FILE", help = "Save key to file")]
         output: Option<PathBuf>,
     },
+    #[command(group(ArgGroup::new("key_source").args(["key", "key_stdin", "key_file"])))]
     Encode {
         #[arg(
             value_name = "DATA_FILE_PATH",
-            help = "Path to the text file containing the message to encode"
+            help = "Path to the text file containing the message to encode (`-` for stdin), or a directory or pattern such as 'notes/*.txt' whose files are bundled"
         )]
-        data_path: String,
+        data_path: Option<String>,
 
         #[arg(
             value_name = "CARRIER_FILE_PATH",
             help = "Path to the carrier image that will store the message"
         )]
-        carrier_path: String,
+        carrier_path: Option<String>,
+
+        #[arg(
+            long = "data",
+            value_name = "DATA_FILE_PATH",
+            help = "Path to the message file (`-` for stdin), given explicitly instead of as a positional"
+        )]
+        data_flag: Option<String>,
+
+        #[arg(
+            long = "carrier",
+            value_name = "CARRIER_FILE_PATH",
+            help = "Path to the carrier image, given explicitly instead of as a positional"
+        )]
+        carrier_flag: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "CARRIER_FILE_PATH",
+            num_args = 1..,
+            conflicts_with_all = ["headerless", "decoy", "canonicalize_output"],
+            help = "Split the message across the carrier and these further carriers, saving shard N as <OUTPUT>-N"
+        )]
+        split: Vec<String>,
 
         #[arg(
             short,
@@ -64,22 +163,359 @@ pub enum Commands {
         )]
         output_path: String,
 
+        #[arg(
+            long,
+            value_enum,
+            value_name = "FORMAT",
+            help = "Save the encoded image as png, bmp or tiff, replacing the output path's extension"
+        )]
+        output_format: Option<OutputImageFormat>,
+
+        #[arg(
+            short,
+            long,
+            help = "When the data path is a directory, also bundle the files in its subdirectories"
+        )]
+        recursive: bool,
+
         #[arg(
             short,
             long,
             value_name = "KEY",
-            help = "Optional encryption key to secure the message"
+            help = "Optional encryption key to secure the message (`-` to type it at a prompt)"
         )]
         key: Option<String>,
 
+        #[arg(long, help = "Read the key from the first line of stdin, for scripts")]
+        key_stdin: bool,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Read the key from a file (a trailing newline is ignored)"
+        )]
+        key_file: Option<String>,
+
+        #[arg(
+            long,
+            requires = "key_source",
+            help = "Use the key bytes directly (zero-padded to 32 bytes) instead of deriving a key with Argon2id"
+        )]
+        raw_key: bool,
+
+        #[arg(
+            long,
+            requires = "key_source",
+            help = "Fail instead of encoding when the key is weak"
+        )]
+        require_strong_key: bool,
+
+        #[arg(
+            long,
+            requires = "key_source",
+            conflicts_with = "split",
+            help = "Bind the ciphertext to the carrier's dimensions, so it only decrypts from an image of the same size"
+        )]
+        bind_carrier: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = Cipher::default(),
+            requires = "key_source",
+            help = "Cipher used to encrypt the message"
+        )]
+        cipher: Cipher,
+
+        #[arg(
+            long,
+            value_enum,
+            value_name = "COST",
+            default_value_t = KdfCost::default(),
+            requires = "key_source",
+            conflicts_with = "raw_key",
+            help = "Memory and time spent deriving the cipher key from the passphrase"
+        )]
+        kdf_cost: KdfCost,
+
         #[arg(
             short,
             long,
             help = "Compress the message before embedding it into the carrier image"
         )]
         compress: bool,
+
+        #[arg(
+            long,
+            overrides_with = "compress",
+            conflicts_with_all = ["compression", "compression_level"],
+            help = "Don't compress the message, even if the config file sets compress"
+        )]
+        no_compress: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            value_name = "ALGORITHM",
+            help = "Compression algorithm (implies --compress) [default: zlib]"
+        )]
+        compression: Option<Algorithm>,
+
+        #[arg(
+            long,
+            value_name = "LEVEL",
+            help = "Compression level, from fastest to smallest (implies --compress)"
+        )]
+        compression_level: Option<u32>,
+
+        #[arg(
+            long,
+            help = "Embed the message without a header (expert use: the decoder must pass the exact --length)"
+        )]
+        headerless: bool,
+
+        #[arg(
+            long,
+            help = "Avoid embedding in perceptually salient pixels such as strong edges (decode needs --palette-safe too)"
+        )]
+        palette_safe: bool,
+
+        #[arg(
+            long,
+            value_name = "SEED",
+            conflicts_with = "decoy",
+            help = "Spread the message over the image in a pseudo-random order seeded by SEED (decode needs the same --seed)"
+        )]
+        seed: Option<u64>,
+
+        #[arg(
+            long,
+            requires = "key_source",
+            conflicts_with_all = ["seed", "decoy"],
+            help = "Spread the message over the image in a pseudo-random order derived from the key (decode needs --scatter too)"
+        )]
+        scatter: bool,
+
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 1,
+            value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=255),
+            conflicts_with = "headerless",
+            help = "Embed into every Nth pixel only, dividing capacity by N (recorded in the header)"
+        )]
+        stride: usize,
+
+        #[arg(
+            long,
+            value_name = "BITS",
+            default_value_t = 1,
+            value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=4),
+            conflicts_with = "headerless",
+            help = "Least significant bits used per channel; more bits add capacity but degrade the image (recorded in the header)"
+        )]
+        depth: usize,
+
+        #[arg(
+            long,
+            conflicts_with = "headerless",
+            help = "Also embed in the alpha channel of RGBA carriers, adding a third more capacity (recorded in the header)"
+        )]
+        use_alpha: bool,
+
+        #[arg(
+            long,
+            value_name = "X,Y,WIDTH,HEIGHT",
+            help = "Embed only in the pixels inside this rectangle, leaving the rest untouched (decode needs the same --region)"
+        )]
+        region: Option<Region>,
+
+        #[arg(
+            long,
+            value_enum,
+            value_name = "POLICY",
+            default_value_t = UnusedBits::Auto,
+            conflicts_with = "headerless",
+            help = "What to do with carrier bits the message does not use"
+        )]
+        unused_bits: UnusedBits,
+
+        #[arg(
+            long,
+            conflicts_with_all = ["headerless", "unused_bits"],
+            help = "Randomize every carrier bit the message does not use, hiding its length (same as --unused-bits randomize)"
+        )]
+        fill: bool,
+
+        #[arg(
+            long,
+            value_name = "DECOY_FILE_PATH",
+            requires_all = ["key_source", "duress_key"],
+            conflicts_with = "headerless",
+            help = "Also embed a decoy message that is revealed by the duress key instead of the real one"
+        )]
+        decoy: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "KEY",
+            requires = "decoy",
+            help = "Key that reveals the decoy message"
+        )]
+        duress_key: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "DB",
+            help = "Lowest PSNR, in decibels, the encoded image may have against the carrier; abort below it"
+        )]
+        min_psnr: Option<f64>,
+
+        #[arg(
+            long,
+            value_name = "MAP_FILE_PATH",
+            conflicts_with = "split",
+            help = "Also save an image highlighting in red every pixel the encoding changed"
+        )]
+        diff_map: Option<String>,
+
+        #[arg(
+            long,
+            help = "Copy the carrier's PNG text and time chunks into the output (stripped by default)"
+        )]
+        keep_metadata: bool,
+
+        #[arg(
+            long,
+            conflicts_with_all = ["split", "decoy", "palette_safe", "seed", "scatter", "region", "fill", "min_psnr", "diff_map", "keep_metadata"],
+            help = "Stream a PNG or TIFF carrier a strip of rows at a time instead of loading it whole, for carriers too large for memory"
+        )]
+        streaming: bool,
+
+        #[arg(
+            long,
+            help = "Print the absolute path of the encoded image after it is written"
+        )]
+        canonicalize_output: bool,
+
+        #[arg(
+            long,
+            conflicts_with = "backup",
+            help = "Refuse to overwrite an existing output file"
+        )]
+        no_clobber: bool,
+
+        #[arg(
+            long,
+            help = "Rename an existing output file to <OUTPUT>.bak before writing"
+        )]
+        backup: bool,
+    },
+
+    #[command(group(ArgGroup::new("key_source").args(["key", "key_stdin", "key_file"])))]
+    BatchEncode {
+        #[arg(
+            value_name = "DATA_FILE_PATH",
+            help = "Path to the text file containing the message to encode (`-` for stdin)"
+        )]
+        data_path: String,
+
+        #[arg(
+            value_name = "CARRIER_DIR",
+            help = "Directory whose images will each store the message"
+        )]
+        carrier_dir: String,
+
+        #[arg(
+            value_name = "OUTPUT_DIR",
+            help = "Directory where each encoded image is saved as <name>-encoded.png (<name>-1-encoded.png, ... when names collide)"
+        )]
+        output_dir: String,
+
+        #[arg(
+            short,
+            long,
+            value_name = "KEY",
+            help = "Optional encryption key to secure the message (`-` to type it at a prompt)"
+        )]
+        key: Option<String>,
+
+        #[arg(long, help = "Read the key from the first line of stdin, for scripts")]
+        key_stdin: bool,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Read the key from a file (a trailing newline is ignored)"
+        )]
+        key_file: Option<String>,
+
+        #[arg(
+            long,
+            requires = "key_source",
+            help = "Use the key bytes directly (zero-padded to 32 bytes) instead of deriving a key with Argon2id"
+        )]
+        raw_key: bool,
+
+        #[arg(
+            long,
+            requires = "key_source",
+            help = "Fail instead of encoding when the key is weak"
+        )]
+        require_strong_key: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = Cipher::default(),
+            requires = "key_source",
+            help = "Cipher used to encrypt the message"
+        )]
+        cipher: Cipher,
+
+        #[arg(
+            long,
+            value_enum,
+            value_name = "COST",
+            default_value_t = KdfCost::default(),
+            requires = "key_source",
+            conflicts_with = "raw_key",
+            help = "Memory and time spent deriving the cipher key from the passphrase"
+        )]
+        kdf_cost: KdfCost,
+
+        #[arg(
+            short,
+            long,
+            help = "Compress the message before embedding it into the carrier images"
+        )]
+        compress: bool,
+
+        #[arg(
+            long,
+            overrides_with = "compress",
+            conflicts_with_all = ["compression", "compression_level"],
+            help = "Don't compress the message, even if the config file sets compress"
+        )]
+        no_compress: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            value_name = "ALGORITHM",
+            help = "Compression algorithm (implies --compress) [default: zlib]"
+        )]
+        compression: Option<Algorithm>,
+
+        #[arg(
+            long,
+            value_name = "LEVEL",
+            help = "Compression level, from fastest to smallest (implies --compress)"
+        )]
+        compression_level: Option<u32>,
     },
 
+    #[command(group(ArgGroup::new("key_source").args(["key", "key_stdin", "key_file"])))]
     Decode {
         #[arg(
             value_name = "CARRIER_FILE_PATH",
@@ -92,7 +528,7 @@ pub enum Commands {
             long,
             value_name = "OUTPUT_FILE_PATH",
             default_value = DEFAULT_DECODED_OUTPUT,
-            help = "Path where the decoded message will be saved"
+            help = "Path where the decoded message will be saved (`-` for stdout)"
         )]
         output_path: String,
 
@@ -100,25 +536,407 @@ pub enum Commands {
             short,
             long,
             value_name = "KEY",
-            help = "Decryption key (required if message was encrypted)"
+            help = "Decryption key (required if message was encrypted, `-` to type it at a prompt)"
         )]
         key: Option<String>,
 
+        #[arg(long, help = "Read the key from the first line of stdin, for scripts")]
+        key_stdin: bool,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Read the key from a file (a trailing newline is ignored)"
+        )]
+        key_file: Option<String>,
+
+        #[arg(
+            long,
+            requires = "key_source",
+            help = "Treat the key as raw bytes, as when encoded with --raw-key or by older releases"
+        )]
+        raw_key: bool,
+
+        #[arg(
+            long,
+            requires = "key_source",
+            conflicts_with = "join",
+            help = "Read a message encoded with --bind-carrier"
+        )]
+        bind_carrier: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            requires = "key_source",
+            help = "Expected cipher; detected from the message when omitted"
+        )]
+        cipher: Option<Cipher>,
+
         #[arg(
             short,
             long,
             help = "Decompress the message after extracting it from the carrier image"
         )]
         decompress: bool,
-    },
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use clap::CommandFactory;
+        #[arg(
+            long,
+            help = "Print a hex/ASCII dump of the decoded message to stdout instead of saving it"
+        )]
+        hexdump: bool,
 
-    #[test]
+        #[arg(
+            short,
+            long,
+            value_name = "N",
+            default_value_t = DEFAULT_HEXDUMP_WIDTH,
+            value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=256),
+            requires = "hexdump",
+            help = "Number of bytes per line in the hex dump"
+        )]
+        width: usize,
+
+        #[arg(
+            long,
+            requires = "length",
+            help = "Read a message that was embedded without a header"
+        )]
+        headerless: bool,
+
+        #[arg(
+            long,
+            value_name = "BYTES",
+            requires = "headerless",
+            help = "Exact number of embedded bytes to read in headerless mode"
+        )]
+        length: Option<usize>,
+
+        #[arg(
+            long,
+            conflicts_with_all = ["headerless", "join"],
+            help = "Read a message from an older release, up to its null byte, even if it looks like it has a header"
+        )]
+        legacy_delimiter: bool,
+
+        #[arg(long, help = "Read a message embedded with --palette-safe")]
+        palette_safe: bool,
+
+        #[arg(
+            long,
+            value_name = "SEED",
+            help = "Read a message embedded with --seed SEED"
+        )]
+        seed: Option<u64>,
+
+        #[arg(
+            long,
+            value_name = "X,Y,WIDTH,HEIGHT",
+            help = "Read a message embedded with --region X,Y,WIDTH,HEIGHT"
+        )]
+        region: Option<Region>,
+
+        #[arg(
+            long,
+            requires = "key_source",
+            conflicts_with = "seed",
+            help = "Read a message embedded with --scatter"
+        )]
+        scatter: bool,
+
+        #[arg(
+            long,
+            conflicts_with = "hexdump",
+            help = "Print the absolute path of the decoded message after it is written"
+        )]
+        canonicalize_output: bool,
+
+        #[arg(
+            long,
+            conflicts_with = "backup",
+            help = "Refuse to overwrite an existing output file"
+        )]
+        no_clobber: bool,
+
+        #[arg(
+            long,
+            help = "Rename an existing output file to <OUTPUT>.bak before writing"
+        )]
+        backup: bool,
+
+        #[arg(
+            long,
+            value_name = "REFERENCE_FILE_PATH",
+            conflicts_with_all = ["hexdump", "canonicalize_output"],
+            help = "Compare the decoded message against a reference file instead of saving it"
+        )]
+        verify_against: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "CARRIER_FILE_PATH",
+            num_args = 1..,
+            conflicts_with = "headerless",
+            help = "Reassemble a message split with encode --split from the carrier and these other shards"
+        )]
+        join: Vec<String>,
+
+        #[arg(
+            long,
+            help = "Replace invalid UTF-8 in a damaged message with U+FFFD instead of failing"
+        )]
+        lossy_utf8: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            value_name = "FORMAT",
+            default_value_t = DecodeFormat::default(),
+            help = "Framing of the hidden message: mindbender's own, or plain bytes from another LSB tool"
+        )]
+        message_format: DecodeFormat,
+
+        #[arg(
+            long,
+            value_name = "TEXT",
+            help = "With --message-format raw-lsb, read up to this delimiter instead of a 4-byte length prefix"
+        )]
+        delimiter: Option<String>,
+    },
+
+    #[command(group(ArgGroup::new("key_source").args(["key", "key_stdin", "key_file"])))]
+    Verify {
+        #[arg(
+            value_name = "CARRIER_FILE_PATH",
+            help = "Path to the image that should contain a hidden message"
+        )]
+        carrier_path: String,
+
+        #[arg(
+            short,
+            long,
+            value_name = "KEY",
+            help = "Decryption key (required if message was encrypted, `-` to type it at a prompt)"
+        )]
+        key: Option<String>,
+
+        #[arg(long, help = "Read the key from the first line of stdin, for scripts")]
+        key_stdin: bool,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Read the key from a file (a trailing newline is ignored)"
+        )]
+        key_file: Option<String>,
+
+        #[arg(
+            long,
+            requires = "key_source",
+            help = "Treat the key as raw bytes, as when encoded with --raw-key or by older releases"
+        )]
+        raw_key: bool,
+
+        #[arg(
+            long,
+            requires = "key_source",
+            conflicts_with = "join",
+            help = "Verify a message encoded with --bind-carrier"
+        )]
+        bind_carrier: bool,
+
+        #[arg(
+            short,
+            long,
+            help = "Decompress the message after extracting it from the carrier image"
+        )]
+        decompress: bool,
+
+        #[arg(long, help = "Read a message embedded with --palette-safe")]
+        palette_safe: bool,
+
+        #[arg(
+            long,
+            value_name = "SEED",
+            help = "Read a message embedded with --seed SEED"
+        )]
+        seed: Option<u64>,
+
+        #[arg(
+            long,
+            value_name = "X,Y,WIDTH,HEIGHT",
+            help = "Read a message embedded with --region X,Y,WIDTH,HEIGHT"
+        )]
+        region: Option<Region>,
+
+        #[arg(
+            long,
+            requires = "key_source",
+            conflicts_with = "seed",
+            help = "Read a message embedded with --scatter"
+        )]
+        scatter: bool,
+
+        #[arg(
+            long,
+            value_name = "CARRIER_FILE_PATH",
+            num_args = 1..,
+            help = "Verify a message split with encode --split, given the carriers of the other shards"
+        )]
+        join: Vec<String>,
+    },
+
+    Detect {
+        #[arg(
+            value_name = "CARRIER_FILE_PATH",
+            help = "Path to the image to screen for hidden data"
+        )]
+        carrier_path: String,
+    },
+
+    Inspect {
+        #[arg(
+            value_name = "CARRIER_FILE_PATH",
+            help = "Path to the image to report on"
+        )]
+        carrier_path: String,
+    },
+
+    DumpHeader {
+        #[arg(
+            value_name = "CARRIER_FILE_PATH",
+            help = "Path to the image containing the hidden message"
+        )]
+        carrier_path: String,
+
+        #[arg(long, help = "Read a header embedded with --palette-safe")]
+        palette_safe: bool,
+
+        #[arg(
+            long,
+            value_name = "SEED",
+            help = "Read a header embedded with --seed SEED"
+        )]
+        seed: Option<u64>,
+
+        #[arg(
+            long,
+            value_name = "X,Y,WIDTH,HEIGHT",
+            help = "Read a header embedded with --region X,Y,WIDTH,HEIGHT"
+        )]
+        region: Option<Region>,
+    },
+
+    Capacity {
+        #[arg(
+            value_name = "CARRIER_FILE_PATH",
+            help = "Path to the image to measure"
+        )]
+        carrier_path: String,
+    },
+
+    #[cfg(feature = "server")]
+    Serve {
+        #[arg(
+            short,
+            long,
+            value_name = "ADDR:PORT",
+            default_value = DEFAULT_LISTEN_ADDRESS,
+            help = "Address to accept encode/decode requests on"
+        )]
+        listen: String,
+    },
+}
+
+impl Commands {
+    /// Fill in the options `matches` shows were left out on the command line from `config`
+    pub fn with_config(mut self, config: &Config, matches: &ArgMatches) -> Self {
+        let omitted = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+
+        match &mut self {
+            Commands::Encode {
+                output_path,
+                cipher,
+                compress,
+                headerless,
+                depth,
+                ..
+            } => {
+                if let Some(default_output) = config.default_output.as_ref() {
+                    if omitted("output_path") {
+                        output_path.clone_from(default_output);
+                    }
+                }
+                if let Some(default_cipher) = config.cipher.filter(|_| omitted("cipher")) {
+                    *cipher = default_cipher;
+                }
+                // Headerless payloads don't record their depth, so only --depth sets it
+                if let Some(default_depth) = config.depth.filter(|_| omitted("depth")) {
+                    if !*headerless {
+                        *depth = default_depth;
+                    }
+                }
+                if config.compress && omitted("compress") && omitted("no_compress") {
+                    *compress = true;
+                }
+            }
+            Commands::BatchEncode {
+                cipher, compress, ..
+            } => {
+                if let Some(default_cipher) = config.cipher.filter(|_| omitted("cipher")) {
+                    *cipher = default_cipher;
+                }
+                if config.compress && omitted("compress") && omitted("no_compress") {
+                    *compress = true;
+                }
+            }
+            _ => {}
+        }
+
+        self
+    }
+}
+
+/// Resolve the encode inputs from the `--data`/`--carrier` flags and the positionals
+///
+/// Named flags take precedence; positionals fill whichever inputs remain, in order,
+/// so `encode secret.txt --carrier photo.png` and `encode --data secret.txt photo.png`
+/// both work
+pub fn resolve_encode_inputs(
+    data_path: Option<&str>,
+    carrier_path: Option<&str>,
+    data_flag: Option<&str>,
+    carrier_flag: Option<&str>,
+) -> Result<(String, String), ApplicationError> {
+    let mut positionals = data_path.into_iter().chain(carrier_path);
+    let mut resolve = |flag: Option<&str>, name: &str, long: &str| {
+        flag.or_else(|| positionals.next())
+            .map(str::to_string)
+            .ok_or_else(|| {
+                ApplicationError::InvalidPathError(format!(
+                    "Missing {} path (pass it as a positional or with {})",
+                    name, long
+                ))
+            })
+    };
+    let data = resolve(data_flag, "data file", "--data")?;
+    let carrier = resolve(carrier_flag, "carrier image", "--carrier")?;
+
+    match positionals.next() {
+        Some(extra) => Err(ApplicationError::InvalidPathError(format!(
+            "Unexpected positional argument '{}'",
+            extra
+        ))),
+        None => Ok((data, carrier)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+
+    #[test]
     fn verify_cli() {
         Cli::command().debug_assert();
     }
@@ -137,6 +955,128 @@ mod tests {
         }
     }
 
+    fn parse_with_config(args: &[&str], config: &Config) -> Commands {
+        let matches = Cli::command().get_matches_from(args);
+        let command = Cli::from_arg_matches(&matches).unwrap().command.unwrap();
+        command.with_config(config, matches.subcommand().unwrap().1)
+    }
+
+    #[test]
+    fn test_config_fills_omitted_options() {
+        let config = Config {
+            default_output: Some("hidden.png".to_string()),
+            cipher: Some(Cipher::Chacha),
+            compress: true,
+            depth: Some(2),
+        };
+
+        let args = ["program", "encode", "m.txt", "in.png"];
+        match parse_with_config(&args, &config) {
+            Commands::Encode {
+                output_path,
+                cipher,
+                compress,
+                depth,
+                ..
+            } => {
+                assert_eq!(output_path, "hidden.png");
+                assert_eq!(cipher, Cipher::Chacha);
+                assert!(compress);
+                assert_eq!(depth, 2);
+            }
+            _ => panic!("Wrong command parsed"),
+        }
+
+        // Compression is an encode setting; decoding still needs --decompress
+        let args = ["program", "decode", "in.png"];
+        match parse_with_config(&args, &config) {
+            Commands::Decode { decompress, .. } => assert!(!decompress),
+            _ => panic!("Wrong command parsed"),
+        }
+    }
+
+    #[test]
+    fn test_no_compress_overrides_config() {
+        let config = Config {
+            compress: true,
+            ..Config::default()
+        };
+
+        let args = ["program", "encode", "m.txt", "in.png", "--no-compress"];
+        match parse_with_config(&args, &config) {
+            Commands::Encode { compress, .. } => assert!(!compress),
+            _ => panic!("Wrong command parsed"),
+        }
+
+        let args = [
+            "program",
+            "batch-encode",
+            "m.txt",
+            "in",
+            "out",
+            "--no-compress",
+        ];
+        match parse_with_config(&args, &config) {
+            Commands::BatchEncode { compress, .. } => assert!(!compress),
+            _ => panic!("Wrong command parsed"),
+        }
+
+        // The last of --compress and --no-compress wins
+        let args = [
+            "program",
+            "encode",
+            "m.txt",
+            "in.png",
+            "-c",
+            "--no-compress",
+        ];
+        match parse_with_config(&args, &config) {
+            Commands::Encode { compress, .. } => assert!(!compress),
+            _ => panic!("Wrong command parsed"),
+        }
+
+        let args = [
+            "program",
+            "encode",
+            "m.txt",
+            "in.png",
+            "--no-compress",
+            "-c",
+        ];
+        match parse_with_config(&args, &config) {
+            Commands::Encode { compress, .. } => assert!(compress),
+            _ => panic!("Wrong command parsed"),
+        }
+    }
+
+    #[test]
+    fn test_flags_override_config() {
+        let config = Config {
+            default_output: Some("hidden.png".to_string()),
+            cipher: Some(Cipher::Chacha),
+            depth: Some(2),
+            ..Config::default()
+        };
+        let args = [
+            "program", "encode", "m.txt", "in.png", "-o", "out.png", "-k", "key", "--cipher",
+            "aes", "--depth", "1",
+        ];
+
+        match parse_with_config(&args, &config) {
+            Commands::Encode {
+                output_path,
+                cipher,
+                depth,
+                ..
+            } => {
+                assert_eq!(output_path, "out.png");
+                assert_eq!(cipher, Cipher::Aes);
+                assert_eq!(depth, 1);
+            }
+            _ => panic!("Wrong command parsed"),
+        }
+    }
+
     #[test]
     fn test_debug_flag_count() {
         let args = ["program", "-ddd", "encode", "message.txt", "input.png"];
@@ -145,6 +1085,15 @@ mod tests {
         assert_eq!(cli.debug, 3);
     }
 
+    #[test]
+    fn test_quiet_conflicts_with_debug() {
+        let cli = Cli::parse_from(["program", "-q", "encode", "message.txt", "input.png"]);
+        assert!(cli.quiet);
+
+        let args = ["program", "-q", "-d", "encode", "message.txt", "input.png"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
     #[test]
     fn test_optional_key() {
         let args = [
@@ -166,6 +1115,409 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_key_stdin() {
+        let args = [
+            "program",
+            "encode",
+            "message.txt",
+            "input.png",
+            "--key-stdin",
+            "--scatter",
+        ];
+        match Cli::parse_from(args).command.unwrap() {
+            Commands::Encode { key, key_stdin, .. } => {
+                assert_eq!(key, None);
+                assert!(key_stdin);
+            }
+            _ => panic!("Wrong command parsed"),
+        }
+
+        let both = ["program", "decode", "in.png", "--key", "k", "--key-stdin"];
+        assert!(Cli::try_parse_from(both).is_err());
+    }
+
+    #[test]
+    fn test_key_file_conflicts_with_key() {
+        let args = ["program", "decode", "in.png", "--key-file", "key.txt"];
+        match Cli::parse_from(args).command.unwrap() {
+            Commands::Decode { key, key_file, .. } => {
+                assert_eq!(key, None);
+                assert_eq!(key_file, Some("key.txt".to_string()));
+            }
+            _ => panic!("Wrong command parsed"),
+        }
+
+        let both = [
+            "program",
+            "encode",
+            "m.txt",
+            "in.png",
+            "--key",
+            "k",
+            "--key-file",
+            "key.txt",
+        ];
+        assert!(Cli::try_parse_from(both).is_err());
+    }
+
+    #[test]
+    fn test_compress_flags() {
+        let args = [
+            "program",
+            "encode",
+            "message.txt",
+            "input.png",
+            "--compress",
+        ];
+        match Cli::parse_from(args).command.unwrap() {
+            Commands::Encode { compress, .. } => assert!(compress),
+            _ => panic!("Wrong command parsed"),
+        }
+
+        match Cli::parse_from(["program", "encode", "message.txt", "input.png"])
+            .command
+            .unwrap()
+        {
+            Commands::Encode { compress, .. } => assert!(!compress),
+            _ => panic!("Wrong command parsed"),
+        }
+
+        match Cli::parse_from(["program", "decode", "input.png", "-d"])
+            .command
+            .unwrap()
+        {
+            Commands::Decode { decompress, .. } => assert!(decompress),
+            _ => panic!("Wrong command parsed"),
+        }
+    }
+
+    #[test]
+    fn test_generate_key_length() {
+        let args = ["program", "generate-key", "--length", "16"];
+
+        let cli = Cli::parse_from(args);
+
+        match cli.command.unwrap() {
+            Commands::GenerateKey {
+                length,
+                encoding,
+                output,
+            } => {
+                assert_eq!(length, Some(16));
+                assert_eq!(encoding, KeyEncoding::Base64);
+                assert_eq!(output, None);
+            }
+            _ => panic!("Wrong command parsed"),
+        }
+    }
+
+    #[test]
+    fn test_output_format() {
+        let cli = Cli::parse_from(["program", "detect", "input.png"]);
+        assert_eq!(cli.format, OutputFormat::Text);
+
+        let cli = Cli::parse_from(["program", "--format", "json", "detect", "input.png"]);
+        assert_eq!(cli.format, OutputFormat::Json);
+    }
+
+    #[test]
+    fn test_size_units() {
+        let cli = Cli::parse_from(["program", "encode", "message.txt", "input.png"]);
+        assert_eq!(cli.size_units, SizeUnits::Binary);
+
+        let args = [
+            "program",
+            "--size-units",
+            "decimal",
+            "encode",
+            "message.txt",
+            "input.png",
+        ];
+        let cli = Cli::parse_from(args);
+        assert_eq!(cli.size_units, SizeUnits::Decimal);
+    }
+
+    #[test]
+    fn test_headerless_requires_length() {
+        let args = ["program", "decode", "input.png", "--headerless"];
+        assert!(Cli::try_parse_from(args).is_err());
+
+        let args = [
+            "program",
+            "decode",
+            "input.png",
+            "--headerless",
+            "--length",
+            "42",
+        ];
+        match Cli::parse_from(args).command.unwrap() {
+            Commands::Decode {
+                headerless, length, ..
+            } => {
+                assert!(headerless);
+                assert_eq!(length, Some(42));
+            }
+            _ => panic!("Wrong command parsed"),
+        }
+    }
+
+    #[test]
+    fn test_hexdump_width() {
+        let args = [
+            "program",
+            "decode",
+            "input.png",
+            "--hexdump",
+            "--width",
+            "8",
+        ];
+
+        let cli = Cli::parse_from(args);
+
+        match cli.command.unwrap() {
+            Commands::Decode { hexdump, width, .. } => {
+                assert!(hexdump);
+                assert_eq!(width, 8);
+            }
+            _ => panic!("Wrong command parsed"),
+        }
+    }
+
+    #[test]
+    fn test_width_requires_hexdump() {
+        let args = ["program", "decode", "input.png", "--width", "8"];
+
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_width_is_bounded() {
+        let args = ["program", "decode", "input.png", "--hexdump", "--width"];
+
+        assert!(Cli::try_parse_from(args.iter().chain(&["256"])).is_ok());
+        assert!(Cli::try_parse_from(args.iter().chain(&["257"])).is_err());
+        assert!(Cli::try_parse_from(args.iter().chain(&["0"])).is_err());
+    }
+
+    #[test]
+    fn test_stride() {
+        let args = ["program", "encode", "m.txt", "in.png", "--stride", "3"];
+        match Cli::parse_from(args).command.unwrap() {
+            Commands::Encode { stride, .. } => assert_eq!(stride, 3),
+            _ => panic!("Wrong command parsed"),
+        }
+
+        let args = ["program", "encode", "m.txt", "in.png", "--stride", "0"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_depth() {
+        let args = ["program", "encode", "m.txt", "in.png", "--depth", "2"];
+        match Cli::parse_from(args).command.unwrap() {
+            Commands::Encode { depth, .. } => assert_eq!(depth, 2),
+            _ => panic!("Wrong command parsed"),
+        }
+
+        for depth in ["0", "5"] {
+            let args = ["program", "encode", "m.txt", "in.png", "--depth", depth];
+            assert!(Cli::try_parse_from(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_seed_and_scatter() {
+        let args = ["program", "decode", "in.png", "--seed", "42"];
+        match Cli::parse_from(args).command.unwrap() {
+            Commands::Decode { seed, scatter, .. } => {
+                assert_eq!(seed, Some(42));
+                assert!(!scatter);
+            }
+            _ => panic!("Wrong command parsed"),
+        }
+
+        let args = ["program", "encode", "m.txt", "in.png", "--scatter"];
+        assert!(Cli::try_parse_from(args).is_err());
+
+        let args = [
+            "program",
+            "encode",
+            "m.txt",
+            "in.png",
+            "-k",
+            "key",
+            "--scatter",
+            "--seed",
+            "1",
+        ];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_decoy_requires_keys() {
+        let args = ["program", "encode", "m.txt", "in.png", "--decoy", "d.txt"];
+        assert!(Cli::try_parse_from(args).is_err());
+
+        let args = [
+            "program",
+            "encode",
+            "m.txt",
+            "in.png",
+            "--key",
+            "real",
+            "--decoy",
+            "d.txt",
+            "--duress-key",
+            "duress",
+        ];
+        match Cli::parse_from(args).command.unwrap() {
+            Commands::Encode {
+                decoy, duress_key, ..
+            } => {
+                assert_eq!(decoy, Some("d.txt".to_string()));
+                assert_eq!(duress_key, Some("duress".to_string()));
+            }
+            _ => panic!("Wrong command parsed"),
+        }
+    }
+
+    fn parse_encode_inputs(args: &[&str]) -> Result<(String, String), ApplicationError> {
+        match Cli::parse_from(args).command.unwrap() {
+            Commands::Encode {
+                data_path,
+                carrier_path,
+                data_flag,
+                carrier_flag,
+                ..
+            } => resolve_encode_inputs(
+                data_path.as_deref(),
+                carrier_path.as_deref(),
+                data_flag.as_deref(),
+                carrier_flag.as_deref(),
+            ),
+            _ => panic!("Wrong command parsed"),
+        }
+    }
+
+    #[test]
+    fn test_encode_positional_inputs() {
+        let inputs = parse_encode_inputs(&["program", "encode", "secret.txt", "photo.png"]);
+
+        assert_eq!(
+            inputs.unwrap(),
+            ("secret.txt".to_string(), "photo.png".to_string())
+        );
+    }
+
+    #[test]
+    fn test_encode_named_inputs() {
+        let args = [
+            "program",
+            "encode",
+            "--carrier",
+            "photo.png",
+            "--data",
+            "secret.txt",
+        ];
+
+        assert_eq!(
+            parse_encode_inputs(&args).unwrap(),
+            ("secret.txt".to_string(), "photo.png".to_string())
+        );
+    }
+
+    #[test]
+    fn test_encode_mixed_inputs() {
+        let args = ["program", "encode", "secret.txt", "--carrier", "photo.png"];
+        assert_eq!(
+            parse_encode_inputs(&args).unwrap(),
+            ("secret.txt".to_string(), "photo.png".to_string())
+        );
+
+        let args = ["program", "encode", "--data", "secret.txt", "photo.png"];
+        assert_eq!(
+            parse_encode_inputs(&args).unwrap(),
+            ("secret.txt".to_string(), "photo.png".to_string())
+        );
+    }
+
+    #[test]
+    fn test_encode_inputs_missing_or_extra() {
+        assert!(parse_encode_inputs(&["program", "encode", "secret.txt"]).is_err());
+
+        let args = [
+            "program",
+            "encode",
+            "extra.txt",
+            "--data",
+            "secret.txt",
+            "--carrier",
+            "photo.png",
+        ];
+        assert!(parse_encode_inputs(&args).is_err());
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        let args = [
+            "program", "encode", "m.txt", "a.png", "--split", "b.png", "c.png",
+        ];
+        match Cli::parse_from(args).command.unwrap() {
+            Commands::Encode { split, .. } => assert_eq!(split, ["b.png", "c.png"]),
+            _ => panic!("Wrong command parsed"),
+        }
+
+        let args = ["program", "decode", "a.png", "--join", "b.png", "c.png"];
+        match Cli::parse_from(args).command.unwrap() {
+            Commands::Decode { join, .. } => assert_eq!(join, ["b.png", "c.png"]),
+            _ => panic!("Wrong command parsed"),
+        }
+
+        let args = [
+            "program",
+            "encode",
+            "m.txt",
+            "a.png",
+            "--split",
+            "b.png",
+            "--headerless",
+        ];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_batch_encode() {
+        let args = [
+            "program",
+            "batch-encode",
+            "watermark.txt",
+            "photos",
+            "out",
+            "--key",
+            "secret",
+        ];
+
+        match Cli::parse_from(args).command.unwrap() {
+            Commands::BatchEncode {
+                data_path,
+                carrier_dir,
+                output_dir,
+                key,
+                ..
+            } => {
+                assert_eq!(data_path, "watermark.txt");
+                assert_eq!(carrier_dir, "photos");
+                assert_eq!(output_dir, "out");
+                assert_eq!(key, Some("secret".to_string()));
+            }
+            _ => panic!("Wrong command parsed"),
+        }
+
+        let args = ["program", "batch-encode", "watermark.txt", "photos"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
     #[test]
     fn test_no_arguments_triggers_tui() {
         let args = ["program"];
@@ -0,0 +1,103 @@
+use crate::error::ApplicationError;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Description of a single operation, recorded in the activity log once it finishes
+#[derive(Debug, Default)]
+pub struct ActivityEntry {
+    pub operation: &'static str,
+    pub carrier: Option<String>,
+    pub output: Option<String>,
+    pub payload_bytes: Option<usize>,
+}
+
+impl ActivityEntry {
+    pub fn new(operation: &'static str) -> Self {
+        Self {
+            operation,
+            ..Self::default()
+        }
+    }
+
+    /// Format the entry as a single tab-separated line, ending with the operation's outcome
+    pub fn to_line(&self, timestamp: SystemTime, outcome: &Result<(), ApplicationError>) -> String {
+        let field = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        let status = match outcome {
+            Ok(()) => "ok".to_string(),
+            Err(e) => format!("error: {}", e).replace(['\n', '\t'], " "),
+        };
+
+        format!(
+            "{}\t{}\tcarrier={}\toutput={}\tpayload={}\t{}",
+            humantime::format_rfc3339_seconds(timestamp),
+            self.operation,
+            field(self.carrier.clone()),
+            field(self.output.clone()),
+            field(self.payload_bytes.map(|bytes| bytes.to_string())),
+            status
+        )
+    }
+}
+
+/// Append one line describing a finished operation to the log at `log_path`
+pub fn append(
+    log_path: &Path,
+    entry: &ActivityEntry,
+    outcome: &Result<(), ApplicationError>,
+) -> Result<(), ApplicationError> {
+    if let Some(parent) = log_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)?;
+    writeln!(file, "{}", entry.to_line(SystemTime::now(), outcome))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    fn entry() -> ActivityEntry {
+        ActivityEntry {
+            carrier: Some("carrier.png".to_string()),
+            output: Some("output.png".to_string()),
+            payload_bytes: Some(42),
+            ..ActivityEntry::new("encode")
+        }
+    }
+
+    #[test]
+    fn test_to_line() {
+        let timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs(86_400);
+
+        assert_eq!(
+            entry().to_line(timestamp, &Ok(())),
+            "1970-01-02T00:00:00Z\tencode\tcarrier=carrier.png\toutput=output.png\tpayload=42\tok"
+        );
+
+        let failure = Err(ApplicationError::EncodingError("bad\ninput".to_string()));
+        let line = ActivityEntry::new("decode").to_line(timestamp, &failure);
+        assert!(line.ends_with("carrier=-\toutput=-\tpayload=-\terror: Encoding error: bad input"));
+    }
+
+    #[test]
+    fn test_append_adds_lines() {
+        let dir = tempdir().unwrap();
+        let log_path = dir.path().join("logs").join("activity.log");
+
+        append(&log_path, &entry(), &Ok(())).unwrap();
+        append(&log_path, &entry(), &Ok(())).unwrap();
+
+        let contents = fs::read_to_string(&log_path).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+    }
+}
@@ -0,0 +1,264 @@
+use super::file::{prepare_output, ExistingOutput};
+use crate::error::ApplicationError;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Prefix marking a message that bundles several text files
+pub const MARKER: &str = "BUNDLE:";
+
+/// A text file in a bundle, named by its path relative to the bundled directory
+#[derive(Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub contents: String,
+}
+
+/// Whether a data path names several files to bundle: a directory, or a file name
+/// containing `*` or `?` wildcards
+pub fn is_bundle_source(data_path: &str) -> bool {
+    Path::new(data_path).is_dir() || file_name(Path::new(data_path)).contains(['*', '?'])
+}
+
+/// Reads the files named by `data_path`, sorted by name
+///
+/// A directory yields every file in it, and with `recursive` the files of its
+/// subdirectories too; a pattern such as `notes/*.txt` yields the files in its directory
+/// whose names match. Fails when nothing matches
+pub fn collect(data_path: &str, recursive: bool) -> Result<Vec<Entry>, ApplicationError> {
+    let path = Path::new(data_path);
+    let mut files = Vec::new();
+    if path.is_dir() {
+        list_files(path, path, recursive, &mut files)?;
+    } else {
+        let pattern = file_name(path);
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?.path();
+            let name = file_name(&entry);
+            if entry.is_file() && matches(&pattern, &name) {
+                files.push((name, entry));
+            }
+        }
+    }
+    if files.is_empty() {
+        return Err(ApplicationError::InvalidPathError(format!(
+            "No files match '{}'",
+            data_path
+        )));
+    }
+
+    files.sort();
+    files
+        .into_iter()
+        .map(|(name, path)| {
+            Ok(Entry {
+                name,
+                contents: fs::read_to_string(path)?,
+            })
+        })
+        .collect()
+}
+
+fn list_files(
+    root: &Path,
+    dir: &Path,
+    recursive: bool,
+    files: &mut Vec<(String, PathBuf)>,
+) -> Result<(), ApplicationError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let parts: Vec<_> = relative.iter().map(|part| part.to_string_lossy()).collect();
+            files.push((parts.join("/"), path));
+        } else if recursive && path.is_dir() {
+            list_files(root, &path, recursive, files)?;
+        }
+    }
+    Ok(())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Whether `name` matches a pattern where `*` stands for any run of characters and `?`
+/// for any single one
+fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // Classic two-pointer wildcard match, backtracking to the last `*`
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Packs files into one message: the marker, then for each file a `<length> <name>` line
+/// followed by its contents, `length` being their size in bytes
+pub fn pack(entries: &[Entry]) -> Result<String, ApplicationError> {
+    let mut message = MARKER.to_string();
+    for entry in entries {
+        if entry.name.contains('\n') {
+            return Err(ApplicationError::InvalidPathError(format!(
+                "Cannot bundle '{}': its name contains a line break",
+                entry.name.escape_default()
+            )));
+        }
+        message.push_str(&format!("{} {}\n", entry.contents.len(), entry.name));
+        message.push_str(&entry.contents);
+    }
+    Ok(message)
+}
+
+/// Splits a message packed with [`pack`] back into its files
+///
+/// Names that would escape the output directory, such as absolute paths or ones
+/// containing `..`, are rejected
+pub fn unpack(message: &str) -> Result<Vec<Entry>, ApplicationError> {
+    let malformed = || ApplicationError::DecodingError("Malformed bundle".to_string());
+    let mut rest = message.strip_prefix(MARKER).ok_or_else(malformed)?;
+
+    let mut entries = Vec::new();
+    while !rest.is_empty() {
+        let (line, after) = rest.split_once('\n').ok_or_else(malformed)?;
+        let (length, name) = line.split_once(' ').ok_or_else(malformed)?;
+        let length: usize = length.parse().map_err(|_| malformed())?;
+        if !is_safe_name(name) {
+            return Err(ApplicationError::DecodingError(format!(
+                "Bundle entry '{}' would be written outside the output directory",
+                name
+            )));
+        }
+        let contents = after.get(..length).ok_or_else(malformed)?;
+        entries.push(Entry {
+            name: name.to_string(),
+            contents: contents.to_string(),
+        });
+        rest = &after[length..];
+    }
+    Ok(entries)
+}
+
+fn is_safe_name(name: &str) -> bool {
+    !name.is_empty()
+        && Path::new(name)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Writes unpacked files under `dir`, creating it and any subdirectories, applying
+/// `policy` to files that already exist
+pub fn write(entries: &[Entry], dir: &str, policy: ExistingOutput) -> Result<(), ApplicationError> {
+    for entry in entries {
+        let path = Path::new(dir).join(&entry.name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        prepare_output(&path, policy)?;
+        fs::write(&path, &entry.contents)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_bundle_and_unbundle_two_files() {
+        let dir = tempdir().unwrap();
+        let notes = dir.path().join("notes");
+        fs::create_dir(&notes).unwrap();
+        fs::write(notes.join("b.txt"), "Second\nfile").unwrap();
+        fs::write(notes.join("a.txt"), "First file, ünïcödé").unwrap();
+
+        let entries = collect(notes.to_str().unwrap(), false).unwrap();
+        let message = pack(&entries).unwrap();
+        assert!(message.starts_with(MARKER));
+        assert_eq!(unpack(&message).unwrap(), entries);
+
+        let output = dir.path().join("unpacked");
+        write(
+            &unpack(&message).unwrap(),
+            output.to_str().unwrap(),
+            ExistingOutput::Overwrite,
+        )
+        .unwrap();
+        for name in ["a.txt", "b.txt"] {
+            assert_eq!(
+                fs::read(output.join(name)).unwrap(),
+                fs::read(notes.join(name)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_collect_pattern_and_recursive() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("one.txt"), "1").unwrap();
+        fs::write(dir.path().join("two.md"), "2").unwrap();
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        fs::write(dir.path().join("nested").join("three.txt"), "3").unwrap();
+
+        let names = |entries: Vec<Entry>| -> Vec<String> {
+            entries.into_iter().map(|entry| entry.name).collect()
+        };
+        let pattern = dir.path().join("*.txt");
+        assert_eq!(
+            names(collect(pattern.to_str().unwrap(), false).unwrap()),
+            ["one.txt"]
+        );
+        assert_eq!(
+            names(collect(dir.path().to_str().unwrap(), true).unwrap()),
+            ["nested/three.txt", "one.txt", "two.md"]
+        );
+
+        let nothing = dir.path().join("*.pdf");
+        assert!(collect(nothing.to_str().unwrap(), false).is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("*.txt", "notes.txt"));
+        assert!(matches("day-?.txt", "day-1.txt"));
+        assert!(matches("*a*b", "xxaxxb"));
+        assert!(!matches("*.txt", "notes.md"));
+        assert!(!matches("day-?.txt", "day-10.txt"));
+    }
+
+    #[test]
+    fn test_unpack_rejects_escaping_names() {
+        for name in ["../evil.txt", "/etc/evil", "a/../../b"] {
+            let message = format!("{}1 {}\nx", MARKER, name);
+            assert!(unpack(&message).is_err());
+        }
+        assert!(unpack(&format!("{}9 short\nx", MARKER)).is_err());
+    }
+}
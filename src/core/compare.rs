@@ -0,0 +1,64 @@
+use crate::error::ApplicationError;
+
+/// Compare decoded bytes against a reference, describing the first mismatch
+pub fn verify_bytes(decoded: &[u8], reference: &[u8]) -> Result<(), ApplicationError> {
+    let mismatch = decoded
+        .iter()
+        .zip(reference.iter())
+        .position(|(a, b)| a != b);
+
+    let summary = match mismatch {
+        Some(offset) => format!(
+            "first difference at byte {} (decoded 0x{:02x}, reference 0x{:02x}); {} bytes decoded, {} bytes in reference",
+            offset,
+            decoded[offset],
+            reference[offset],
+            decoded.len(),
+            reference.len()
+        ),
+        None if decoded.len() != reference.len() => format!(
+            "length mismatch: {} bytes decoded, {} bytes in reference",
+            decoded.len(),
+            reference.len()
+        ),
+        None => return Ok(()),
+    };
+
+    Err(ApplicationError::VerificationError(summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identical_bytes() {
+        assert!(verify_bytes(b"Hello, world!", b"Hello, world!").is_ok());
+    }
+
+    #[test]
+    fn test_single_differing_byte() {
+        let result = verify_bytes(b"Hello, world!", b"Hello, world?");
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Verification error: first difference at byte 12 (decoded 0x21, reference 0x3f); 13 bytes decoded, 13 bytes in reference"
+        );
+    }
+
+    #[test]
+    fn test_length_mismatch() {
+        let result = verify_bytes(b"Hello", b"Hello, world!");
+
+        assert!(matches!(
+            result,
+            Err(ApplicationError::VerificationError(message)) if message.starts_with("length mismatch")
+        ));
+    }
+
+    #[test]
+    fn test_binary_bytes() {
+        assert!(verify_bytes(&[0x00, 0xff, 0x10], &[0x00, 0xff, 0x10]).is_ok());
+        assert!(verify_bytes(&[0x00, 0xff, 0x10], &[0x00, 0xfe, 0x10]).is_err());
+    }
+}
@@ -1,50 +1,258 @@
 use crate::error::ApplicationError;
+use clap::ValueEnum;
 use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
 use std::io::prelude::*;
+use std::ops::RangeInclusive;
 
-/// Compress data
-pub fn compress(data: &[u8]) -> Result<Vec<u8>, ApplicationError> {
-    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
-    encoder
-        .write_all(data)
-        .map_err(|e| ApplicationError::IoError(e))?;
-    encoder.finish().map_err(|e| ApplicationError::IoError(e))
+/// Prefix marking a compressed payload
+pub const MARKER: &str = "COMPRESSED:";
+
+/// Algorithm name in the marker of a payload stored without compression
+const STORED: &str = "none";
+
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_WINDOW_BITS: u32 = 22;
+
+/// Compression algorithm applied to the payload
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Algorithm {
+    /// Zlib (deflate), the most widely supported
+    #[default]
+    Zlib,
+    /// Zstandard, fast with good ratios on small text
+    Zstd,
+    /// Brotli, the best ratios on repetitive data but slower
+    Brotli,
+}
+
+impl Algorithm {
+    /// Name used in the payload marker and on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Zlib => "zlib",
+            Algorithm::Zstd => "zstd",
+            Algorithm::Brotli => "brotli",
+        }
+    }
+
+    /// Compression levels the algorithm accepts
+    pub fn levels(self) -> RangeInclusive<u32> {
+        match self {
+            Algorithm::Zlib => 0..=9,
+            Algorithm::Zstd => 1..=22,
+            Algorithm::Brotli => 0..=11,
+        }
+    }
+
+    /// Level used when none is given
+    pub fn default_level(self) -> u32 {
+        match self {
+            Algorithm::Zlib => 6,
+            Algorithm::Zstd => 3,
+            Algorithm::Brotli => 11,
+        }
+    }
+}
+
+/// Compress data with `algorithm` at `level`, or the algorithm's default level
+pub fn compress(
+    data: &[u8],
+    algorithm: Algorithm,
+    level: Option<u32>,
+) -> Result<Vec<u8>, ApplicationError> {
+    let level = level.unwrap_or(algorithm.default_level());
+    if !algorithm.levels().contains(&level) {
+        return Err(ApplicationError::EncodingError(format!(
+            "Compression level {} is out of range for {} ({}-{})",
+            level,
+            algorithm.name(),
+            algorithm.levels().start(),
+            algorithm.levels().end()
+        )));
+    }
+
+    match algorithm {
+        Algorithm::Zlib => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level));
+            encoder.write_all(data).map_err(ApplicationError::IoError)?;
+            encoder.finish().map_err(ApplicationError::IoError)
+        }
+        Algorithm::Zstd => zstd::encode_all(data, level as i32).map_err(ApplicationError::IoError),
+        Algorithm::Brotli => {
+            let mut compressed = Vec::new();
+            {
+                let mut encoder = brotli::CompressorWriter::new(
+                    &mut compressed,
+                    BROTLI_BUFFER_SIZE,
+                    level,
+                    BROTLI_WINDOW_BITS,
+                );
+                encoder.write_all(data).map_err(ApplicationError::IoError)?;
+            }
+            Ok(compressed)
+        }
+    }
 }
 
-/// Decompress data
-pub fn decompress(data: &[u8]) -> Result<Vec<u8>, ApplicationError> {
-    let mut decoder = ZlibDecoder::new(data);
+/// Decompress data compressed with `algorithm`
+pub fn decompress(data: &[u8], algorithm: Algorithm) -> Result<Vec<u8>, ApplicationError> {
     let mut decompressed = Vec::new();
-    decoder
-        .read_to_end(&mut decompressed)
-        .map_err(|e| ApplicationError::IoError(e))?;
+    match algorithm {
+        Algorithm::Zlib => ZlibDecoder::new(data).read_to_end(&mut decompressed),
+        Algorithm::Zstd => zstd::Decoder::new(data)?.read_to_end(&mut decompressed),
+        Algorithm::Brotli => {
+            brotli::Decompressor::new(data, BROTLI_BUFFER_SIZE).read_to_end(&mut decompressed)
+        }
+    }
+    .map_err(ApplicationError::IoError)?;
     Ok(decompressed)
 }
 
+/// Marker prefixed to a payload compressed with `algorithm` at `level`, e.g. `COMPRESSED:zstd:3:`
+pub fn marker(algorithm: Algorithm, level: Option<u32>) -> String {
+    format!(
+        "{}{}:{}:",
+        MARKER,
+        algorithm.name(),
+        level.unwrap_or(algorithm.default_level())
+    )
+}
+
+/// Marker prefixed to a payload stored as is because compressing it would have made it larger
+pub fn stored_marker() -> String {
+    format!("{}{}:0:", MARKER, STORED)
+}
+
+/// Split a payload starting with [`MARKER`] into its algorithm and the data after the marker
+///
+/// The algorithm is `None` for payloads stored without compression (see [`stored_marker`]).
+/// Payloads from older releases carry no algorithm after the marker and are always zlib
+pub fn parse_marker(payload: &str) -> Result<(Option<Algorithm>, &str), ApplicationError> {
+    let rest = payload
+        .strip_prefix(MARKER)
+        .ok_or_else(|| ApplicationError::DecodingError("Message is not compressed".to_string()))?;
+    // Base64 never contains ':', so a bare payload is the legacy format
+    let mut fields = rest.splitn(3, ':');
+    match (fields.next(), fields.next(), fields.next()) {
+        (Some(STORED), Some(_), Some(data)) => Ok((None, data)),
+        (Some(name), Some(level), Some(data)) => {
+            let algorithm = Algorithm::from_str(name, false).map_err(|_| {
+                ApplicationError::DecodingError(format!("Unknown compression algorithm '{}'", name))
+            })?;
+            level.parse::<u32>().map_err(|_| {
+                ApplicationError::DecodingError(format!("Invalid compression level '{}'", level))
+            })?;
+            Ok((Some(algorithm), data))
+        }
+        _ => Ok((Some(Algorithm::Zlib), rest)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const ALGORITHMS: [Algorithm; 3] = [Algorithm::Zlib, Algorithm::Zstd, Algorithm::Brotli];
+
     #[test]
     fn test_compress_decompress() {
         let original_data = b"Hello, world!";
-        let compressed_data = compress(original_data).expect("Compression failed");
-        let decompressed_data = decompress(&compressed_data).expect("Decompression failed");
+        for algorithm in ALGORITHMS {
+            let compressed_data =
+                compress(original_data, algorithm, None).expect("Compression failed");
+            let decompressed_data =
+                decompress(&compressed_data, algorithm).expect("Decompression failed");
 
-        assert_eq!(original_data.to_vec(), decompressed_data);
+            assert_eq!(original_data.to_vec(), decompressed_data, "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_compress_decompress_at_every_level() {
+        let original_data = "repetitive data ".repeat(64);
+        for algorithm in ALGORITHMS {
+            for level in algorithm.levels() {
+                let compressed_data = compress(original_data.as_bytes(), algorithm, Some(level))
+                    .expect("Compression failed");
+                let decompressed_data =
+                    decompress(&compressed_data, algorithm).expect("Decompression failed");
+
+                assert_eq!(original_data.as_bytes(), decompressed_data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compress_rejects_out_of_range_level() {
+        assert!(compress(b"data", Algorithm::Zlib, Some(10)).is_err());
+        assert!(compress(b"data", Algorithm::Zstd, Some(0)).is_err());
+        assert!(compress(b"data", Algorithm::Brotli, Some(12)).is_err());
     }
 
     #[test]
     fn test_compression_error_handling() {
         let empty_data: &[u8] = &[];
-        let compressed_data = compress(empty_data);
-        assert!(compressed_data.is_ok());
+        for algorithm in ALGORITHMS {
+            let compressed_data = compress(empty_data, algorithm, None);
+            assert!(compressed_data.is_ok());
+        }
     }
 
     #[test]
     fn test_decompression_error_handling() {
         let invalid_data = b"This is not compressed!";
-        let decompressed_data = decompress(invalid_data);
-        assert!(decompressed_data.is_err());
+        for algorithm in ALGORITHMS {
+            let decompressed_data = decompress(invalid_data, algorithm);
+            assert!(decompressed_data.is_err(), "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_decompress_with_wrong_algorithm_fails() {
+        let original_data = b"Hello, world!";
+        for algorithm in ALGORITHMS {
+            let compressed_data = compress(original_data, algorithm, None).unwrap();
+            for other in ALGORITHMS.into_iter().filter(|other| *other != algorithm) {
+                assert!(
+                    decompress(&compressed_data, other).is_err(),
+                    "{:?} data decompressed as {:?}",
+                    algorithm,
+                    other
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_marker_round_trip() {
+        for algorithm in ALGORITHMS {
+            let payload = format!("{}SGVsbG8=", marker(algorithm, Some(5)));
+            assert_eq!(
+                parse_marker(&payload).unwrap(),
+                (Some(algorithm), "SGVsbG8=")
+            );
+        }
+        assert_eq!(marker(Algorithm::Zstd, None), "COMPRESSED:zstd:3:");
+    }
+
+    #[test]
+    fn test_parse_legacy_marker() {
+        assert_eq!(
+            parse_marker("COMPRESSED:SGVsbG8=").unwrap(),
+            (Some(Algorithm::Zlib), "SGVsbG8=")
+        );
+    }
+
+    #[test]
+    fn test_parse_stored_marker() {
+        let payload = format!("{}Hi: there", stored_marker());
+        assert_eq!(parse_marker(&payload).unwrap(), (None, "Hi: there"));
+    }
+
+    #[test]
+    fn test_parse_marker_rejects_unknown_algorithm() {
+        assert!(parse_marker("COMPRESSED:lzma:5:SGVsbG8=").is_err());
+        assert!(parse_marker("COMPRESSED:zstd:high:SGVsbG8=").is_err());
+        assert!(parse_marker("SGVsbG8=").is_err());
     }
 }
@@ -0,0 +1,84 @@
+use crate::cryptography::cipher::Cipher;
+use crate::error::ApplicationError;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// Defaults read from the TOML file passed with `--config`
+///
+/// Each value only applies when the matching option is left out on the command line
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Where `encode` saves the image when `--output-path` is not given
+    pub default_output: Option<String>,
+    /// Cipher used by `encode --key` when `--cipher` is not given
+    pub cipher: Option<Cipher>,
+    /// Compress on encode, as if `--compress` were given, unless `--no-compress` is
+    pub compress: bool,
+    /// Bits per channel used by `encode` when `--depth` is not given
+    pub depth: Option<usize>,
+}
+
+/// Reads and parses a configuration file
+pub fn load(path: &Path) -> Result<Config, ApplicationError> {
+    let text = fs::read_to_string(path)?;
+    parse(&text).map_err(|e| match e {
+        ApplicationError::ConfigError(message) => {
+            ApplicationError::ConfigError(format!("{}: {}", path.display(), message))
+        }
+        e => e,
+    })
+}
+
+/// Parses configuration from TOML text
+pub fn parse(text: &str) -> Result<Config, ApplicationError> {
+    toml::from_str(text).map_err(|e| ApplicationError::ConfigError(e.message().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = parse(
+            r#"
+            default_output = "hidden.png"
+            cipher = "chacha"
+            compress = true
+            depth = 2
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                default_output: Some("hidden.png".to_string()),
+                cipher: Some(Cipher::Chacha),
+                compress: true,
+                depth: Some(2),
+            }
+        );
+        assert_eq!(parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        for text in [
+            "compress = ",
+            "compres = true",
+            "cipher = \"des\"",
+            "depth = -1",
+        ] {
+            let result = parse(text);
+            assert!(
+                matches!(result, Err(ApplicationError::ConfigError(_))),
+                "{}: {:?}",
+                text,
+                result
+            );
+        }
+    }
+}
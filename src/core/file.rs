@@ -1,35 +1,172 @@
 use crate::error::ApplicationError;
+use std::ffi::OsString;
 use std::fs;
-use std::path::Path;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Path that stands for stdin when reading and stdout when writing
+pub const STDIO_SENTINEL: &str = "-";
+
+/// Whether `file_path` is `-`, standing for stdin or stdout
+pub fn is_stdio(file_path: impl AsRef<Path>) -> bool {
+    file_path.as_ref() == Path::new(STDIO_SENTINEL)
+}
 
 /// Validate that a file path is valid
-pub fn validate_path(file_path: &str) -> Result<(), ApplicationError> {
+pub fn validate_path(file_path: impl AsRef<Path>) -> Result<(), ApplicationError> {
+    let file_path = file_path.as_ref();
     match fs::metadata(file_path) {
         Ok(metadata) if metadata.is_file() => Ok(()),
         Ok(_) => Err(ApplicationError::InvalidPathError(format!(
             "Path '{}' is not a file.",
-            file_path
+            file_path.display()
         ))),
         Err(e) => Err(ApplicationError::IoError(e)),
     }
 }
 
-// @todo this should support reading from stdin
 /// Read text data from the specified file path
-pub fn read_text(file_path: &str) -> Result<String, ApplicationError> {
+pub fn read_text(file_path: impl AsRef<Path>) -> Result<String, ApplicationError> {
     fs::read_to_string(file_path).map_err(ApplicationError::IoError)
 }
 
-// @todo this should support printing to stdout
+/// Read text data from the specified file path, or from stdin when the path is `-`
+pub fn read_text_or_stdin(file_path: impl AsRef<Path>) -> Result<String, ApplicationError> {
+    read_text_or(file_path, io::stdin().lock())
+}
+
+fn read_text_or(
+    file_path: impl AsRef<Path>,
+    mut stdin: impl Read,
+) -> Result<String, ApplicationError> {
+    if !is_stdio(&file_path) {
+        return read_text(file_path);
+    }
+
+    let mut text = String::new();
+    stdin
+        .read_to_string(&mut text)
+        .map_err(ApplicationError::IoError)?;
+    Ok(text)
+}
+
+/// Read raw bytes from the specified file path
+pub fn read_bytes(file_path: impl AsRef<Path>) -> Result<Vec<u8>, ApplicationError> {
+    validate_path(&file_path)?;
+    fs::read(file_path).map_err(ApplicationError::IoError)
+}
+
+/// Read a key from the specified file path, without a trailing line ending
+///
+/// A file that isn't UTF-8 text holds a binary key (see `generate-key --encoding raw`),
+/// which is used byte for byte
+pub fn read_key(file_path: impl AsRef<Path>) -> Result<Vec<u8>, ApplicationError> {
+    let file_path = file_path.as_ref();
+    let bytes = read_bytes(file_path)?;
+    let key = match std::str::from_utf8(&bytes) {
+        Ok(text) => text
+            .strip_suffix('\n')
+            .map(|key| key.strip_suffix('\r').unwrap_or(key))
+            .unwrap_or(text)
+            .as_bytes(),
+        Err(_) => &bytes,
+    };
+    if key.is_empty() {
+        return Err(ApplicationError::EncryptionError(format!(
+            "Key file '{}' is empty",
+            file_path.display()
+        )));
+    }
+
+    Ok(key.to_vec())
+}
+
 /// Write text data to the specified file path
-pub fn write_text(text: &str, file_path: &str) -> Result<(), ApplicationError> {
-    ensure_parent_directory(file_path)?;
+pub fn write_text(text: &str, file_path: impl AsRef<Path>) -> Result<(), ApplicationError> {
+    ensure_parent_directory(&file_path)?;
     fs::write(file_path, text).map_err(ApplicationError::IoError)
 }
 
+/// Write binary data to the specified file path, creating its parent directory if needed
+pub fn write_bytes(bytes: &[u8], file_path: impl AsRef<Path>) -> Result<(), ApplicationError> {
+    ensure_parent_directory(&file_path)?;
+    fs::write(file_path, bytes).map_err(ApplicationError::IoError)
+}
+
+/// Write binary data to the specified file path, or to stdout when the path is `-`
+pub fn write_bytes_or_stdout(
+    bytes: &[u8],
+    file_path: impl AsRef<Path>,
+) -> Result<(), ApplicationError> {
+    write_bytes_or(bytes, file_path, io::stdout().lock())
+}
+
+fn write_bytes_or(
+    bytes: &[u8],
+    file_path: impl AsRef<Path>,
+    mut stdout: impl Write,
+) -> Result<(), ApplicationError> {
+    if !is_stdio(&file_path) {
+        return write_bytes(bytes, file_path);
+    }
+
+    stdout
+        .write_all(bytes)
+        .and_then(|_| stdout.flush())
+        .map_err(ApplicationError::IoError)
+}
+
+/// What happens when an output file already exists
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExistingOutput {
+    /// Replace it
+    #[default]
+    Overwrite,
+    /// Fail with [`ApplicationError::InvalidPathError`]
+    Refuse,
+    /// Rename it to the same path with `.bak` appended, replacing any earlier backup
+    Backup,
+}
+
+/// Apply `policy` to an existing file at `file_path`, just before it is written
+///
+/// Nothing happens when there is no such file or the path is `-` (stdout)
+pub fn prepare_output(
+    file_path: impl AsRef<Path>,
+    policy: ExistingOutput,
+) -> Result<(), ApplicationError> {
+    let file_path = file_path.as_ref();
+    if is_stdio(file_path) || !file_path.exists() {
+        return Ok(());
+    }
+
+    match policy {
+        ExistingOutput::Overwrite => Ok(()),
+        ExistingOutput::Refuse => Err(ApplicationError::InvalidPathError(format!(
+            "Output '{}' already exists",
+            file_path.display()
+        ))),
+        ExistingOutput::Backup => {
+            fs::rename(file_path, backup_path(file_path)).map_err(ApplicationError::IoError)
+        }
+    }
+}
+
+/// `file_path` with `.bak` appended, e.g. `hidden.png.bak`
+pub fn backup_path(file_path: impl AsRef<Path>) -> PathBuf {
+    let mut backup = OsString::from(file_path.as_ref());
+    backup.push(".bak");
+    backup.into()
+}
+
+/// Resolve a path to its absolute form, following `..` components and symlinks
+pub fn canonical_path(file_path: impl AsRef<Path>) -> Result<PathBuf, ApplicationError> {
+    fs::canonicalize(file_path).map_err(ApplicationError::IoError)
+}
+
 /// Ensures that the parent directory exists by creating it if it doesn't
-pub fn ensure_parent_directory(file_path: &str) -> Result<(), ApplicationError> {
-    if let Some(parent) = Path::new(file_path).parent() {
+pub fn ensure_parent_directory(file_path: impl AsRef<Path>) -> Result<(), ApplicationError> {
+    if let Some(parent) = file_path.as_ref().parent() {
         fs::create_dir_all(parent).map_err(ApplicationError::IoError)?;
     }
     Ok(())
@@ -39,14 +176,45 @@ pub fn ensure_parent_directory(file_path: &str) -> Result<(), ApplicationError>
 mod tests {
     use super::*;
     use std::fs::{self, File};
+    use std::path::Component;
     use tempfile::tempdir;
 
+    #[test]
+    fn test_prepare_output_policies() {
+        let dir = tempdir().unwrap();
+        let file_path = &dir.path().join("hidden.png");
+        let missing = dir.path().join("missing.png");
+
+        for policy in [
+            ExistingOutput::Overwrite,
+            ExistingOutput::Refuse,
+            ExistingOutput::Backup,
+        ] {
+            assert!(prepare_output(&missing, policy).is_ok());
+            assert!(prepare_output(STDIO_SENTINEL, policy).is_ok());
+        }
+
+        fs::write(file_path, "original").unwrap();
+        assert!(prepare_output(file_path, ExistingOutput::Overwrite).is_ok());
+        assert!(matches!(
+            prepare_output(file_path, ExistingOutput::Refuse),
+            Err(ApplicationError::InvalidPathError(_))
+        ));
+
+        prepare_output(file_path, ExistingOutput::Backup).unwrap();
+        assert!(!file_path.exists());
+        assert_eq!(
+            fs::read_to_string(backup_path(file_path)).unwrap(),
+            "original"
+        );
+    }
+
     #[test]
     fn test_validate_path_valid() {
         let dir = tempdir().unwrap();
         let file_path = dir.path().join("test_file.txt");
         File::create(&file_path).expect("Failed to create test file");
-        let result = validate_path(file_path.to_str().unwrap());
+        let result = validate_path(file_path);
 
         assert!(result.is_ok());
     }
@@ -55,7 +223,7 @@ mod tests {
     fn test_validate_path_invalid() {
         let dir = tempdir().unwrap();
         let file_path = dir.path().join("non_existent_file.txt");
-        let result = validate_path(file_path.to_str().unwrap());
+        let result = validate_path(file_path);
 
         assert!(result.is_err());
     }
@@ -66,16 +234,61 @@ mod tests {
         let file_path = dir.path().join("test_file.txt");
         let content = "Hello, world!";
         fs::write(&file_path, content).expect("Failed to write to test file");
-        let result = read_text(file_path.to_str().unwrap()).unwrap();
+        let result = read_text(file_path).unwrap();
+
+        assert_eq!(result, content);
+    }
+
+    #[test]
+    fn test_canonical_path() {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        let file_path = dir.path().join("test_file.txt");
+        File::create(&file_path).expect("Failed to create test file");
+        let indirect_path = dir.path().join("nested").join("..").join("test_file.txt");
+        let result = canonical_path(indirect_path).unwrap();
+
+        assert!(result.is_absolute());
+        assert!(!result.components().any(|c| c == Component::ParentDir));
+        assert_eq!(result, fs::canonicalize(&file_path).unwrap());
+    }
+
+    #[test]
+    fn test_read_bytes() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test_file.bin");
+        let content = [0x00, 0x9f, 0xff, b'A'];
+        fs::write(&file_path, content).expect("Failed to write to test file");
+        let result = read_bytes(file_path).unwrap();
 
         assert_eq!(result, content);
     }
 
+    #[test]
+    fn test_read_key() {
+        let dir = tempdir().unwrap();
+        let file_path = &dir.path().join("key.txt");
+
+        fs::write(file_path, "secret key\n").unwrap();
+        assert_eq!(read_key(file_path).unwrap(), b"secret key");
+        fs::write(file_path, "secret key\r\n").unwrap();
+        assert_eq!(read_key(file_path).unwrap(), b"secret key");
+        fs::write(file_path, "secret key \n\n").unwrap();
+        assert_eq!(read_key(file_path).unwrap(), b"secret key \n");
+        // A binary key keeps a final newline byte
+        fs::write(file_path, [0xff, 0x00, b'\n']).unwrap();
+        assert_eq!(read_key(file_path).unwrap(), [0xff, 0x00, b'\n']);
+
+        fs::write(file_path, "\n").unwrap();
+        assert!(read_key(file_path).is_err());
+        assert!(read_key(dir.path().join("missing.txt")).is_err());
+    }
+
     #[test]
     fn test_ensure_parent_directory() {
         let dir = tempdir().unwrap();
         let nested_path = dir.path().join("nested").join("file.txt");
-        let result = ensure_parent_directory(nested_path.to_str().unwrap());
+        let result = ensure_parent_directory(&nested_path);
 
         assert!(result.is_ok());
         assert!(nested_path.parent().unwrap().exists());
@@ -86,7 +299,7 @@ mod tests {
         let dir = tempdir().unwrap();
         let file_path = dir.path().join("output_text.txt");
         let content = "Test text content";
-        let result = write_text(content, file_path.to_str().unwrap());
+        let result = write_text(content, &file_path);
 
         assert!(result.is_ok());
 
@@ -94,4 +307,63 @@ mod tests {
 
         assert_eq!(read_content, content);
     }
+
+    #[test]
+    fn test_read_text_or_stdin_sentinel() {
+        let stdin = io::Cursor::new("piped secret\n");
+        let text = read_text_or(STDIO_SENTINEL, stdin).unwrap();
+
+        assert_eq!(text, "piped secret\n");
+    }
+
+    #[test]
+    fn test_read_text_or_stdin_file() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("message.txt");
+        fs::write(&file_path, "from file").unwrap();
+        let stdin = io::Cursor::new("unused");
+        let text = read_text_or(file_path, stdin).unwrap();
+
+        assert_eq!(text, "from file");
+    }
+
+    #[test]
+    fn test_write_bytes_or_stdout_sentinel() {
+        let mut stdout = Vec::new();
+        write_bytes_or(b"decoded", STDIO_SENTINEL, &mut stdout).unwrap();
+
+        assert_eq!(stdout, b"decoded");
+    }
+
+    #[test]
+    fn test_write_bytes_or_stdout_file() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("decoded.txt");
+        let mut stdout = Vec::new();
+        write_bytes_or(b"decoded", &file_path, &mut stdout).unwrap();
+
+        assert!(stdout.is_empty());
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "decoded");
+    }
+
+    #[test]
+    fn test_unicode_and_non_utf8_paths() {
+        let dir = tempdir().unwrap();
+        let mut names = vec![OsString::from("秘密の メッセージ 🔑.txt")];
+        // Other platforms' filesystems only store valid Unicode names
+        #[cfg(target_os = "linux")]
+        names.push(std::os::unix::ffi::OsStringExt::from_vec(
+            b"caf\xe9.txt".to_vec(),
+        ));
+
+        for name in names {
+            let file_path = dir.path().join("nested").join(name);
+            write_text("hidden", &file_path).unwrap();
+
+            assert!(validate_path(&file_path).is_ok());
+            assert_eq!(read_text_or_stdin(&file_path).unwrap(), "hidden");
+            prepare_output(&file_path, ExistingOutput::Backup).unwrap();
+            assert_eq!(read_text(backup_path(&file_path)).unwrap(), "hidden");
+        }
+    }
 }
@@ -1,11 +1,19 @@
 use super::file::{ensure_parent_directory, validate_path};
 use crate::error::ApplicationError;
-use image::{ImageFormat, ImageReader, RgbImage};
-use std::path::Path;
+use crate::steganography::gif::Animation;
+use clap::ValueEnum;
+use image::{
+    ColorType, EncodableLayout, GrayImage, ImageBuffer, ImageFormat, ImageReader, Pixel,
+    PixelWithColorType, RgbImage, RgbaImage,
+};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 
 /// Validate that the file path has a supported image extension
-pub fn has_valid_image_extension(file_path: &str) -> bool {
-    Path::new(file_path)
+pub fn has_valid_image_extension(file_path: impl AsRef<Path>) -> bool {
+    file_path
+        .as_ref()
         .extension()
         .and_then(|ext| ext.to_str())
         .map(|ext| match ext.to_lowercase().as_str() {
@@ -15,14 +23,25 @@ pub fn has_valid_image_extension(file_path: &str) -> bool {
         .unwrap_or(false)
 }
 
+/// Paths of the files in `dir` with a supported image extension, in name order
+pub fn list_images(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, ApplicationError> {
+    let mut images: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && has_valid_image_extension(path))
+        .collect();
+    images.sort();
+    Ok(images)
+}
+
 /// Determine whether a file is lossless
-pub fn is_lossless(file_path: &str) -> Result<bool, ApplicationError> {
-    let format = ImageFormat::from_path(file_path)
+pub fn is_lossless(file_path: impl AsRef<Path>) -> Result<bool, ApplicationError> {
+    let format = ImageFormat::from_path(&file_path)
         .map_err(|_| ApplicationError::InvalidPathError("Unsupported image format".to_string()))?;
 
     match format {
         ImageFormat::Png | ImageFormat::Bmp | ImageFormat::Tiff => Ok(true),
-        ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::WebP => Ok(false),
+        ImageFormat::WebP => webp_is_lossless(file_path),
+        ImageFormat::Jpeg | ImageFormat::Gif => Ok(false),
         _ => Err(ApplicationError::InvalidPathError(format!(
             "Unsupported file type '{:?}'",
             format
@@ -30,43 +49,251 @@ pub fn is_lossless(file_path: &str) -> Result<bool, ApplicationError> {
     }
 }
 
-/// Convert a lossy image to a lossless format (PNG)
-pub fn convert_to_lossless(
-    file_path: &str,
-    output_path: &str,
-) -> Result<RgbImage, ApplicationError> {
-    ensure_parent_directory(output_path)?;
-    let image = load_image(file_path)?;
-    image
-        .save_with_format(output_path, ImageFormat::Png)
-        .map_err(ApplicationError::ImageError)?;
+/// Determine whether a WebP file is lossless by walking its RIFF chunks
+///
+/// Simple files start with a `VP8L` (lossless) or `VP8 ` (lossy) chunk; extended
+/// (`VP8X`) files carry one of them after their feature chunks. Animated files
+/// have neither at the top level and are treated as lossy
+pub fn webp_is_lossless(file_path: impl AsRef<Path>) -> Result<bool, ApplicationError> {
+    let file_path = file_path.as_ref();
+    validate_path(file_path)?;
+    let mut file = BufReader::new(File::open(file_path)?);
 
-    Ok(image)
+    let mut riff = [0u8; 12];
+    file.read_exact(&mut riff)?;
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WEBP" {
+        return Err(ApplicationError::InvalidPathError(format!(
+            "'{}' is not a WebP file",
+            file_path.display()
+        )));
+    }
+
+    let mut chunk = [0u8; 8];
+    while file.read_exact(&mut chunk).is_ok() {
+        match &chunk[0..4] {
+            b"VP8L" => return Ok(true),
+            b"VP8 " => return Ok(false),
+            _ => {
+                let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+                // Chunk payloads are padded to an even length
+                file.seek_relative(i64::from(size) + i64::from(size & 1))?;
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// Determine whether a file is a GIF, which keeps its frames when encoded to a GIF
+pub fn is_gif(file_path: impl AsRef<Path>) -> bool {
+    matches!(ImageFormat::from_path(file_path), Ok(ImageFormat::Gif))
+}
+
+/// Load every frame of a GIF, see [`Animation`]
+pub fn load_gif(file_path: impl AsRef<Path>) -> Result<Animation, ApplicationError> {
+    validate_path(&file_path)?;
+    Animation::read(BufReader::new(File::open(file_path)?))
+}
+
+/// Write the frames of a GIF to the specified file path
+pub fn write_gif(
+    animation: &Animation,
+    file_path: impl AsRef<Path>,
+) -> Result<(), ApplicationError> {
+    ensure_parent_directory(&file_path)?;
+    let mut file = BufWriter::new(File::create(file_path)?);
+    animation.write(&mut file)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Carrier pixels in the color type the payload is embedded in
+///
+/// Grayscale carriers keep their single luma channel, so they are saved back as grayscale;
+/// every other carrier is expanded to RGBA
+pub enum Carrier {
+    Gray(GrayImage),
+    Color(RgbaImage),
+}
+
+impl Carrier {
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            Carrier::Gray(image) => image.dimensions(),
+            Carrier::Color(image) => image.dimensions(),
+        }
+    }
+}
+
+/// Convert a lossy image to lossless pixels in memory, ready to be saved as PNG
+///
+/// Nothing is written to disk, so no intermediate file is left behind
+pub fn convert_to_lossless(file_path: impl AsRef<Path>) -> Result<Carrier, ApplicationError> {
+    // Decoding yields the exact pixels a PNG round trip would preserve
+    load_carrier(file_path)
+}
+
+/// The most significant byte of every sample of a 16-bit carrier
+///
+/// A 16-bit carrier is embedded through the [`Carrier`] of its least significant bytes, so
+/// the payload lands in the low bits of each 16-bit channel; [`join_high_bytes`] puts the
+/// high bytes back before saving
+pub struct HighBytes(Vec<u8>);
+
+/// Load an image as a [`Carrier`], grayscale when it is stored as 8 or 16-bit luma
+///
+/// Other images are converted to RGBA, keeping their alpha channel; images without one
+/// get a fully opaque alpha channel. Images with 16 bits per channel are loaded as the
+/// least significant byte of each sample, see [`load_carrier_keeping_depth`]
+pub fn load_carrier(file_path: impl AsRef<Path>) -> Result<Carrier, ApplicationError> {
+    Ok(load_carrier_keeping_depth(file_path)?.0)
+}
+
+/// [`load_carrier`], also returning the [`HighBytes`] of a carrier with 16 bits per channel
+pub fn load_carrier_keeping_depth(
+    file_path: impl AsRef<Path>,
+) -> Result<(Carrier, Option<HighBytes>), ApplicationError> {
+    validate_path(&file_path)?;
+    let image = ImageReader::open(file_path)?.decode()?;
+
+    Ok(match image.color() {
+        ColorType::L8 => (Carrier::Gray(image.into_luma8()), None),
+        ColorType::L16 => {
+            let (low, high) = split_samples(image.into_luma16());
+            (Carrier::Gray(low), Some(high))
+        }
+        ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+            let (low, high) = split_samples(image.into_rgba16());
+            (Carrier::Color(low), Some(high))
+        }
+        _ => (Carrier::Color(image.into_rgba8()), None),
+    })
+}
+
+/// Splits 16-bit samples into an 8-bit image of their low bytes and their [`HighBytes`]
+fn split_samples<P: Pixel<Subpixel = u16>, Q: Pixel<Subpixel = u8>>(
+    image: ImageBuffer<P, Vec<u16>>,
+) -> (ImageBuffer<Q, Vec<u8>>, HighBytes) {
+    let (width, height) = image.dimensions();
+    let (high, low) = image
+        .into_raw()
+        .into_iter()
+        .map(|sample| {
+            let [high, low] = sample.to_be_bytes();
+            (high, low)
+        })
+        .unzip();
+    let low = ImageBuffer::from_raw(width, height, low).unwrap_or_default();
+    (low, HighBytes(high))
+}
+
+/// Rebuilds the 16-bit samples of a carrier split by [`load_carrier_keeping_depth`] from its
+/// encoded low bytes and the high bytes set aside
+pub fn join_high_bytes<P: Pixel<Subpixel = u8>, Q: Pixel<Subpixel = u16>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    high_bytes: &HighBytes,
+) -> ImageBuffer<Q, Vec<u16>> {
+    let (width, height) = image.dimensions();
+    let samples = high_bytes
+        .0
+        .iter()
+        .zip(image.as_raw())
+        .map(|(&high, &low)| u16::from_be_bytes([high, low]))
+        .collect();
+    ImageBuffer::from_raw(width, height, samples).unwrap_or_default()
 }
 
 /// Load an image and convert it to RgbImage format
-pub fn load_image(file_path: &str) -> Result<RgbImage, ApplicationError> {
-    validate_path(file_path)?;
+pub fn load_image(file_path: impl AsRef<Path>) -> Result<RgbImage, ApplicationError> {
+    validate_path(&file_path)?;
     let image_reader = ImageReader::open(file_path)?;
     let image = image_reader.decode()?.to_rgb8();
 
     Ok(image)
 }
 
-/// Write image data to the specified file path
-pub fn write_image_file(image: &RgbImage, file_path: &str) -> Result<(), ApplicationError> {
-    ensure_parent_directory(file_path)?;
+/// Determine whether an image has an alpha channel, reading only its header
+pub fn has_alpha_channel(file_path: impl AsRef<Path>) -> Result<bool, ApplicationError> {
+    validate_path(&file_path)?;
+    let decoder = ImageReader::open(file_path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+
+    Ok(image::ImageDecoder::color_type(&decoder).has_alpha())
+}
+
+/// Image format to save an encoded carrier as, overriding the output path's extension
+///
+/// Lossy formats are accepted here only so that asking for one gets a clear error
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputImageFormat {
+    Png,
+    Bmp,
+    Tiff,
+    #[value(hide = true)]
+    Jpeg,
+    #[value(hide = true)]
+    Gif,
+}
+
+impl OutputImageFormat {
+    /// Name used on the command line, which is also the file extension
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputImageFormat::Png => "png",
+            OutputImageFormat::Bmp => "bmp",
+            OutputImageFormat::Tiff => "tiff",
+            OutputImageFormat::Jpeg => "jpeg",
+            OutputImageFormat::Gif => "gif",
+        }
+    }
+
+    /// Whether saving in this format keeps every bit of the pixels
+    pub fn is_lossless(self) -> bool {
+        !matches!(self, OutputImageFormat::Jpeg | OutputImageFormat::Gif)
+    }
+}
+
+/// `file_path` with its extension replaced by (or, lacking one, set to) `format`'s
+pub fn with_format_extension(file_path: impl AsRef<Path>, format: OutputImageFormat) -> PathBuf {
+    file_path.as_ref().with_extension(format.name())
+}
+
+/// `file_path` with `.png` appended unless it already has a supported image extension
+pub fn with_image_extension(file_path: impl AsRef<Path>) -> PathBuf {
+    let file_path = file_path.as_ref();
+    if has_valid_image_extension(file_path) {
+        return file_path.to_path_buf();
+    }
+    let mut with_png = file_path.as_os_str().to_owned();
+    with_png.push(".png");
+    with_png.into()
+}
 
-    let format = ImageFormat::from_path(file_path)?;
+/// Write image data to the specified file path, returning the path actually written
+///
+/// Paths without a supported image extension are written as PNG, see [`with_image_extension`]
+pub fn write_image_file<P>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    file_path: impl AsRef<Path>,
+) -> Result<PathBuf, ApplicationError>
+where
+    P: PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+{
+    let file_path = with_image_extension(file_path);
+    ensure_parent_directory(&file_path)?;
+
+    let format = ImageFormat::from_path(&file_path)?;
     image
-        .save_with_format(file_path, format)
-        .map_err(ApplicationError::ImageError)
+        .save_with_format(&file_path, format)
+        .map_err(ApplicationError::ImageError)?;
+    Ok(file_path)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs::File;
     use tempfile::tempdir;
 
     #[test]
@@ -74,7 +301,7 @@ mod tests {
         let dir = tempdir().unwrap();
         let file_path = dir.path().join("test_file.txt");
         File::create(&file_path).expect("Failed to create test file");
-        let result = validate_path(file_path.to_str().unwrap());
+        let result = validate_path(file_path);
 
         assert!(result.is_ok());
     }
@@ -83,18 +310,35 @@ mod tests {
     fn test_validate_path_invalid() {
         let dir = tempdir().unwrap();
         let file_path = dir.path().join("non_existent_file.txt");
-        let result = validate_path(file_path.to_str().unwrap());
+        let result = validate_path(file_path);
 
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_list_images_skips_other_files() {
+        let dir = tempdir().unwrap();
+        for name in ["b.png", "a.JPG", "notes.txt"] {
+            File::create(dir.path().join(name)).unwrap();
+        }
+        fs::create_dir(dir.path().join("nested.png")).unwrap();
+
+        let images = list_images(dir.path()).unwrap();
+        let names: Vec<_> = images
+            .iter()
+            .map(|path| path.file_name().unwrap())
+            .collect();
+
+        assert_eq!(names, ["a.JPG", "b.png"]);
+    }
+
     #[test]
     fn test_is_lossless_png() {
         let dir = tempdir().unwrap();
         let file_path = dir.path().join("test_image.png");
         let image = RgbImage::new(10, 10);
         image.save(&file_path).expect("Failed to save image");
-        let result = is_lossless(file_path.to_str().unwrap());
+        let result = is_lossless(file_path);
 
         assert_eq!(result.unwrap(), true);
     }
@@ -107,30 +351,65 @@ mod tests {
         image
             .save_with_format(&file_path, ImageFormat::Jpeg)
             .expect("Failed to save image");
-        let result = is_lossless(file_path.to_str().unwrap());
+        let result = is_lossless(file_path);
 
         assert_eq!(result.unwrap(), false);
     }
 
+    #[test]
+    fn test_webp_is_lossless() {
+        assert!(webp_is_lossless("tests/example/carrier_lossless.webp").unwrap());
+        assert!(!webp_is_lossless("tests/example/carrier_lossy.webp").unwrap());
+        assert!(!webp_is_lossless("tests/example/carrier_lossy_alpha.webp").unwrap());
+    }
+
+    #[test]
+    fn test_webp_is_lossless_rejects_other_formats() {
+        assert!(webp_is_lossless("tests/example/carrier.png").is_err());
+    }
+
+    #[test]
+    fn test_is_lossless_webp() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test_image.webp");
+        let image = RgbImage::new(10, 10);
+        image.save(&file_path).expect("Failed to save image");
+
+        assert!(is_lossless(file_path).unwrap());
+        assert!(!is_lossless("tests/example/carrier_lossy.webp").unwrap());
+    }
+
     #[test]
     fn test_convert_to_lossless() {
         let dir = tempdir().unwrap();
         let input_path = dir.path().join("test_image.jpg");
-        let output_path = dir.path().join("converted_image.png");
         let image = RgbImage::new(10, 10);
         image
             .save_with_format(&input_path, ImageFormat::Jpeg)
             .expect("Failed to save image");
-        let converted_image =
-            convert_to_lossless(input_path.to_str().unwrap(), output_path.to_str().unwrap())
-                .expect("Conversion failed");
+        let converted_image = convert_to_lossless(input_path).expect("Conversion failed");
 
-        assert!(output_path.exists());
         assert_eq!(converted_image.dimensions(), (10, 10));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
 
-        let result = is_lossless(output_path.to_str().unwrap());
+    #[test]
+    fn test_16_bit_carrier_keeps_high_bytes() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("gray16.png");
+        let samples: Vec<u16> = (0..100).map(|i| i * 650 + 1).collect();
+        let image: ImageBuffer<image::Luma<u16>, _> =
+            ImageBuffer::from_raw(10, 10, samples.clone()).unwrap();
+        image.save(&file_path).unwrap();
 
-        assert_eq!(result.unwrap(), true);
+        let (carrier, high_bytes) = load_carrier_keeping_depth(file_path).unwrap();
+        let Carrier::Gray(low) = carrier else {
+            panic!("expected a grayscale carrier");
+        };
+        assert_eq!(low.as_raw()[1], (651 & 0xff) as u8);
+
+        let joined: ImageBuffer<image::Luma<u16>, _> = join_high_bytes(&low, &high_bytes.unwrap());
+        assert_eq!(joined.into_raw(), samples);
     }
 
     #[test]
@@ -139,7 +418,7 @@ mod tests {
         let file_path = dir.path().join("test_image.png");
         let image = RgbImage::new(10, 10);
         image.save(&file_path).expect("Failed to save image");
-        let loaded_image = load_image(file_path.to_str().unwrap()).expect("Failed to load image");
+        let loaded_image = load_image(file_path).expect("Failed to load image");
 
         assert_eq!(loaded_image.dimensions(), (10, 10));
     }
@@ -148,7 +427,7 @@ mod tests {
     fn test_ensure_parent_directory() {
         let dir = tempdir().unwrap();
         let nested_path = dir.path().join("nested").join("file.txt");
-        let result = ensure_parent_directory(nested_path.to_str().unwrap());
+        let result = ensure_parent_directory(&nested_path);
 
         assert!(result.is_ok());
         assert!(nested_path.parent().unwrap().exists());
@@ -159,9 +438,43 @@ mod tests {
         let dir = tempdir().unwrap();
         let file_path = dir.path().join("output_image.png");
         let image = RgbImage::new(10, 10);
-        let result = write_image_file(&image, file_path.to_str().unwrap());
+        let written = write_image_file(&image, &file_path).unwrap();
 
-        assert!(result.is_ok());
+        assert_eq!(written, file_path);
         assert!(file_path.exists());
     }
+
+    #[test]
+    fn test_with_format_extension() {
+        assert_eq!(
+            with_format_extension("out/hidden.png", OutputImageFormat::Bmp),
+            Path::new("out/hidden.bmp")
+        );
+        assert_eq!(
+            with_format_extension("hidden", OutputImageFormat::Tiff),
+            Path::new("hidden.tiff")
+        );
+    }
+
+    #[test]
+    fn test_write_image_file_defaults_to_png() {
+        let dir = tempdir().unwrap();
+        let image = RgbImage::new(10, 10);
+
+        for name in ["output_image", "output_image.txt"] {
+            let file_path = dir.path().join(name);
+            let written = write_image_file(&image, &file_path).unwrap();
+
+            assert_eq!(written, dir.path().join(format!("{}.png", name)));
+            assert_eq!(
+                ImageReader::open(&written)
+                    .unwrap()
+                    .with_guessed_format()
+                    .unwrap()
+                    .format(),
+                Some(ImageFormat::Png)
+            );
+            assert!(!file_path.exists());
+        }
+    }
 }
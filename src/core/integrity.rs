@@ -0,0 +1,43 @@
+use crate::error::ApplicationError;
+use crate::steganography::header::CHECKSUM_SIZE;
+use sha2::{Digest, Sha256};
+
+/// SHA-256 of a message as given to encode, before compression or encryption
+pub fn checksum(message: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    Sha256::digest(message).into()
+}
+
+/// Check a decoded message against the checksum recorded at encode time
+pub fn verify(message: &[u8], expected: &[u8; CHECKSUM_SIZE]) -> Result<(), ApplicationError> {
+    if checksum(message) != *expected {
+        return Err(ApplicationError::DecodingError(
+            "checksum mismatch".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_matching_message() {
+        let message = b"Meet at dawn";
+
+        assert!(verify(message, &checksum(message)).is_ok());
+    }
+
+    #[test]
+    fn test_flipped_byte_is_a_mismatch() {
+        let message = b"Meet at dawn".to_vec();
+        let expected = checksum(&message);
+        let mut corrupted = message.clone();
+        corrupted[3] ^= 0x01;
+
+        assert_eq!(
+            verify(&corrupted, &expected).unwrap_err().to_string(),
+            ApplicationError::DecodingError("checksum mismatch".to_string()).to_string()
+        );
+    }
+}
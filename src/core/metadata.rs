@@ -0,0 +1,205 @@
+use super::file::{ensure_parent_directory, validate_path};
+use crate::error::ApplicationError;
+use image::{ImageBuffer, ImageFormat, Pixel};
+use png::chunk::{self, ChunkType};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+const CHUNK_LENGTH_SIZE: usize = 4;
+const CHUNK_TYPE_SIZE: usize = 4;
+const CHUNK_CRC_SIZE: usize = 4;
+
+/// Ancillary chunks carried over by `--keep-metadata`: text and last-modification time
+const PRESERVED_CHUNKS: [ChunkType; 4] = [chunk::tEXt, chunk::zTXt, chunk::iTXt, chunk::tIME];
+
+/// A raw PNG chunk, without its length and CRC
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    pub kind: ChunkType,
+    pub data: Vec<u8>,
+}
+
+/// Whether a path names a PNG file, judging by its extension
+pub fn is_png(file_path: impl AsRef<Path>) -> bool {
+    matches!(ImageFormat::from_path(file_path), Ok(ImageFormat::Png))
+}
+
+/// Reads the text and time chunks of a PNG file, in file order
+///
+/// Other formats carry no metadata this tool can preserve, so they yield no chunks
+pub fn read_png_metadata(file_path: impl AsRef<Path>) -> Result<Vec<Chunk>, ApplicationError> {
+    let file_path = file_path.as_ref();
+    validate_path(file_path)?;
+    if !is_png(file_path) {
+        return Ok(Vec::new());
+    }
+
+    let bytes = fs::read(file_path)?;
+    let malformed = || {
+        ApplicationError::EncodingError(format!("Malformed PNG chunk in {}", file_path.display()))
+    };
+    let mut rest = bytes.strip_prefix(&PNG_SIGNATURE).ok_or_else(malformed)?;
+    let mut chunks = Vec::new();
+
+    while !rest.is_empty() {
+        let header_size = CHUNK_LENGTH_SIZE + CHUNK_TYPE_SIZE;
+        let header = rest.get(..header_size).ok_or_else(malformed)?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let kind = ChunkType([header[4], header[5], header[6], header[7]]);
+        let data = rest
+            .get(header_size..header_size + length)
+            .ok_or_else(malformed)?;
+
+        if PRESERVED_CHUNKS.contains(&kind) {
+            chunks.push(Chunk {
+                kind,
+                data: data.to_vec(),
+            });
+        }
+        if kind == chunk::IEND {
+            break;
+        }
+        rest = rest
+            .get(header_size + length + CHUNK_CRC_SIZE..)
+            .ok_or_else(malformed)?;
+    }
+
+    Ok(chunks)
+}
+
+/// Writes an RGB or RGBA image as PNG with the given chunks placed ahead of the pixel data
+pub fn write_png_with_metadata<P>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    file_path: impl AsRef<Path>,
+    chunks: &[Chunk],
+) -> Result<(), ApplicationError>
+where
+    P: Pixel,
+    P::Subpixel: PngSample,
+{
+    ensure_parent_directory(&file_path)?;
+    let encoding_error = |e: png::EncodingError| {
+        ApplicationError::EncodingError(format!("PNG encoding failed: {}", e))
+    };
+
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(file_path)?),
+        image.width(),
+        image.height(),
+    );
+    encoder.set_color(match P::CHANNEL_COUNT {
+        1 => png::ColorType::Grayscale,
+        4 => png::ColorType::Rgba,
+        _ => png::ColorType::Rgb,
+    });
+    encoder.set_depth(P::Subpixel::DEPTH);
+
+    let mut writer = encoder.write_header().map_err(encoding_error)?;
+    for chunk in chunks {
+        writer
+            .write_chunk(chunk.kind, &chunk.data)
+            .map_err(encoding_error)?;
+    }
+    writer
+        .write_image_data(&P::Subpixel::to_png_bytes(image.as_raw()))
+        .map_err(encoding_error)?;
+    writer.finish().map_err(encoding_error)
+}
+
+/// Channel samples as a PNG stores them: at their bit depth, most significant byte first
+pub trait PngSample: Sized {
+    const DEPTH: png::BitDepth;
+
+    fn to_png_bytes(samples: &[Self]) -> Vec<u8>;
+}
+
+impl PngSample for u8 {
+    const DEPTH: png::BitDepth = png::BitDepth::Eight;
+
+    fn to_png_bytes(samples: &[u8]) -> Vec<u8> {
+        samples.to_vec()
+    }
+}
+
+impl PngSample for u16 {
+    const DEPTH: png::BitDepth = png::BitDepth::Sixteen;
+
+    fn to_png_bytes(samples: &[u16]) -> Vec<u8> {
+        samples
+            .iter()
+            .flat_map(|sample| sample.to_be_bytes())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+    use tempfile::tempdir;
+
+    fn write_png_with_text(file_path: &Path) {
+        let mut encoder = png::Encoder::new(File::create(file_path).unwrap(), 4, 4);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder
+            .add_text_chunk("Author".to_string(), "Jane".to_string())
+            .unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_chunk(chunk::tIME, &[7, 232, 1, 2, 3, 4, 5])
+            .unwrap();
+        writer.write_chunk(chunk::pHYs, &[0; 9]).unwrap();
+        writer.write_image_data(&[0; 48]).unwrap();
+    }
+
+    #[test]
+    fn test_read_png_metadata() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("carrier.png");
+        write_png_with_text(&file_path);
+
+        let chunks = read_png_metadata(&file_path).unwrap();
+        let kinds: Vec<ChunkType> = chunks.iter().map(|chunk| chunk.kind).collect();
+
+        assert_eq!(kinds, [chunk::tEXt, chunk::tIME]);
+        assert_eq!(chunks[0].data, b"Author\0Jane");
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let dir = tempdir().unwrap();
+        let source_path = dir.path().join("carrier.png");
+        let output_path = dir.path().join("output.png");
+        write_png_with_text(&source_path);
+
+        let chunks = read_png_metadata(&source_path).unwrap();
+        let image = RgbImage::new(4, 4);
+        write_png_with_metadata(&image, &output_path, &chunks).unwrap();
+
+        assert_eq!(read_png_metadata(&output_path).unwrap(), chunks);
+        assert_eq!(
+            image::open(&output_path).unwrap().to_rgb8().dimensions(),
+            (4, 4)
+        );
+    }
+
+    #[test]
+    fn test_non_png_has_no_metadata() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("carrier.bmp");
+        RgbImage::new(4, 4).save(&file_path).unwrap();
+
+        assert!(read_png_metadata(&file_path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_malformed_png() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("carrier.png");
+        fs::write(&file_path, b"not a png").unwrap();
+
+        assert!(read_png_metadata(&file_path).is_err());
+    }
+}
@@ -1,4 +1,14 @@
+pub mod activity;
+pub mod bundle;
+pub mod compare;
 pub mod compression;
+pub mod config;
 pub mod file;
 pub mod image;
+pub mod integrity;
+pub mod metadata;
 pub mod operations;
+pub mod shard;
+pub mod streaming;
+pub mod timings;
+pub mod units;
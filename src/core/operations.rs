@@ -1,138 +1,2202 @@
 use crate::core;
-use crate::cryptography::{aes, util::key_to_bytes};
+use crate::core::compression::Algorithm;
+use crate::core::image::{Carrier, HighBytes, OutputImageFormat};
+use crate::core::timings::Phase;
+use crate::cryptography::{
+    cipher::{self, Cipher},
+    util::{derive_seed, KdfCost},
+};
 use crate::error::ApplicationError;
-use crate::steganography::lsb;
+use crate::steganography::{
+    analysis, gif,
+    header::{Header, PayloadInfo, CHECKSUM_SIZE, HEADER_SIZE, MAX_HEADER_SIZE},
+    layout::Layout,
+    lsb::{self, RawFraming},
+    util::{image_capacity_bytes, payload_capacity_bits},
+};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
 use base64::engine::general_purpose::STANDARD as BASE64_ENGINE;
 use base64::Engine;
-use colored::*;
+use clap::ValueEnum;
+use image::{
+    DynamicImage, EncodableLayout, GrayImage, ImageBuffer, ImageFormat, Luma, Pixel,
+    PixelWithColorType, Rgb, Rgba, RgbaImage,
+};
+use log::{debug, info};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const BITS_PER_BYTE: usize = 8;
+const DECOY_SLOTS: usize = 2;
 
 /// Progress tracking interface
 pub trait Progress {
     fn update(&self, message: &str);
+    fn warn(&self, message: &str);
     fn finish_with_message(&self, message: &str);
+
+    /// Records how long a phase of the operation took
+    fn timing(&self, _phase: Phase, _elapsed: Duration) {}
+}
+
+/// Progress sink that discards all updates, for callers with no terminal to draw on
+pub struct SilentProgress;
+
+impl Progress for SilentProgress {
+    fn update(&self, _message: &str) {}
+
+    fn warn(&self, _message: &str) {}
+
+    fn finish_with_message(&self, _message: &str) {}
+}
+
+/// What happens to the least significant bits the payload does not use
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum UnusedBits {
+    /// Randomize them when the carrier already holds an embedded message, so leftovers
+    /// of the old payload don't mark where it ended; otherwise leave them alone
+    #[default]
+    Auto,
+    /// Leave them as they are in the carrier
+    Preserve,
+    /// Always replace them with random bits
+    Randomize,
+}
+
+/// Framing of the message a carrier holds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum DecodeFormat {
+    /// Mindbender's header and payload, optionally encrypted and compressed
+    #[default]
+    Mindbender,
+    /// Plain bytes in the color channels' LSBs, as written by other LSB tools (see
+    /// [`lsb::decode_raw`])
+    RawLsb,
+}
+
+/// Settings controlling how a key encrypts the payload
+#[derive(Clone, Debug, Default)]
+pub struct EncryptionOptions {
+    /// Use the key bytes directly, zero-padded to 32 bytes, instead of deriving
+    /// the cipher key from them with Argon2id
+    pub raw_key: bool,
+    /// Cipher to encrypt with (AES by default). On decode the cipher is read from
+    /// the payload's tag, and this only rejects payloads tagged with another one
+    pub cipher: Option<Cipher>,
+    /// Authenticate the carrier's dimensions with the ciphertext, so the message only
+    /// decrypts from an image of the same width and height
+    pub bind_carrier: bool,
+    /// Argon2id cost of deriving the cipher key from a passphrase. It is recorded with
+    /// the ciphertext, so decoding ignores this setting
+    pub kdf_cost: KdfCost,
+}
+
+/// Settings controlling how the payload is compressed
+#[derive(Clone, Debug, Default)]
+pub struct CompressionOptions {
+    pub algorithm: Algorithm,
+    /// Compression level, or the algorithm's default when unset
+    pub level: Option<u32>,
+}
+
+/// Settings controlling how a message is prepared and embedded
+#[derive(Clone, Debug, Default)]
+pub struct EncodeOptions {
+    pub key: Option<Vec<u8>>,
+    pub encryption: EncryptionOptions,
+    /// Compress the payload before embedding it; uncompressed when unset
+    pub compression: Option<CompressionOptions>,
+    pub headerless: bool,
+    pub layout: Layout,
+    pub decoy: Option<Decoy>,
+    pub unused_bits: UnusedBits,
+    /// Copy the carrier's PNG text and time chunks into the output, which must be a
+    /// PNG; otherwise all metadata is stripped
+    pub keep_metadata: bool,
+    /// Run every step but saving the encoded image
+    pub dry_run: bool,
+    /// What happens to an output file that already exists
+    pub existing_output: core::file::ExistingOutput,
+    /// Format to save the encoded image in, replacing the output path's extension
+    pub output_format: Option<OutputImageFormat>,
+    /// When the data path is a directory, also bundle the files in its subdirectories
+    pub recursive: bool,
+    /// Lowest PSNR, in dB, the encoded image may have against the carrier; embedding
+    /// that degrades it further fails instead of saving
+    pub min_psnr: Option<f64>,
+    /// Also save an image highlighting the pixels embedding changed to this path, see
+    /// [`analysis::change_map`]
+    pub diff_map: Option<String>,
+    /// Read the carrier and write the encoded image a strip of rows at a time, holding
+    /// only the rows the payload goes into in memory (see [`encode_streamed`])
+    pub streaming: bool,
+}
+
+/// A second message, revealed by its own "duress" key, stored alongside the real one
+///
+/// The two messages occupy disjoint pixel slots in key-dependent order, so
+/// decoding with either key yields only the matching message
+#[derive(Clone, Debug)]
+pub struct Decoy {
+    pub data_path: String,
+    pub key: Vec<u8>,
+}
+
+/// Settings controlling how a message is extracted and recovered
+#[derive(Clone, Debug, Default)]
+pub struct DecodeOptions {
+    pub key: Option<Vec<u8>>,
+    pub encryption: EncryptionOptions,
+    pub decompress: bool,
+    pub headerless_length: Option<usize>,
+    /// Read the payload in the format of releases before the header, up to its null byte,
+    /// without looking for a header (see [`lsb::decode_legacy_delimited`])
+    pub legacy_delimiter: bool,
+    pub layout: Layout,
+    /// Carriers holding the other shards of a message split with [`encode_split`]
+    pub join: Vec<String>,
+    /// Replace invalid UTF-8 in the extracted payload with U+FFFD, with a warning,
+    /// instead of failing
+    pub lossy_utf8: bool,
+    /// Extract and recover the message without writing it
+    pub dry_run: bool,
+    /// What happens to an output file that already exists
+    pub existing_output: core::file::ExistingOutput,
+    /// Read a message embedded by another LSB tool, framed this way, instead of a
+    /// mindbender payload. It is returned as is, without decryption or decompression
+    pub raw_lsb: Option<RawFraming>,
+}
+
+/// Summary of a completed encode operation
+pub struct EncodeSummary {
+    pub output_path: PathBuf,
+    pub payload_bytes: usize,
+    pub capacity_bytes: usize,
+    /// Whether the payload was compressed, which compression skips when it wouldn't help
+    pub compressed: bool,
+}
+
+/// How many bytes a carrier can hide with the default layout
+pub struct Capacity {
+    /// Every least significant bit of the carrier
+    pub raw_bytes: usize,
+    /// Size of the header an unencrypted encode writes, with its checksum but no region
+    pub header_bytes: usize,
+    /// What is left for the payload after the header
+    pub usable_bytes: usize,
+    /// Longest message that still fits once encrypted with a passphrase and base64-encoded
+    pub encrypted_bytes: usize,
 }
 
 /// Encodes a message into an image using LSB steganography
 ///
-/// 1. Loads and validates the carrier image
-/// 2. Converts lossy images to lossless format if necessary
-/// 3. Reads the message from the data file
-/// 4. Optionally encrypts the message using the provided key
-/// 5. Optionally compresses the message
-/// 6. Encodes the message into the image using LSB steganography
-/// 7. Saves the resulting image to the specified output path
+/// 1. Loads and validates the carrier image, and checks the output format is lossless
+/// 2. Converts lossy images to lossless pixels in memory if necessary
+/// 3. Reads the message from the data file, or from stdin when `data_path` is `-`
+/// 4. Optionally encrypts and compresses the message (see [`prepare_payload`])
+/// 5. Encodes the message into the image using LSB steganography following the
+///    options' layout, without a header when `headerless` is set. With a
+///    decoy, both messages go into randomly assigned slots (see [`Decoy`])
+/// 6. Handles the bits the payload left unused according to [`UnusedBits`]
+///    (not applicable to headerless payloads)
+/// 7. Saves the resulting image to the specified output path, stripped of metadata
+///    unless `keep_metadata` is set
+///
+/// A GIF carrier saved as a GIF is encoded across all of its frames instead, keeping its
+/// animation (see [`gif`])
 pub fn encode(
     data_path: &str,
     carrier_path: &str,
     output_path: &str,
-    key: Option<String>,
-    compress: bool,
+    options: &EncodeOptions,
     progress: &impl Progress,
-) -> Result<(), ApplicationError> {
+) -> Result<EncodeSummary, ApplicationError> {
+    encode_with(
+        || read_data(data_path, options),
+        Path::new(carrier_path),
+        Path::new(output_path),
+        options,
+        progress,
+    )
+}
+
+/// Reads the message to encode from `data_path`, or from stdin when it is `-`
+///
+/// A directory or a pattern such as `notes/*.txt` bundles every file it names into one
+/// message, see [`core::bundle`]
+fn read_data(data_path: &str, options: &EncodeOptions) -> Result<String, ApplicationError> {
+    if !core::bundle::is_bundle_source(data_path) {
+        return core::file::read_text_or_stdin(data_path);
+    }
+    let entries = core::bundle::collect(data_path, options.recursive)?;
+    info!("Bundling {} files from {}", entries.len(), data_path);
+    core::bundle::pack(&entries)
+}
+
+/// [`encode`] with the message supplied by `read_message`, called once the carrier is loaded
+fn encode_with(
+    read_message: impl FnOnce() -> Result<String, ApplicationError>,
+    carrier_path: &Path,
+    output_path: &Path,
+    options: &EncodeOptions,
+    progress: &impl Progress,
+) -> Result<EncodeSummary, ApplicationError> {
+    let output_path = output_path_with_format(output_path, options)?;
+    if options.streaming {
+        return encode_streamed(read_message, carrier_path, &output_path, options, progress);
+    }
+    if core::image::is_gif(carrier_path) && core::image::is_gif(&output_path) {
+        return encode_gif(read_message, carrier_path, output_path, options, progress);
+    }
+    let output_path = checked_output_path(&output_path, options)?;
+    let (image, preserved) = load_carrier(carrier_path, options, progress)?;
+
+    progress.update("Reading data file...");
+    let data = read_message()?;
+    info!("Message is {} bytes", data.len());
+
+    let (payload, capacity_bits) = match image {
+        Carrier::Gray(image) => {
+            embed_message(image, data, &output_path, &preserved, options, progress)?
+        }
+        Carrier::Color(image) => {
+            embed_message(image, data, &output_path, &preserved, options, progress)?
+        }
+    };
+
+    progress.finish_with_message(&format!(
+        "{} => {}",
+        completed("Encoding", options.dry_run),
+        output_path.display()
+    ));
+
+    Ok(EncodeSummary {
+        output_path,
+        payload_bytes: payload.data.len(),
+        capacity_bytes: capacity_bits / BITS_PER_BYTE,
+        compressed: is_compressed(&payload.data),
+    })
+}
+
+/// [`encode_with`] into the frames of a GIF carrier, saved as a GIF
+fn encode_gif(
+    read_message: impl FnOnce() -> Result<String, ApplicationError>,
+    carrier_path: &Path,
+    output_path: PathBuf,
+    options: &EncodeOptions,
+    progress: &impl Progress,
+) -> Result<EncodeSummary, ApplicationError> {
+    if options.headerless || options.decoy.is_some() || options.keep_metadata {
+        return Err(ApplicationError::EncodingError(
+            "A GIF carrier cannot be headerless, have a decoy, or keep metadata".to_string(),
+        ));
+    }
+    if options.encryption.bind_carrier || options.unused_bits == UnusedBits::Randomize {
+        return Err(ApplicationError::EncodingError(
+            "A GIF carrier cannot be bound or have its unused bits randomized".to_string(),
+        ));
+    }
+    if options.min_psnr.is_some() || options.layout.region.is_some() || options.diff_map.is_some() {
+        return Err(ApplicationError::EncodingError(
+            "A GIF carrier cannot have a minimum PSNR, a region or a diff map".to_string(),
+        ));
+    }
+
     progress.update("Loading carrier image...");
-    let mut image = if core::image::is_lossless(&carrier_path)? {
-        core::image::load_image(&carrier_path)?
+    let mut animation = timed(progress, Phase::Load, "Loading carrier image", || {
+        core::image::load_gif(carrier_path)
+    })?;
+    info!(
+        "Carrier {} is a {}x{} GIF with {} frame(s)",
+        carrier_path.display(),
+        animation.width,
+        animation.height,
+        animation.frames.len()
+    );
+
+    progress.update("Reading data file...");
+    let data = read_message()?;
+    info!("Message is {} bytes", data.len());
+    let payload = prepare_checked(data, options.key.as_deref(), options, &[], progress)?;
+
+    progress.update("Encoding data into image...");
+    timed(progress, Phase::Embed, "Encoding data into image", || {
+        gif::encode(&payload.data, &mut animation, &options.layout, payload.info)
+    })?;
+    let capacity_bits = payload_capacity_bits(
+        &gif::carrier_indices(&animation),
+        &options.layout,
+        MAX_HEADER_SIZE,
+    );
+
+    if options.dry_run {
+        info!("Dry run: not saving {}", output_path.display());
     } else {
-        println!(
-            "{}",
-            "Warning: Carrier image is lossy. Converting to lossless format...".yellow()
-        );
-        let temp_output = format!("{}.png", output_path);
-        core::image::convert_to_lossless(&carrier_path, &temp_output)?;
-        core::image::load_image(&temp_output)?
-    };
+        progress.update("Saving encoded image...");
+        core::file::prepare_output(&output_path, options.existing_output)?;
+        timed(progress, Phase::Save, "Saving encoded image", || {
+            core::image::write_gif(&animation, &output_path)
+        })?;
+    }
+    progress.finish_with_message(&format!(
+        "{} => {}",
+        completed("Encoding", options.dry_run),
+        output_path.display()
+    ));
+
+    Ok(EncodeSummary {
+        output_path,
+        payload_bytes: payload.data.len(),
+        capacity_bytes: capacity_bits / BITS_PER_BYTE,
+        compressed: is_compressed(&payload.data),
+    })
+}
+
+/// [`encode_with`] a strip of rows at a time, for PNG and TIFF carriers too large to
+/// decode into memory
+///
+/// Only the leading rows that the header and payload go into are held in memory and
+/// embedded; every row after them is copied to the output as it is read. This needs a
+/// layout in raster order (see [`Layout::is_raster_order`]), and rules out the options
+/// that have to see the whole carrier: a decoy, randomized unused bits, a minimum PSNR, a
+/// diff map and kept metadata. The encoded image keeps the carrier's color type
+fn encode_streamed(
+    read_message: impl FnOnce() -> Result<String, ApplicationError>,
+    carrier_path: &Path,
+    output_path: &Path,
+    options: &EncodeOptions,
+    progress: &impl Progress,
+) -> Result<EncodeSummary, ApplicationError> {
+    if !options.layout.is_raster_order() || options.decoy.is_some() {
+        return Err(ApplicationError::EncodingError(
+            "A streamed carrier cannot be palette-safe, seeded, scattered, confined to a region or have a decoy".to_string(),
+        ));
+    }
+    if options.unused_bits == UnusedBits::Randomize
+        || options.min_psnr.is_some()
+        || options.diff_map.is_some()
+        || options.keep_metadata
+    {
+        return Err(ApplicationError::EncodingError(
+            "A streamed carrier cannot have its unused bits randomized, a minimum PSNR, a diff map or kept metadata".to_string(),
+        ));
+    }
+    let output_path = checked_output_path(output_path, options)?;
+    if output_path.exists() && fs::canonicalize(&output_path)? == fs::canonicalize(carrier_path)? {
+        return Err(ApplicationError::EncodingError(
+            "A streamed carrier cannot be overwritten by its own encoded image".to_string(),
+        ));
+    }
+
+    progress.update("Reading carrier header...");
+    let reader = core::streaming::StripReader::open(carrier_path)?;
+    let dimensions = (reader.width, reader.height);
+    info!(
+        "Carrier {} is {}x{}; streaming it",
+        carrier_path.display(),
+        reader.width,
+        reader.height
+    );
 
     progress.update("Reading data file...");
-    let data = core::file::read_text(&data_path)?;
+    let data = read_message()?;
+    info!("Message is {} bytes", data.len());
+    let aad = carrier_aad(dimensions, &options.encryption);
+    let payload = prepare_checked(data, options.key.as_deref(), options, &aad, progress)?;
 
-    let mut data = if let Some(key) = key {
-        progress.update("Encrypting data...");
-        let key_bytes = key_to_bytes(&key)?;
-        aes::encrypt(&data, &key_bytes)?
+    progress.update("Encoding data into image...");
+    let capacity_bits = match reader.samples {
+        core::streaming::Samples::Gray => {
+            stream_payload::<Luma<u8>>(reader, &payload, &output_path, options, progress)?
+        }
+        core::streaming::Samples::Rgb => {
+            stream_payload::<Rgb<u8>>(reader, &payload, &output_path, options, progress)?
+        }
+        core::streaming::Samples::Rgba => {
+            stream_payload::<Rgba<u8>>(reader, &payload, &output_path, options, progress)?
+        }
+    };
+    progress.finish_with_message(&format!(
+        "{} => {}",
+        completed("Encoding", options.dry_run),
+        output_path.display()
+    ));
+
+    Ok(EncodeSummary {
+        output_path,
+        payload_bytes: payload.data.len(),
+        capacity_bytes: capacity_bits / BITS_PER_BYTE,
+        compressed: is_compressed(&payload.data),
+    })
+}
+
+/// Embeds a prepared payload into the leading rows of a streamed carrier of `P` pixels
+/// and writes the encoded image, returning the payload capacity in bits
+fn stream_payload<P: Pixel<Subpixel = u8>>(
+    mut reader: core::streaming::StripReader,
+    payload: &CheckedPayload,
+    output_path: &Path,
+    options: &EncodeOptions,
+    progress: &impl Progress,
+) -> Result<usize, ApplicationError> {
+    let layout = &options.layout;
+    let depth = layout.depth.max(1);
+    let (width, height, samples) = (reader.width, reader.height, reader.samples);
+    let pixel_count = width as usize * height as usize;
+    let row_bytes = reader.row_bytes();
+    // The largest header is assumed, which at worst reads a few more rows than needed
+    let skip = if options.headerless {
+        0
     } else {
-        data
+        lsb::header_pixels::<P>(MAX_HEADER_SIZE)
     };
+    let channels = (payload.data.len() * BITS_PER_BYTE).div_ceil(depth);
+    let rows = layout
+        .pixels_spanned::<P>(skip, channels)
+        .div_ceil(width.max(1) as usize)
+        .clamp(1, height.max(1) as usize);
 
-    if compress {
-        progress.update("Compressing data...");
-        let compressed_data = core::compression::compress(data.as_bytes())?;
-        // Add a marker to indicate compression
-        data = format!("COMPRESSED:{}", BASE64_ENGINE.encode(&compressed_data));
+    let head = timed(progress, Phase::Load, "Loading leading rows", || {
+        let mut head = Vec::with_capacity(rows * row_bytes);
+        while head.len() < rows * row_bytes {
+            match reader.next() {
+                Some(strip) => head.extend_from_slice(&strip?),
+                None => break,
+            }
+        }
+        Ok::<_, ApplicationError>(head)
+    })?;
+    let head_rows = (head.len() / row_bytes.max(1)) as u32;
+    debug!("Embedding into the first {} of {} rows", head_rows, height);
+    let mut image = ImageBuffer::<P, _>::from_raw(width, head_rows, head).ok_or_else(|| {
+        ApplicationError::EncodingError("Carrier ended before its last row".to_string())
+    })?;
+
+    let started = Instant::now();
+    let capacity_bits = if options.headerless {
+        lsb::encode_headerless(&payload.data, &mut image, layout)?;
+        layout.channels_within::<P>(0, pixel_count) * depth
+    } else {
+        if randomizes_unused(&image, options) {
+            return Err(ApplicationError::EncodingError(
+                "The carrier already holds a message, whose leftovers a streamed encode cannot randomize; pass --unused-bits preserve to keep them".to_string(),
+            ));
+        }
+        embed_payload(&payload.data, &mut image, layout, payload.info, false)?;
+        layout.channels_within::<P>(skip, pixel_count) * depth
+    };
+    progress.timing(Phase::Embed, started.elapsed());
+    info!(
+        "Payload uses {} of {} bytes of capacity ({:.1}%)",
+        payload.data.len(),
+        capacity_bits / BITS_PER_BYTE,
+        payload.data.len() as f64 * 100.0 / (capacity_bits / BITS_PER_BYTE).max(1) as f64
+    );
+
+    if options.dry_run {
+        info!("Dry run: not saving {}", output_path.display());
+        return Ok(capacity_bits);
     }
+    core::file::prepare_output(output_path, options.existing_output)?;
+    let strips = std::iter::once(Ok(image.into_raw())).chain(reader);
+    let written = timed(progress, Phase::Save, "Saving encoded image", || {
+        core::streaming::write_strips(output_path, (width, height), samples, strips)
+    });
+    if written.is_err() {
+        // A half-written image would only fail to decode later
+        let _ = fs::remove_file(output_path);
+    }
+    written?;
 
-    progress.update("Encoding data into image...");
-    lsb::encode(&data, &mut image)?;
+    Ok(capacity_bits)
+}
+
+/// A payload ready to embed, with what its header records: the checksum of the message it
+/// was prepared from, unless it was encrypted, and whether it was encrypted
+struct CheckedPayload {
+    data: String,
+    info: PayloadInfo,
+}
+
+/// [`prepare_payload`] with the options' encryption and compression, recording the
+/// checksum of `message` (see [`core::integrity`]) for the header
+///
+/// An encrypted payload gets no checksum: the header is readable without the key, so a hash
+/// of the plaintext would let anyone confirm a guessed message, and the cipher's
+/// authentication tag already catches damage
+fn prepare_checked(
+    message: String,
+    key: Option<&[u8]>,
+    options: &EncodeOptions,
+    aad: &[u8],
+    progress: &impl Progress,
+) -> Result<CheckedPayload, ApplicationError> {
+    let checksum = key
+        .is_none()
+        .then(|| core::integrity::checksum(message.as_bytes()));
+    let data = prepare_payload(
+        message,
+        key,
+        &options.encryption,
+        options.compression.as_ref(),
+        aad,
+        progress,
+    )?;
+    Ok(CheckedPayload {
+        data,
+        info: PayloadInfo {
+            checksum,
+            encrypted: key.is_some(),
+        },
+    })
+}
+
+/// Encodes `message` into a loaded carrier (see [`encode_into`]) and saves it, returning
+/// the embedded payload and the payload capacity in bits
+fn embed_message<P: CarrierPixel>(
+    mut image: ImageBuffer<P, Vec<u8>>,
+    message: String,
+    output_path: &Path,
+    preserved: &Preserved,
+    options: &EncodeOptions,
+    progress: &impl Progress,
+) -> Result<(CheckedPayload, usize), ApplicationError> {
+    let original = options.diff_map.as_ref().map(|_| image.clone());
+    let encoded = encode_into(&mut image, message, options, progress)?;
 
     progress.update("Saving encoded image...");
-    let output_path = if !core::image::has_valid_image_extension(&output_path) {
-        format!("{}.png", output_path)
-    } else {
-        output_path.to_string()
+    if let Some(original) = original {
+        save_change_map(&original, &image, options)?;
+    }
+    save_encoded(image, output_path, preserved, options, progress)?;
+
+    Ok(encoded)
+}
+
+/// Saves the map of the pixels that changed from `original` to `encoded` to the options'
+/// diff map path, if any
+fn save_change_map<P: Pixel<Subpixel = u8>>(
+    original: &ImageBuffer<P, Vec<u8>>,
+    encoded: &ImageBuffer<P, Vec<u8>>,
+    options: &EncodeOptions,
+) -> Result<(), ApplicationError> {
+    let Some(diff_map) = &options.diff_map else {
+        return Ok(());
+    };
+    if options.dry_run {
+        info!("Dry run: not saving {}", diff_map);
+        return Ok(());
+    }
+    let map = analysis::change_map(original, encoded);
+    let diff_map = core::image::write_image_file(&map, diff_map)?;
+    info!("Saved the change map to {}", diff_map.display());
+    Ok(())
+}
+
+/// Prepares `message` (see [`prepare_checked`]) and embeds it into a carrier in memory,
+/// returning the embedded payload and the payload capacity in bits
+///
+/// This is the whole of encoding once the carrier is loaded, shared by [`encode`] and
+/// [`encode_buffer`]
+fn encode_into<P: Pixel<Subpixel = u8>>(
+    image: &mut ImageBuffer<P, Vec<u8>>,
+    message: String,
+    options: &EncodeOptions,
+    progress: &impl Progress,
+) -> Result<(CheckedPayload, usize), ApplicationError> {
+    let aad = carrier_aad(image.dimensions(), &options.encryption);
+    let payload = prepare_checked(message, options.key.as_deref(), options, &aad, progress)?;
+    let capacity_bits = embed_into(image, &payload, &aad, options, progress)?;
+    Ok((payload, capacity_bits))
+}
+
+/// Embeds a prepared payload (and the decoy, if any) into a carrier in memory, returning
+/// the payload capacity in bits
+fn embed_into<P: Pixel<Subpixel = u8>>(
+    image: &mut ImageBuffer<P, Vec<u8>>,
+    payload: &CheckedPayload,
+    aad: &[u8],
+    options: &EncodeOptions,
+    progress: &impl Progress,
+) -> Result<usize, ApplicationError> {
+    let data = &payload.data;
+    let randomize_unused = randomizes_unused(image, options);
+    let original = options.min_psnr.map(|_| image.clone());
+    // Timed on its own, so a decoy's encryption isn't counted as embedding
+    let embed = |payload: &CheckedPayload, image: &mut ImageBuffer<P, Vec<u8>>, layout: &Layout| {
+        let started = Instant::now();
+        let embedded = embed_payload(&payload.data, image, layout, payload.info, randomize_unused);
+        progress.timing(Phase::Embed, started.elapsed());
+        embedded
+    };
+
+    progress.update("Encoding data into image...");
+    let started = Instant::now();
+    let capacity_bits = match &options.decoy {
+        Some(decoy) => {
+            let key = options.key.as_deref().ok_or_else(|| {
+                ApplicationError::EncodingError(
+                    "A decoy message requires an encryption key".to_string(),
+                )
+            })?;
+            if decoy.key == key {
+                return Err(ApplicationError::EncodingError(
+                    "The duress key must differ from the encryption key".to_string(),
+                ));
+            }
+
+            progress.update("Reading decoy file...");
+            let decoy_data = core::file::read_text(&decoy.data_path)?;
+            let decoy_payload =
+                prepare_checked(decoy_data, Some(&decoy.key), options, aad, progress)?;
+
+            let real_slot = OsRng.next_u32() as usize % DECOY_SLOTS;
+            let decoy_slot = (real_slot + 1) % DECOY_SLOTS;
+            let layout = keyed_slot_layout(&options.layout, key, real_slot);
+            embed(payload, image, &layout)?;
+            embed(
+                &decoy_payload,
+                image,
+                &keyed_slot_layout(&options.layout, &decoy.key, decoy_slot),
+            )?;
+            payload_capacity_bits(image, &layout, MAX_HEADER_SIZE)
+        }
+        None if options.headerless => {
+            let started = Instant::now();
+            lsb::encode_headerless(data, image, &options.layout)?;
+            progress.timing(Phase::Embed, started.elapsed());
+            options.layout.capacity_bits(image)
+        }
+        None => {
+            embed(payload, image, &options.layout)?;
+            payload_capacity_bits(image, &options.layout, MAX_HEADER_SIZE)
+        }
     };
-    core::image::write_image_file(&image, &output_path)?;
+    debug!("Encoding data into image took {:?}", started.elapsed());
+    info!(
+        "Payload uses {} of {} bytes of capacity ({:.1}%)",
+        data.len(),
+        capacity_bits / BITS_PER_BYTE,
+        data.len() as f64 * 100.0 / (capacity_bits / BITS_PER_BYTE).max(1) as f64
+    );
+    if let Some(original) = original {
+        check_psnr(&original, image, options)?;
+    }
+
+    Ok(capacity_bits)
+}
+
+/// Fails when `encoded` has degraded below the options' minimum PSNR against `original`
+fn check_psnr<P: Pixel<Subpixel = u8>>(
+    original: &ImageBuffer<P, Vec<u8>>,
+    encoded: &ImageBuffer<P, Vec<u8>>,
+    options: &EncodeOptions,
+) -> Result<(), ApplicationError> {
+    let Some(min_psnr) = options.min_psnr else {
+        return Ok(());
+    };
+    let psnr = analysis::psnr(original, encoded);
+    info!("Encoded image has a PSNR of {:.2} dB", psnr);
+    if psnr < min_psnr {
+        return Err(ApplicationError::EncodingError(format!(
+            "Embedding lowers the PSNR to {:.2} dB, below the minimum of {:.2} dB; use a bigger carrier or a lower --depth",
+            psnr, min_psnr
+        )));
+    }
+    Ok(())
+}
+
+/// Encodes `data` into a copy of `carrier` without touching the disk, the in-memory
+/// counterpart of [`encode`]
+///
+/// `data` must be UTF-8 text. The options apply as they do to [`encode`], except that a
+/// decoy, whose message is read from a file, is rejected; the options that only concern
+/// saving the image are ignored
+pub fn encode_buffer<P: Pixel<Subpixel = u8>>(
+    data: &[u8],
+    carrier: &ImageBuffer<P, Vec<u8>>,
+    options: &EncodeOptions,
+) -> Result<ImageBuffer<P, Vec<u8>>, ApplicationError> {
+    if options.decoy.is_some() {
+        return Err(ApplicationError::EncodingError(
+            "A decoy message cannot be encoded in memory".to_string(),
+        ));
+    }
+    let message = String::from_utf8(data.to_vec())
+        .map_err(|_| ApplicationError::EncodingError("Message is not valid UTF-8".to_string()))?;
+
+    let mut image = carrier.clone();
+    encode_into(&mut image, message, options, &SilentProgress)?;
+    Ok(image)
+}
+
+/// Encodes a message too large for one carrier across several, one shard per carrier
+///
+/// The payload is prepared once (see [`prepare_payload`]) and split in proportion to each
+/// carrier's capacity, each shard marked with the message id, its position and the shard
+/// count (see [`core::shard`]). Shard `n` is saved to `output_path` with `-n` appended to
+/// its name, e.g. `hidden-2.png`
+pub fn encode_split(
+    data_path: &str,
+    carrier_paths: &[String],
+    output_path: &str,
+    options: &EncodeOptions,
+    progress: &impl Progress,
+) -> Result<Vec<EncodeSummary>, ApplicationError> {
+    if options.headerless || options.decoy.is_some() {
+        return Err(ApplicationError::EncodingError(
+            "A split message cannot be headerless or have a decoy".to_string(),
+        ));
+    }
+    if options.encryption.bind_carrier || options.diff_map.is_some() {
+        return Err(ApplicationError::EncodingError(
+            "A split message cannot be bound to a single carrier or have a diff map".to_string(),
+        ));
+    }
+    let output_path = checked_output_path(
+        &output_path_with_format(Path::new(output_path), options)?,
+        options,
+    )?;
+    let carriers = carrier_paths
+        .iter()
+        .map(|carrier_path| load_carrier(Path::new(carrier_path), options, progress))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    progress.update("Reading data file...");
+    let data = read_data(data_path, options)?;
+    info!("Message is {} bytes", data.len());
+    let CheckedPayload { data, info } =
+        prepare_checked(data, options.key.as_deref(), options, &[], progress)?;
+    let capacities: Vec<usize> = carriers
+        .iter()
+        .map(|(image, _)| carrier_capacity_bits(image, &options.layout) / BITS_PER_BYTE)
+        .collect();
+    let shards = core::shard::split(&data, OsRng.next_u32(), &capacities)?;
+    info!(
+        "Split the {} byte payload into {} shards",
+        data.len(),
+        shards.len()
+    );
+
+    let total = shards.len();
+    let summaries = carriers
+        .into_iter()
+        .zip(shards)
+        .zip(capacities)
+        .enumerate()
+        .map(|(index, (((image, preserved), shard), capacity_bytes))| {
+            progress.update(&format!("Encoding shard {} of {}...", index + 1, total));
+            let shard_output_path = numbered_path(&output_path, index + 1);
+            match image {
+                Carrier::Gray(image) => embed_shard(
+                    image,
+                    &shard,
+                    info,
+                    &shard_output_path,
+                    &preserved,
+                    options,
+                    progress,
+                )?,
+                Carrier::Color(image) => embed_shard(
+                    image,
+                    &shard,
+                    info,
+                    &shard_output_path,
+                    &preserved,
+                    options,
+                    progress,
+                )?,
+            }
+            Ok(EncodeSummary {
+                output_path: shard_output_path,
+                payload_bytes: shard.len(),
+                capacity_bytes,
+                compressed: is_compressed(&data),
+            })
+        })
+        .collect::<Result<Vec<_>, ApplicationError>>()?;
 
     progress.finish_with_message(&format!(
-        "Encoding completed successfully => {}",
-        output_path
+        "{} => {} shards",
+        completed("Encoding", options.dry_run),
+        total
     ));
 
+    Ok(summaries)
+}
+
+/// Embeds one shard of a split payload into its carrier and saves it
+///
+/// Every shard's header carries the `info` of the whole message
+fn embed_shard<P: CarrierPixel>(
+    mut image: ImageBuffer<P, Vec<u8>>,
+    shard: &str,
+    info: PayloadInfo,
+    output_path: &Path,
+    preserved: &Preserved,
+    options: &EncodeOptions,
+    progress: &impl Progress,
+) -> Result<(), ApplicationError> {
+    let randomize_unused = randomizes_unused(&image, options);
+    let original = options.min_psnr.map(|_| image.clone());
+    timed(progress, Phase::Embed, "Encoding shard into image", || {
+        embed_payload(shard, &mut image, &options.layout, info, randomize_unused)
+    })?;
+    if let Some(original) = original {
+        check_psnr(&original, &image, options)?;
+    }
+    save_encoded(image, output_path, preserved, options, progress)
+}
+
+/// [`payload_capacity_bits`] of a carrier of either color type
+fn carrier_capacity_bits(image: &Carrier, layout: &Layout) -> usize {
+    match image {
+        Carrier::Gray(image) => payload_capacity_bits(image, layout, MAX_HEADER_SIZE),
+        Carrier::Color(image) => payload_capacity_bits(image, layout, MAX_HEADER_SIZE),
+    }
+}
+
+/// `output_path` with the extension of the options' `output_format`, or with a `.png`
+/// extension when it has no image extension
+///
+/// Lossy output formats are rejected outright, since they would destroy the payload
+fn output_path_with_format(
+    output_path: &Path,
+    options: &EncodeOptions,
+) -> Result<PathBuf, ApplicationError> {
+    match options.output_format {
+        Some(format) if !format.is_lossless() => Err(ApplicationError::EncodingError(format!(
+            "Output format {} is lossy and would destroy the hidden data; use png, bmp or tiff",
+            format.name()
+        ))),
+        Some(format) => Ok(core::image::with_format_extension(output_path, format)),
+        None => Ok(core::image::with_image_extension(output_path)),
+    }
+}
+
+/// `output_path` with a `.png` extension when it has no image extension, checked to be a
+/// format that can hold the payload
+fn checked_output_path(
+    output_path: &Path,
+    options: &EncodeOptions,
+) -> Result<PathBuf, ApplicationError> {
+    let output_path = core::image::with_image_extension(output_path);
+    if !core::image::is_lossless(&output_path)? {
+        return Err(ApplicationError::EncodingError(
+            "Output format is lossy and would destroy the hidden data".to_string(),
+        ));
+    }
+    if options.keep_metadata && !core::metadata::is_png(&output_path) {
+        return Err(ApplicationError::EncodingError(
+            "Metadata can only be kept in a PNG output".to_string(),
+        ));
+    }
+    Ok(output_path)
+}
+
+/// `path` with `-n` appended to its file name, before the extension
+fn numbered_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = OsString::from(path.file_stem().unwrap_or_default());
+    name.push(format!("-{}", n));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// What an encoded carrier keeps of the original besides the pixels the payload goes into
+struct Preserved {
+    /// PNG text and time chunks, empty unless `keep_metadata` is set
+    metadata: Vec<core::metadata::Chunk>,
+    /// High bytes of a 16-bit carrier, which is saved back with 16 bits per channel
+    high_bytes: Option<HighBytes>,
+}
+
+/// Loads a carrier for encoding as lossless pixels, with what its output preserves
+fn load_carrier(
+    carrier_path: &Path,
+    options: &EncodeOptions,
+    progress: &impl Progress,
+) -> Result<(Carrier, Preserved), ApplicationError> {
+    if options.layout.use_alpha && !core::image::has_alpha_channel(carrier_path)? {
+        return Err(ApplicationError::EncodingError(
+            "Carrier image has no alpha channel".to_string(),
+        ));
+    }
+
+    progress.update("Loading carrier image...");
+    let (image, high_bytes) = timed(progress, Phase::Load, "Loading carrier image", || {
+        if core::image::is_lossless(carrier_path)? {
+            core::image::load_carrier_keeping_depth(carrier_path)
+        } else {
+            progress.warn("Carrier image is lossy. Converting to lossless format...");
+            Ok((core::image::convert_to_lossless(carrier_path)?, None))
+        }
+    })?;
+    let (width, height) = image.dimensions();
+    info!("Carrier {} is {}x{}", carrier_path.display(), width, height);
+    if let Carrier::Gray(_) = image {
+        info!("Carrier is grayscale; embedding in its luma channel");
+    }
+    if high_bytes.is_some() {
+        info!("Carrier has 16 bits per channel; embedding in their low bytes");
+    }
+    let metadata = if options.keep_metadata {
+        core::metadata::read_png_metadata(carrier_path)?
+    } else {
+        Vec::new()
+    };
+
+    Ok((
+        image,
+        Preserved {
+            metadata,
+            high_bytes,
+        },
+    ))
+}
+
+/// Whether the carrier bits the payload leaves unused get randomized, see [`UnusedBits`]
+fn randomizes_unused<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    options: &EncodeOptions,
+) -> bool {
+    match options.unused_bits {
+        UnusedBits::Auto => {
+            Header::parse(&lsb::read_header_bytes(image, &options.layout)).is_some()
+        }
+        UnusedBits::Preserve => false,
+        UnusedBits::Randomize => true,
+    }
+}
+
+/// Embeds a prepared payload with its header, randomizing the unused bits if asked to
+fn embed_payload<P: Pixel<Subpixel = u8>>(
+    data: &str,
+    image: &mut ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+    info: PayloadInfo,
+    randomize_unused: bool,
+) -> Result<(), ApplicationError> {
+    let header = lsb::encode(data, image, layout, info)?;
+    if randomize_unused {
+        lsb::randomize_unused(image, layout, &header);
+    }
     Ok(())
 }
 
+/// Saves an encoded carrier at its original bit depth, stripped of metadata unless
+/// `keep_metadata` is set
+fn save_encoded<P: CarrierPixel>(
+    image: ImageBuffer<P, Vec<u8>>,
+    output_path: &Path,
+    preserved: &Preserved,
+    options: &EncodeOptions,
+    progress: &impl Progress,
+) -> Result<(), ApplicationError> {
+    if options.dry_run {
+        info!("Dry run: not saving {}", output_path.display());
+        return Ok(());
+    }
+    core::file::prepare_output(output_path, options.existing_output)?;
+    timed(progress, Phase::Save, "Saving encoded image", || {
+        P::save(
+            image,
+            output_path,
+            options.keep_metadata.then_some(&preserved.metadata),
+            preserved.high_bytes.as_ref(),
+            options.layout.use_alpha,
+        )
+    })
+}
+
+/// Pixel types of a loaded [`Carrier`]
+trait CarrierPixel: Pixel<Subpixel = u8> + PixelWithColorType {
+    /// Saves an encoded carrier in the color type it is stored as, with 16 bits per channel
+    /// when it has `high_bytes`
+    fn save(
+        image: ImageBuffer<Self, Vec<u8>>,
+        output_path: &Path,
+        metadata: Option<&Vec<core::metadata::Chunk>>,
+        high_bytes: Option<&HighBytes>,
+        use_alpha: bool,
+    ) -> Result<(), ApplicationError>;
+}
+
+impl CarrierPixel for Luma<u8> {
+    fn save(
+        image: GrayImage,
+        output_path: &Path,
+        metadata: Option<&Vec<core::metadata::Chunk>>,
+        high_bytes: Option<&HighBytes>,
+        _use_alpha: bool,
+    ) -> Result<(), ApplicationError> {
+        match high_bytes {
+            Some(high_bytes) => save_image(
+                &core::image::join_high_bytes::<_, Luma<u16>>(&image, high_bytes),
+                output_path,
+                metadata,
+            ),
+            None => save_image(&image, output_path, metadata),
+        }
+    }
+}
+
+impl CarrierPixel for Rgba<u8> {
+    fn save(
+        image: RgbaImage,
+        output_path: &Path,
+        metadata: Option<&Vec<core::metadata::Chunk>>,
+        high_bytes: Option<&HighBytes>,
+        use_alpha: bool,
+    ) -> Result<(), ApplicationError> {
+        // The alpha channel is only kept when it carries payload bits
+        match (high_bytes, use_alpha) {
+            (Some(high_bytes), true) => save_image(
+                &core::image::join_high_bytes::<_, Rgba<u16>>(&image, high_bytes),
+                output_path,
+                metadata,
+            ),
+            (Some(high_bytes), false) => save_image(
+                &DynamicImage::ImageRgba16(core::image::join_high_bytes(&image, high_bytes))
+                    .into_rgb16(),
+                output_path,
+                metadata,
+            ),
+            (None, true) => save_image(&image, output_path, metadata),
+            (None, false) => save_image(
+                &DynamicImage::ImageRgba8(image).into_rgb8(),
+                output_path,
+                metadata,
+            ),
+        }
+    }
+}
+
+/// Whether a prepared payload was compressed, rather than stored because compression didn't help
+fn is_compressed(payload: &str) -> bool {
+    payload.starts_with(core::compression::MARKER)
+        && !payload.starts_with(&core::compression::stored_marker())
+}
+
+/// Outcome of encoding the message into one carrier of a batch
+pub struct BatchEntry {
+    pub carrier_path: PathBuf,
+    pub result: Result<EncodeSummary, ApplicationError>,
+}
+
+impl BatchEntry {
+    /// Whether the carrier was skipped for being too small, rather than failing
+    pub fn is_skipped(&self) -> bool {
+        matches!(
+            self.result,
+            Err(ApplicationError::InsufficientCapacity { .. })
+        )
+    }
+}
+
+/// Encodes the same message into every image in `carrier_dir`, in parallel
+///
+/// Each encoded image is saved as `<stem>-encoded.png` in `output_dir`, which is created if
+/// needed, with a numbered suffix when stems collide (see [`batch_output_paths`]). Carriers too small for the message are skipped with a warning, and other failures
+/// are recorded in their entry without stopping the rest of the batch
+pub fn batch_encode(
+    data_path: &str,
+    carrier_dir: &str,
+    output_dir: &str,
+    options: &EncodeOptions,
+    progress: &(impl Progress + Sync),
+) -> Result<Vec<BatchEntry>, ApplicationError> {
+    let carriers = core::image::list_images(carrier_dir)?;
+    if carriers.is_empty() {
+        return Err(ApplicationError::InvalidPathError(format!(
+            "No images found in '{}'",
+            carrier_dir
+        )));
+    }
+
+    progress.update("Reading data file...");
+    let data = read_data(data_path, options)?;
+    if !options.dry_run {
+        fs::create_dir_all(output_dir)?;
+    }
+
+    progress.update(&format!("Encoding into {} carriers...", carriers.len()));
+    let output_paths = batch_output_paths(&carriers, output_dir);
+    let entries: Vec<BatchEntry> = carriers
+        .par_iter()
+        .zip(&output_paths)
+        .map(|(carrier_path, output_path)| {
+            let carrier_progress = CarrierProgress {
+                carrier_path,
+                progress,
+            };
+            let result = encode_with(
+                || Ok(data.clone()),
+                carrier_path,
+                output_path,
+                options,
+                &carrier_progress,
+            );
+            let entry = BatchEntry {
+                carrier_path: carrier_path.clone(),
+                result,
+            };
+            if let (true, Err(e)) = (entry.is_skipped(), &entry.result) {
+                progress.warn(&format!("Skipping {}: {}", carrier_path.display(), e));
+            }
+            entry
+        })
+        .collect();
+
+    let encoded = entries.iter().filter(|entry| entry.result.is_ok()).count();
+    progress.finish_with_message(&format!(
+        "Encoded {} of {} carriers",
+        encoded,
+        entries.len()
+    ));
+
+    Ok(entries)
+}
+
+/// Where a batch saves the encoded copy of each of `carriers`: `<stem>-encoded.png` in
+/// `output_dir`
+///
+/// Carriers sharing a stem, like `photo.png` and `photo.jpg`, would write to the same file,
+/// so all but the first get `-1`, `-2`, ... after the stem, skipping names another carrier
+/// already has
+fn batch_output_paths(carriers: &[PathBuf], output_dir: &str) -> Vec<PathBuf> {
+    let name = |carrier_path: &Path, suffix: Option<usize>| {
+        let mut name = OsString::from(carrier_path.file_stem().unwrap_or_default());
+        if let Some(suffix) = suffix {
+            name.push(format!("-{}", suffix));
+        }
+        name.push("-encoded.png");
+        name
+    };
+
+    let mut taken: HashSet<OsString> = carriers.iter().map(|path| name(path, None)).collect();
+    let mut assigned = HashSet::new();
+    carriers
+        .iter()
+        .map(|carrier_path| {
+            let plain = name(carrier_path, None);
+            let name = if assigned.insert(plain.clone()) {
+                plain
+            } else {
+                (1..)
+                    .map(|suffix| name(carrier_path, Some(suffix)))
+                    .find(|name| taken.insert(name.clone()))
+                    .unwrap()
+            };
+            Path::new(output_dir).join(name)
+        })
+        .collect()
+}
+
+/// Progress of one carrier in a batch
+///
+/// Carriers are encoded in parallel, so their phases would only garble the shared
+/// progress; warnings are passed on naming the carrier, and completion as an update
+struct CarrierProgress<'a, P> {
+    carrier_path: &'a Path,
+    progress: &'a P,
+}
+
+impl<P: Progress> Progress for CarrierProgress<'_, P> {
+    fn update(&self, _message: &str) {}
+
+    fn warn(&self, message: &str) {
+        self.progress
+            .warn(&format!("{}: {}", self.carrier_path.display(), message));
+    }
+
+    fn finish_with_message(&self, _message: &str) {
+        self.progress
+            .update(&format!("Encoded {}", self.carrier_path.display()));
+    }
+
+    fn timing(&self, phase: Phase, elapsed: Duration) {
+        self.progress.timing(phase, elapsed);
+    }
+}
+
 /// Decodes a message from an image using LSB steganography
 ///
-/// 1. Loads the carrier image containing the hidden message
-/// 2. Extracts the message using LSB steganography
-/// 3. Optionally decrypts the message using the provided key
-/// 4. Optionally decompresses the message
-/// 5. Saves the decoded message to the specified output path
+/// 1. Extracts the message from the carrier image (see [`decode_message`]). A raw LSB
+///    message is kept as the bytes extracted, whether or not they are text
+/// 2. Saves the decoded message to the specified output path, or writes it to
+///    stdout when `output_path` is `-`. A bundle of files (see [`core::bundle`]) is
+///    unpacked into `output_path` as a directory instead
+///
+/// Returns the size of the decoded message in bytes
 pub fn decode(
     carrier_path: &str,
     output_path: &str,
-    key: Option<String>,
-    decompress: bool,
+    options: &DecodeOptions,
     progress: &impl Progress,
-) -> Result<(), ApplicationError> {
+) -> Result<usize, ApplicationError> {
+    let decoded_message = match options.raw_lsb {
+        Some(_) => decode_message_bytes(carrier_path, options, progress)?,
+        None => decode_message(carrier_path, options, progress)?.into_bytes(),
+    };
+
+    if options.raw_lsb.is_none() && decoded_message.starts_with(core::bundle::MARKER.as_bytes()) {
+        if output_path == core::file::STDIO_SENTINEL {
+            return Err(ApplicationError::InvalidPathError(
+                "The message bundles several files; decode it to a directory".to_string(),
+            ));
+        }
+        let entries = core::bundle::unpack(&String::from_utf8_lossy(&decoded_message))?;
+        info!("Message bundles {} files", entries.len());
+        if options.dry_run {
+            info!("Dry run: not writing {}", output_path);
+        } else {
+            progress.update("Unpacking bundled files...");
+            timed(progress, Phase::Save, "Unpacking bundled files", || {
+                core::bundle::write(&entries, output_path, options.existing_output)
+            })?;
+        }
+    } else if options.dry_run {
+        info!("Dry run: not writing {}", output_path);
+    } else {
+        progress.update("Saving decoded message...");
+        core::file::prepare_output(output_path, options.existing_output)?;
+        timed(progress, Phase::Save, "Saving decoded message", || {
+            core::file::write_bytes_or_stdout(&decoded_message, output_path)
+        })?;
+    }
+
+    progress.finish_with_message(&format!(
+        "{} => {}",
+        completed("Decoding", options.dry_run),
+        output_path
+    ));
+
+    Ok(decoded_message.len())
+}
+
+/// Extracts a message from an image using LSB steganography without saving it
+///
+/// 1. Loads the carrier image containing the hidden message
+/// 2. Extracts the message using LSB steganography following the options' layout,
+///    reading exactly `headerless_length` bytes when the message has no header
+/// 3. Optionally decompresses and decrypts the message (see [`recover_message`])
+/// 4. If that fails and a key was given, retries each keyed decoy slot (see [`Decoy`])
+///
+/// With `join` carriers, the message is reassembled from its shards instead (see
+/// [`encode_split`]). GIF carriers are read across all of their frames (see [`gif`]).
+/// With `raw_lsb`, steps 3 and 4 are skipped
+pub fn decode_message(
+    carrier_path: &str,
+    options: &DecodeOptions,
+    progress: &impl Progress,
+) -> Result<String, ApplicationError> {
+    let message = decode_message_bytes(carrier_path, options, progress)?;
+    payload_text(message, options, progress)
+}
+
+/// [`decode_message`] without requiring the message to be text
+///
+/// A payload that is neither encrypted nor compressed is returned byte for byte
+pub fn decode_message_bytes(
+    carrier_path: &str,
+    options: &DecodeOptions,
+    progress: &impl Progress,
+) -> Result<Vec<u8>, ApplicationError> {
+    check_raw_lsb(options)?;
+    if !options.join.is_empty() {
+        return decode_joined_message(carrier_path, options, progress);
+    }
+    if core::image::is_gif(carrier_path) {
+        return decode_gif(carrier_path, options, progress);
+    }
+    match load_encoded(carrier_path, progress)? {
+        Carrier::Gray(image) => decode_loaded(&image, options, progress),
+        Carrier::Color(image) => decode_loaded(&image, options, progress),
+    }
+}
+
+/// [`decode_message_bytes`] from a loaded carrier, which [`decode_buffer`] also goes through
+fn decode_loaded<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    options: &DecodeOptions,
+    progress: &impl Progress,
+) -> Result<Vec<u8>, ApplicationError> {
+    if let Some(framing) = &options.raw_lsb {
+        return extract_raw_message(image, options, framing, progress);
+    }
+    let result = extract_message(image, options, &options.layout, progress);
+    match options.key.as_deref() {
+        Some(key) if result.is_err() && options.headerless_length.is_none() => (0..DECOY_SLOTS)
+            .map(|slot| keyed_slot_layout(&options.layout, key, slot))
+            .find_map(|layout| extract_message(image, options, &layout, progress).ok())
+            .map_or(result, Ok),
+        _ => result,
+    }
+}
+
+/// Extracts the message hidden in `carrier` without touching the disk, the in-memory
+/// counterpart of [`decode_message`]
+///
+/// Split messages need the other shards' carriers, so `join` must be empty
+pub fn decode_buffer<P: Pixel<Subpixel = u8>>(
+    carrier: &ImageBuffer<P, Vec<u8>>,
+    options: &DecodeOptions,
+) -> Result<Vec<u8>, ApplicationError> {
+    if !options.join.is_empty() {
+        return Err(ApplicationError::DecodingError(
+            "A split message cannot be joined in memory".to_string(),
+        ));
+    }
+    check_raw_lsb(options)?;
+    decode_loaded(carrier, options, &SilentProgress)
+}
+
+/// [`decode_message_bytes`] from the frames of a GIF carrier
+fn decode_gif(
+    carrier_path: &str,
+    options: &DecodeOptions,
+    progress: &impl Progress,
+) -> Result<Vec<u8>, ApplicationError> {
+    if options.encryption.bind_carrier {
+        return Err(ApplicationError::DecodingError(
+            "A GIF carrier cannot be bound".to_string(),
+        ));
+    }
+    progress.update("Loading carrier image...");
+    let animation = timed(progress, Phase::Load, "Loading carrier image", || {
+        core::image::load_gif(carrier_path)
+    })?;
+    info!(
+        "Carrier {} is a {}x{} GIF with {} frame(s)",
+        carrier_path,
+        animation.width,
+        animation.height,
+        animation.frames.len()
+    );
+    decode_loaded(&gif::carrier_indices(&animation), options, progress)
+}
+
+/// Reassembles a split message from the shards in `carrier_path` and the `join` carriers
+fn decode_joined_message(
+    carrier_path: &str,
+    options: &DecodeOptions,
+    progress: &impl Progress,
+) -> Result<Vec<u8>, ApplicationError> {
+    if options.encryption.bind_carrier {
+        return Err(ApplicationError::DecodingError(
+            "A split message cannot be bound to a single carrier".to_string(),
+        ));
+    }
+    let shards = std::iter::once(carrier_path)
+        .chain(options.join.iter().map(String::as_str))
+        .map(|path| {
+            let (shard, header) = match load_encoded(path, progress)? {
+                Carrier::Gray(image) => extract_payload(&image, options, &options.layout, progress),
+                Carrier::Color(image) => {
+                    extract_payload(&image, options, &options.layout, progress)
+                }
+            }?;
+            let shard = payload_text(shard, options, progress)?;
+            if !shard.starts_with(core::shard::MARKER) {
+                return Err(ApplicationError::DecodingError(format!(
+                    "{} does not hold a shard of a split message",
+                    path
+                )));
+            }
+            Ok((shard, header))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let (shards, headers): (Vec<_>, Vec<_>) = shards.into_iter().unzip();
+    let payload = core::shard::join(&shards)?;
+    info!(
+        "Joined {} shards into a {} byte payload",
+        shards.len(),
+        payload.len()
+    );
+
+    check_encrypted(headers[0].as_ref(), options.key.as_deref())?;
+    let message = recover_message(
+        payload,
+        options.key.as_deref(),
+        &options.encryption,
+        options.decompress,
+        &[],
+        progress,
+    )?;
+    check_integrity(message.as_bytes(), headers[0].as_ref(), options, progress)?;
+    Ok(message.into_bytes())
+}
+
+/// Loads a carrier holding a hidden message
+fn load_encoded(carrier_path: &str, progress: &impl Progress) -> Result<Carrier, ApplicationError> {
     progress.update("Loading carrier image...");
-    let image = core::image::load_image(&carrier_path)?;
+    let image = timed(progress, Phase::Load, "Loading carrier image", || {
+        core::image::load_carrier(carrier_path)
+    })?;
+    let (width, height) = image.dimensions();
+    info!("Carrier {} is {}x{}", carrier_path, width, height);
+    Ok(image)
+}
+
+fn extract_message<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    options: &DecodeOptions,
+    layout: &Layout,
+    progress: &impl Progress,
+) -> Result<Vec<u8>, ApplicationError> {
+    let (payload, header) = extract_payload(image, options, layout, progress)?;
+    if payload.starts_with(core::shard::MARKER.as_bytes()) {
+        return Err(ApplicationError::DecodingError(
+            "The carrier holds one shard of a split message; pass the others with --join"
+                .to_string(),
+        ));
+    }
+    check_encrypted(header.as_ref(), options.key.as_deref())?;
+
+    // Only encrypted or compressed payloads are text; a plain one is the message itself
+    let message = if options.key.is_none()
+        && !options.decompress
+        && !payload.starts_with(core::compression::MARKER.as_bytes())
+    {
+        payload
+    } else {
+        recover_message(
+            payload_text(payload, options, progress)?,
+            options.key.as_deref(),
+            &options.encryption,
+            options.decompress,
+            &carrier_aad(image.dimensions(), &options.encryption),
+            progress,
+        )?
+        .into_bytes()
+    };
+    check_integrity(&message, header.as_ref(), options, progress)?;
+    Ok(message)
+}
 
+/// Extracts the raw payload, before it is decompressed or decrypted, with its header unless
+/// it is headerless or legacy
+fn extract_payload<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    options: &DecodeOptions,
+    layout: &Layout,
+    progress: &impl Progress,
+) -> Result<(Vec<u8>, Option<Header>), ApplicationError> {
     progress.update("Decoding data from image...");
-    let mut decoded_message = lsb::decode(&image)?;
+    let (bytes, header) =
+        timed(
+            progress,
+            Phase::Extract,
+            "Decoding data from image",
+            || match options.headerless_length {
+                Some(length) => {
+                    lsb::decode_headerless(image, length, layout).map(|bytes| (bytes, None))
+                }
+                None if options.legacy_delimiter => {
+                    lsb::decode_legacy_delimited(image, layout).map(|bytes| (bytes, None))
+                }
+                None => lsb::decode_with_header(image, layout),
+            },
+        )?;
+    info!("Extracted a {} byte payload", bytes.len());
 
-    if let Some(key) = key {
-        progress.update("Decrypting data...");
-        let key_bytes = key_to_bytes(&key)?;
-        decoded_message = aes::decrypt(&decoded_message, &key_bytes)?;
+    Ok((bytes, header))
+}
+
+/// Extracted bytes as text, replacing invalid UTF-8 with a warning when `lossy_utf8` is set
+fn payload_text(
+    bytes: Vec<u8>,
+    options: &DecodeOptions,
+    progress: &impl Progress,
+) -> Result<String, ApplicationError> {
+    if !options.lossy_utf8 {
+        return lsb::bytes_to_string(bytes);
+    }
+    let (text, replaced) = lsb::bytes_to_string_lossy(bytes);
+    if replaced > 0 {
+        progress.warn(&format!(
+            "Replaced {} byte(s) of invalid UTF-8 with U+FFFD",
+            replaced
+        ));
+    }
+    Ok(text)
+}
+
+/// Rejects options that only apply to mindbender's framing when reading a raw LSB message
+fn check_raw_lsb(options: &DecodeOptions) -> Result<(), ApplicationError> {
+    if options.raw_lsb.is_some()
+        && (options.key.is_some()
+            || options.decompress
+            || options.headerless_length.is_some()
+            || options.legacy_delimiter
+            || !options.join.is_empty())
+    {
+        return Err(ApplicationError::DecodingError(
+            "A raw LSB message cannot be decrypted, decompressed, headerless, legacy or joined"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Extracts a message embedded by another LSB tool, see [`lsb::decode_raw`]
+fn extract_raw_message<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    options: &DecodeOptions,
+    framing: &RawFraming,
+    progress: &impl Progress,
+) -> Result<Vec<u8>, ApplicationError> {
+    progress.update("Decoding data from image...");
+    let bytes = timed(progress, Phase::Extract, "Decoding data from image", || {
+        lsb::decode_raw(image, &options.layout, framing)
+    })?;
+    info!("Extracted a {} byte raw LSB message", bytes.len());
+
+    Ok(bytes)
+}
+
+/// Fails before decrypting when the header records that the payload is encrypted but no
+/// key was given, or that it is not encrypted but a key was
+///
+/// Payloads whose header doesn't record it, or that have no header, are left to decryption
+pub fn check_encrypted(
+    header: Option<&Header>,
+    key: Option<&[u8]>,
+) -> Result<(), ApplicationError> {
+    match (header.and_then(|header| header.is_encrypted()), key) {
+        (Some(true), None) => Err(ApplicationError::DecryptionError(
+            "this message is encrypted; provide --key".to_string(),
+        )),
+        (Some(false), Some(_)) => Err(ApplicationError::DecryptionError(
+            "this message is not encrypted; decode without --key".to_string(),
+        )),
+        _ => Ok(()),
     }
+}
+
+/// Verifies a recovered message against the checksum from its header, if it has one
+///
+/// With `lossy_utf8` a damaged message is still wanted, so a mismatch only warns
+fn check_integrity(
+    message: &[u8],
+    header: Option<&Header>,
+    options: &DecodeOptions,
+    progress: &impl Progress,
+) -> Result<(), ApplicationError> {
+    let Some(checksum) = header.and_then(|header| header.checksum.as_ref()) else {
+        return Ok(());
+    };
+    match core::integrity::verify(message, checksum) {
+        Err(_) if options.lossy_utf8 => {
+            progress.warn("Message does not match its checksum and may be damaged");
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Facts about a carrier image, see [`inspect`]
+#[derive(Debug)]
+pub struct ImageInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Format named by the file's extension
+    pub format: ImageFormat,
+    pub is_lossless: bool,
+    /// Bytes the default layout can embed, header and checksum included
+    pub capacity_bytes: usize,
+    /// Whether the carrier has a valid header announcing a payload that can be extracted
+    pub has_payload: bool,
+}
+
+/// Reports a carrier's dimensions, format and capacity, and whether it appears to hold an
+/// embedded message
+///
+/// Only the default layout is checked for a payload, and the payload is not decrypted or
+/// decompressed
+pub fn inspect(carrier_path: &str) -> Result<ImageInfo, ApplicationError> {
+    let format = ImageFormat::from_path(carrier_path)
+        .map_err(|_| ApplicationError::InvalidPathError("Unsupported image format".to_string()))?;
+    let is_lossless = core::image::is_lossless(carrier_path)?;
+    let image = core::image::load_carrier(carrier_path)?;
+    let (width, height) = image.dimensions();
+    let (capacity_bytes, has_payload) = match &image {
+        Carrier::Gray(image) => inspect_pixels(image),
+        Carrier::Color(image) => inspect_pixels(image),
+    };
+
+    Ok(ImageInfo {
+        width,
+        height,
+        format,
+        is_lossless,
+        capacity_bytes,
+        has_payload,
+    })
+}
+
+/// Measures how many bytes `carrier_path` can hide, see [`Capacity`]
+///
+/// The carrier is loaded as an encode would load it, so a grayscale image holds a
+/// third of what a color image of the same size does
+pub fn capacity(carrier_path: &str) -> Result<Capacity, ApplicationError> {
+    Ok(match core::image::load_carrier(carrier_path)? {
+        Carrier::Gray(image) => carrier_capacity(&image),
+        Carrier::Color(image) => carrier_capacity(&image),
+    })
+}
+
+/// [`Capacity`] of a loaded carrier
+fn carrier_capacity<P: Pixel<Subpixel = u8>>(image: &ImageBuffer<P, Vec<u8>>) -> Capacity {
+    let layout = Layout::default();
+    let header_bytes = HEADER_SIZE + CHECKSUM_SIZE;
+    let usable_bytes = payload_capacity_bits(image, &layout, header_bytes) / BITS_PER_BYTE;
+
+    Capacity {
+        raw_bytes: image_capacity_bytes(image, &layout, header_bytes),
+        header_bytes,
+        usable_bytes,
+        // An encrypted payload's header has no checksum
+        encrypted_bytes: cipher::max_plaintext_len(
+            payload_capacity_bits(image, &layout, HEADER_SIZE) / BITS_PER_BYTE,
+            false,
+            KdfCost::default(),
+        ),
+    }
+}
+
+/// Capacity of a loaded carrier in bytes with the default layout, and whether it holds a payload
+fn inspect_pixels<P: Pixel<Subpixel = u8>>(image: &ImageBuffer<P, Vec<u8>>) -> (usize, bool) {
+    let layout = Layout::default();
+    let has_payload = matches!(
+        Header::parse(&lsb::read_header_bytes(image, &layout)),
+        Some(Ok(_))
+    ) && lsb::decode(image, &layout).is_ok();
+
+    (
+        image_capacity_bytes(image, &layout, MAX_HEADER_SIZE),
+        has_payload,
+    )
+}
+
+/// Saves an encoded image, writing the given PNG metadata chunks when there are any to keep
+fn save_image<P>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    output_path: &Path,
+    metadata: Option<&Vec<core::metadata::Chunk>>,
+) -> Result<(), ApplicationError>
+where
+    P: PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+    P::Subpixel: core::metadata::PngSample,
+{
+    match metadata {
+        Some(metadata) => core::metadata::write_png_with_metadata(image, output_path, metadata),
+        None => core::image::write_image_file(image, output_path).map(|_| ()),
+    }
+}
+
+/// Message announcing that `operation` finished, noting when it was a dry run
+fn completed(operation: &str, dry_run: bool) -> String {
+    if dry_run {
+        format!("{} dry run completed, nothing written", operation)
+    } else {
+        format!("{} completed successfully", operation)
+    }
+}
+
+/// Runs one step of an operation, logging how long it took and recording it as `phase`
+fn timed<T>(progress: &impl Progress, phase: Phase, step: &str, run: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = run();
+    let elapsed = started.elapsed();
+    debug!("{} took {:?}", step, elapsed);
+    progress.timing(phase, elapsed);
+    result
+}
+
+/// Layout for one of the decoy slots, shuffled by a seed derived from `key`
+fn keyed_slot_layout(base: &Layout, key: &[u8], slot: usize) -> Layout {
+    Layout {
+        slot: Some((slot, DECOY_SLOTS)),
+        seed: Some(derive_seed(key)),
+        ..base.clone()
+    }
+}
+
+/// Associated data binding an encrypted payload to the carrier: its width and height as
+/// big-endian `u32`s when [`EncryptionOptions::bind_carrier`] is set, otherwise nothing
+fn carrier_aad((width, height): (u32, u32), encryption: &EncryptionOptions) -> Vec<u8> {
+    if !encryption.bind_carrier {
+        return Vec::new();
+    }
+    [width.to_be_bytes(), height.to_be_bytes()].concat()
+}
+
+/// Prepares a message for embedding
+///
+/// 1. Optionally encrypts the message using the provided key, authenticating `aad`
+///    with it
+/// 2. Optionally compresses the message, marking it with a `COMPRESSED:` prefix
+///    that records the algorithm and level
+pub fn prepare_payload(
+    message: String,
+    key: Option<&[u8]>,
+    encryption: &EncryptionOptions,
+    compression: Option<&CompressionOptions>,
+    aad: &[u8],
+    progress: &impl Progress,
+) -> Result<String, ApplicationError> {
+    let mut data = if let Some(key) = key {
+        progress.update("Encrypting data...");
+        let encrypted = timed(progress, Phase::Encrypt, "Encrypting data", || {
+            cipher::encrypt(
+                &message,
+                key,
+                encryption.cipher.unwrap_or_default(),
+                encryption.raw_key,
+                encryption.kdf_cost,
+                aad,
+            )
+        })?;
+        info!(
+            "Encrypted {} bytes into {} bytes",
+            message.len(),
+            encrypted.len()
+        );
+        encrypted
+    } else {
+        message
+    };
+
+    if let Some(compression) = compression {
+        progress.update("Compressing data...");
+        let compressed_data = timed(progress, Phase::Compress, "Compressing data", || {
+            core::compression::compress(data.as_bytes(), compression.algorithm, compression.level)
+        })?;
+        // Add a marker to indicate compression
+        let compressed = format!(
+            "{}{}",
+            core::compression::marker(compression.algorithm, compression.level),
+            BASE64_ENGINE.encode(&compressed_data)
+        );
+        let ratio = compressed.len() as f64 / data.len().max(1) as f64;
+        info!(
+            "Compressed {} bytes into {} bytes ({:.0}% of the original)",
+            data.len(),
+            compressed.len(),
+            ratio * 100.0
+        );
+        // Base64 and the marker can outgrow what compression saves on small inputs,
+        // so store those as is, still marked so decoding with --decompress works
+        let stored = format!("{}{}", core::compression::stored_marker(), data);
+        data = if compressed.len() < stored.len() {
+            compressed
+        } else {
+            progress.warn(&format!(
+                "Compression would grow the message to {:.0}% of its size; storing it uncompressed",
+                ratio * 100.0
+            ));
+            stored
+        };
+    }
+
+    Ok(data)
+}
+
+/// Recovers the original message from an extracted payload, undoing [`prepare_payload`]
+///
+/// 1. Optionally decompresses the payload
+/// 2. Optionally decrypts the payload using the provided key, which fails unless `aad`
+///    matches the associated data it was encrypted with
+pub fn recover_message(
+    payload: String,
+    key: Option<&[u8]>,
+    encryption: &EncryptionOptions,
+    decompress: bool,
+    aad: &[u8],
+    progress: &impl Progress,
+) -> Result<String, ApplicationError> {
+    let mut message = payload;
 
     if decompress {
         progress.update("Decompressing data...");
-        if !decoded_message.starts_with("COMPRESSED:") {
+        if !message.starts_with(core::compression::MARKER) {
             return Err(ApplicationError::DecodingError(
                 "Decompression expected, but message is not compressed".to_string(),
             ));
         }
-        let base64_data = &decoded_message["COMPRESSED:".len()..];
-        let compressed_data = BASE64_ENGINE
-            .decode(base64_data)
-            .map_err(|_| ApplicationError::DecodingError("Base64 decoding failed".to_string()))?;
-        decoded_message = String::from_utf8(core::compression::decompress(&compressed_data)?)
-            .map_err(|e| {
-                ApplicationError::DecodingError(format!("UTF-8 decoding failed: {}", e))
-            })?;
-    } else if decoded_message.starts_with("COMPRESSED:") {
+        message = match core::compression::parse_marker(&message)? {
+            (Some(algorithm), base64_data) => {
+                let compressed_data = BASE64_ENGINE.decode(base64_data).map_err(|_| {
+                    ApplicationError::DecodingError("Base64 decoding failed".to_string())
+                })?;
+                let decompressed =
+                    timed(progress, Phase::Decompress, "Decompressing data", || {
+                        core::compression::decompress(&compressed_data, algorithm)
+                    })?;
+                info!(
+                    "Decompressed {} bytes into {} bytes",
+                    message.len(),
+                    decompressed.len()
+                );
+                String::from_utf8(decompressed).map_err(|e| {
+                    ApplicationError::DecodingError(format!("UTF-8 decoding failed: {}", e))
+                })?
+            }
+            (None, stored) => {
+                info!("Message was stored without compression");
+                stored.to_string()
+            }
+        };
+    } else if message.starts_with(core::compression::MARKER) {
         return Err(ApplicationError::DecodingError(
             "Data is compressed but decompression was not requested".to_string(),
         ));
     }
 
-    progress.update("Saving decoded message...");
-    core::file::write_text(&decoded_message, &output_path)?;
+    if let Some(key) = key {
+        progress.update("Decrypting data...");
+        message = timed(progress, Phase::Decrypt, "Decrypting data", || {
+            cipher::decrypt(&message, key, encryption.cipher, encryption.raw_key, aad)
+        })?;
+        info!("Decrypted message is {} bytes", message.len());
+    }
+
+    Ok(message)
+}
 
-    progress.finish_with_message(&format!(
-        "Decoding completed successfully => {}",
-        output_path
-    ));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
 
-    Ok(())
+    /// Progress sink remembering the warnings it was given
+    #[derive(Default)]
+    struct RecordingProgress {
+        warnings: RefCell<Vec<String>>,
+    }
+
+    impl Progress for RecordingProgress {
+        fn update(&self, _message: &str) {}
+
+        fn warn(&self, message: &str) {
+            self.warnings.borrow_mut().push(message.to_string());
+        }
+
+        fn finish_with_message(&self, _message: &str) {}
+    }
+
+    fn round_trip(message: &str) -> (String, Vec<String>) {
+        let progress = RecordingProgress::default();
+        let compression = CompressionOptions::default();
+        let encryption = EncryptionOptions::default();
+        let payload = prepare_payload(
+            message.to_string(),
+            None,
+            &encryption,
+            Some(&compression),
+            &[],
+            &progress,
+        )
+        .unwrap();
+        let recovered = recover_message(payload.clone(), None, &encryption, true, &[], &progress);
+
+        assert_eq!(recovered.unwrap(), message);
+        (payload, progress.warnings.into_inner())
+    }
+
+    #[test]
+    fn test_compression_skipped_for_tiny_input() {
+        let (payload, warnings) = round_trip("Hello");
+
+        assert_eq!(
+            payload,
+            format!("{}Hello", core::compression::stored_marker())
+        );
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_compression_applied_to_repetitive_input() {
+        let message = "all work and no play ".repeat(100);
+        let (payload, warnings) = round_trip(&message);
+
+        assert!(payload.starts_with(&core::compression::marker(Algorithm::Zlib, None)));
+        assert!(payload.len() < message.len() / 4);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_stored_payload_requires_decompress_flag() {
+        let payload = format!("{}Hello", core::compression::stored_marker());
+        let result = recover_message(
+            payload,
+            None,
+            &EncryptionOptions::default(),
+            false,
+            &[],
+            &RecordingProgress::default(),
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_inspect_png_with_and_without_payload() {
+        let dir = tempfile::tempdir().unwrap();
+        let carrier = dir.path().join("carrier.png");
+        let encoded = dir.path().join("encoded.png");
+        let mut image = RgbaImage::from_pixel(40, 30, image::Rgba([100, 150, 200, 255]));
+        image.save(&carrier).unwrap();
+        lsb::encode(
+            "Hello",
+            &mut image,
+            &Layout::default(),
+            PayloadInfo::default(),
+        )
+        .unwrap();
+        image.save(&encoded).unwrap();
+
+        let info = inspect(carrier.to_str().unwrap()).unwrap();
+        assert_eq!((info.width, info.height), (40, 30));
+        assert_eq!(info.format, ImageFormat::Png);
+        assert!(info.is_lossless);
+        assert_eq!(
+            info.capacity_bytes,
+            image_capacity_bytes(&image, &Layout::default(), MAX_HEADER_SIZE)
+        );
+        assert!(!info.has_payload);
+        assert!(inspect(encoded.to_str().unwrap()).unwrap().has_payload);
+    }
+
+    #[test]
+    fn test_inspect_jpeg() {
+        let dir = tempfile::tempdir().unwrap();
+        let carrier = dir.path().join("carrier.jpg");
+        image::RgbImage::from_pixel(16, 8, image::Rgb([100, 150, 200]))
+            .save(&carrier)
+            .unwrap();
+
+        let info = inspect(carrier.to_str().unwrap()).unwrap();
+        assert_eq!((info.width, info.height), (16, 8));
+        assert_eq!(info.format, ImageFormat::Jpeg);
+        assert!(!info.is_lossless);
+        assert!(!info.has_payload);
+    }
+
+    #[test]
+    fn test_encode_decode_buffer_in_memory() {
+        let carrier = image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8])
+        });
+        let message = "Never touches the disk. ".repeat(8);
+        let encode_options = EncodeOptions {
+            key: Some(b"in-memory key".to_vec()),
+            compression: Some(CompressionOptions::default()),
+            layout: Layout {
+                depth: 2,
+                ..Layout::default()
+            },
+            ..EncodeOptions::default()
+        };
+
+        let encoded = encode_buffer(message.as_bytes(), &carrier, &encode_options).unwrap();
+        assert_eq!(encoded.dimensions(), carrier.dimensions());
+        assert_ne!(encoded, carrier);
+
+        let decode_options = DecodeOptions {
+            key: encode_options.key.clone(),
+            decompress: true,
+            ..DecodeOptions::default()
+        };
+        assert_eq!(
+            decode_buffer(&encoded, &decode_options).unwrap(),
+            message.as_bytes()
+        );
+        assert!(decode_buffer(&encoded, &DecodeOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_encode_buffer_rejects_decoy_and_invalid_utf8() {
+        let carrier = image::RgbImage::new(64, 64);
+        let decoy = EncodeOptions {
+            key: Some(b"real".to_vec()),
+            decoy: Some(Decoy {
+                data_path: "decoy.txt".to_string(),
+                key: b"duress".to_vec(),
+            }),
+            ..EncodeOptions::default()
+        };
+
+        assert!(encode_buffer(b"Hello", &carrier, &decoy).is_err());
+        assert!(encode_buffer(&[0xff, 0xfe], &carrier, &EncodeOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_streamed_encode_matches_in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_path = dir.path().join("message.txt");
+        let message = "Streamed a row at a time. ".repeat(20);
+        fs::write(&data_path, &message).unwrap();
+        let data_path = data_path.to_str().unwrap();
+        let color = image::RgbImage::from_fn(50, 120, |x, y| {
+            image::Rgb([(x * 5) as u8, (y * 2) as u8, ((x + y) * 3) as u8])
+        });
+        let gray = DynamicImage::ImageRgb8(color.clone()).into_luma8();
+        let streamed = |carrier: &str, output: &str, options: &EncodeOptions| {
+            let carrier = dir.path().join(carrier);
+            let output = dir.path().join(output);
+            let options = EncodeOptions {
+                streaming: true,
+                ..options.clone()
+            };
+            encode(
+                data_path,
+                carrier.to_str().unwrap(),
+                output.to_str().unwrap(),
+                &options,
+                &SilentProgress,
+            )
+            .unwrap();
+            image::open(output).unwrap()
+        };
+
+        color.save(dir.path().join("color.png")).unwrap();
+        let options = EncodeOptions {
+            layout: Layout {
+                stride: 2,
+                depth: 2,
+                ..Layout::default()
+            },
+            ..EncodeOptions::default()
+        };
+        let encoded = streamed("color.png", "color-encoded.png", &options);
+        assert_eq!(
+            encoded.into_rgb8(),
+            encode_buffer(message.as_bytes(), &color, &options).unwrap()
+        );
+
+        gray.save(dir.path().join("gray.tiff")).unwrap();
+        let options = EncodeOptions {
+            headerless: true,
+            ..EncodeOptions::default()
+        };
+        let encoded = streamed("gray.tiff", "gray-encoded.tiff", &options).into_luma8();
+        assert_eq!(
+            encoded,
+            encode_buffer(message.as_bytes(), &gray, &options).unwrap()
+        );
+        let decode_options = DecodeOptions {
+            headerless_length: Some(message.len()),
+            ..DecodeOptions::default()
+        };
+        assert_eq!(
+            decode_buffer(&encoded, &decode_options).unwrap(),
+            message.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_streamed_encode_rejects_whole_carrier_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_path = dir.path().join("message.txt");
+        let carrier = dir.path().join("carrier.png");
+        let output = dir.path().join("encoded.png");
+        fs::write(&data_path, "Hello").unwrap();
+        image::RgbImage::new(32, 32).save(&carrier).unwrap();
+        let encode_streamed = |options: EncodeOptions| {
+            encode(
+                data_path.to_str().unwrap(),
+                carrier.to_str().unwrap(),
+                output.to_str().unwrap(),
+                &EncodeOptions {
+                    streaming: true,
+                    ..options
+                },
+                &SilentProgress,
+            )
+        };
+
+        assert!(encode_streamed(EncodeOptions {
+            layout: Layout {
+                seed: Some(7),
+                ..Layout::default()
+            },
+            ..EncodeOptions::default()
+        })
+        .is_err());
+        assert!(encode_streamed(EncodeOptions {
+            unused_bits: UnusedBits::Randomize,
+            ..EncodeOptions::default()
+        })
+        .is_err());
+        assert!(!output.exists());
+
+        // An earlier message's leftovers would need the whole carrier to randomize
+        encode_streamed(EncodeOptions::default()).unwrap();
+        fs::rename(&output, &carrier).unwrap();
+        assert!(encode_streamed(EncodeOptions::default()).is_err());
+        encode_streamed(EncodeOptions {
+            unused_bits: UnusedBits::Preserve,
+            ..EncodeOptions::default()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_min_psnr() {
+        let carrier = |size: u32| {
+            image::RgbImage::from_fn(size, size, |x, y| {
+                image::Rgb([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8])
+            })
+        };
+        let message = "Distortion budget. ".repeat(30);
+        let options = |depth: usize| EncodeOptions {
+            key: Some(b"psnr key".to_vec()),
+            layout: Layout {
+                depth,
+                ..Layout::default()
+            },
+            min_psnr: Some(40.0),
+            ..EncodeOptions::default()
+        };
+
+        assert!(encode_buffer(message.as_bytes(), &carrier(64), &options(1)).is_ok());
+        match encode_buffer(message.as_bytes(), &carrier(32), &options(4)) {
+            Err(ApplicationError::EncodingError(message)) => {
+                assert!(message.contains("bigger carrier"))
+            }
+            other => panic!(
+                "Expected the PSNR guard to trip, got {:?}",
+                other.map(|_| ())
+            ),
+        }
+    }
+
+    #[test]
+    fn test_batch_output_paths_number_colliding_stems() {
+        let carriers: Vec<PathBuf> = ["photo-1.png", "photo.jpg", "photo.png", "view.bmp"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+
+        assert_eq!(
+            batch_output_paths(&carriers, "out"),
+            [
+                "out/photo-1-encoded.png",
+                "out/photo-encoded.png",
+                "out/photo-2-encoded.png",
+                "out/view-encoded.png",
+            ]
+            .map(PathBuf::from)
+        );
+    }
 }
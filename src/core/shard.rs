@@ -0,0 +1,227 @@
+use crate::error::ApplicationError;
+
+/// Prefix marking one shard of a payload split across several carriers
+pub const MARKER: &str = "SHARD:";
+
+/// One shard of a split payload, as read back from its marker
+#[derive(Debug, PartialEq, Eq)]
+struct Shard<'a> {
+    /// Random id shared by every shard of the same message
+    id: u32,
+    /// Position of the shard, from 1
+    index: usize,
+    total: usize,
+    data: &'a str,
+}
+
+/// Marker prefixed to shard `index` (from 1) of `total`, e.g. `SHARD:1a2b3c4d:2/3:`
+pub fn marker(id: u32, index: usize, total: usize) -> String {
+    format!("{}{:08x}:{}/{}:", MARKER, id, index, total)
+}
+
+/// Split `payload` into one marked shard per carrier, sized in proportion to each
+/// carrier's room so that no carrier is filled much more than the others
+///
+/// `capacities` are the bytes each carrier holds, marker included
+pub fn split(
+    payload: &str,
+    id: u32,
+    capacities: &[usize],
+) -> Result<Vec<String>, ApplicationError> {
+    let total = capacities.len();
+    let markers: Vec<String> = (1..=total).map(|index| marker(id, index, total)).collect();
+    let rooms: Vec<usize> = capacities
+        .iter()
+        .zip(&markers)
+        .map(|(capacity, marker)| capacity.saturating_sub(marker.len()))
+        .collect();
+    let total_room: usize = rooms.iter().sum();
+    if payload.len() > total_room {
+        return Err(ApplicationError::InsufficientCapacity {
+            needed: payload.len() + markers.iter().map(String::len).sum::<usize>(),
+            available: capacities.iter().sum(),
+        });
+    }
+
+    let mut shards = Vec::with_capacity(total);
+    let (mut start, mut filled_room) = (0, 0);
+    for (marker, room) in markers.iter().zip(&rooms) {
+        filled_room += room;
+        let mut end = (payload.len() as u128 * filled_room as u128)
+            .checked_div(total_room as u128)
+            .unwrap_or(0) as usize;
+        // Shards are embedded as text, so they may not cut a character in two
+        while !payload.is_char_boundary(end) {
+            end -= 1;
+        }
+        shards.push(format!("{}{}", marker, &payload[start..end]));
+        start = end;
+    }
+
+    Ok(shards)
+}
+
+/// Reassemble a payload from all of its shards, given in any order
+pub fn join(shards: &[String]) -> Result<String, ApplicationError> {
+    let mut shards = shards
+        .iter()
+        .map(|shard| parse(shard))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (id, total) = match shards.first() {
+        Some(first) => (first.id, first.total),
+        None => {
+            return Err(ApplicationError::DecodingError(
+                "No shards to join".to_string(),
+            ))
+        }
+    };
+    if shards.iter().any(|shard| shard.id != id) {
+        return Err(ApplicationError::DecodingError(
+            "Shards belong to different messages (mismatched ids)".to_string(),
+        ));
+    }
+    if shards.iter().any(|shard| shard.total != total) {
+        return Err(ApplicationError::DecodingError(
+            "Shards disagree on how many shards the message has".to_string(),
+        ));
+    }
+
+    shards.sort_by_key(|shard| shard.index);
+    if let Some(pair) = shards
+        .windows(2)
+        .find(|pair| pair[0].index == pair[1].index)
+    {
+        return Err(ApplicationError::DecodingError(format!(
+            "Shard {} of {} was given more than once",
+            pair[0].index, total
+        )));
+    }
+    let missing: Vec<String> = (1..=total)
+        .filter(|index| shards.iter().all(|shard| shard.index != *index))
+        .map(|index| index.to_string())
+        .collect();
+    if !missing.is_empty() {
+        return Err(ApplicationError::DecodingError(format!(
+            "Missing shard(s) {} of {}",
+            missing.join(", "),
+            total
+        )));
+    }
+
+    Ok(shards.iter().map(|shard| shard.data).collect())
+}
+
+/// Read a shard's marker, see [`marker`]
+fn parse(payload: &str) -> Result<Shard<'_>, ApplicationError> {
+    let malformed = || ApplicationError::DecodingError("Malformed shard marker".to_string());
+    let rest = payload.strip_prefix(MARKER).ok_or_else(|| {
+        ApplicationError::DecodingError("Payload is not a shard of a split message".to_string())
+    })?;
+
+    let mut fields = rest.splitn(3, ':');
+    let (id, position, data) = match (fields.next(), fields.next(), fields.next()) {
+        (Some(id), Some(position), Some(data)) => (id, position, data),
+        _ => return Err(malformed()),
+    };
+    let id = u32::from_str_radix(id, 16).map_err(|_| malformed())?;
+    let (index, total) = position.split_once('/').ok_or_else(malformed)?;
+    let index: usize = index.parse().map_err(|_| malformed())?;
+    let total: usize = total.parse().map_err(|_| malformed())?;
+    if index == 0 || index > total {
+        return Err(malformed());
+    }
+
+    Ok(Shard {
+        id,
+        index,
+        total,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_join_round_trip() {
+        let payload = "x".repeat(1000);
+        let shards = split(&payload, 7, &[400, 400, 400]).unwrap();
+
+        assert_eq!(shards.len(), 3);
+        assert!(shards.iter().all(|shard| shard.len() <= 400));
+        assert_eq!(join(&shards).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_split_is_proportional_to_capacity() {
+        let shards = split(&"x".repeat(300), 7, &[1000, 2000]).unwrap();
+        let header = marker(7, 1, 2).len();
+
+        assert_eq!(
+            shards
+                .iter()
+                .map(|shard| shard.len() - header)
+                .sum::<usize>(),
+            300
+        );
+        assert!(shards[1].len() > shards[0].len());
+    }
+
+    #[test]
+    fn test_split_keeps_characters_whole() {
+        let payload = "🦀".repeat(50);
+        let shards = split(&payload, 7, &[100, 100, 100]).unwrap();
+
+        assert_eq!(join(&shards).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_split_rejects_insufficient_capacity() {
+        let result = split(&"x".repeat(100), 7, &[50, 50]);
+
+        assert!(matches!(
+            result,
+            Err(ApplicationError::InsufficientCapacity { available: 100, .. })
+        ));
+    }
+
+    #[test]
+    fn test_join_accepts_any_order() {
+        let payload = "The quick brown fox jumps over the lazy dog";
+        let mut shards = split(payload, 7, &[40, 40, 40]).unwrap();
+        shards.reverse();
+
+        assert_eq!(join(&shards).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_join_reports_missing_shards() {
+        let shards = split(&"x".repeat(100), 7, &[60, 60, 60]).unwrap();
+        let error = join(&shards[..1]).unwrap_err().to_string();
+
+        assert!(error.contains("Missing shard(s) 2, 3 of 3"), "{}", error);
+    }
+
+    #[test]
+    fn test_join_rejects_mismatched_ids() {
+        let mut shards = split(&"x".repeat(100), 7, &[80, 80]).unwrap();
+        shards[1] = split(&"y".repeat(100), 8, &[80, 80]).unwrap().remove(1);
+
+        assert!(join(&shards)
+            .unwrap_err()
+            .to_string()
+            .contains("mismatched ids"));
+    }
+
+    #[test]
+    fn test_join_rejects_duplicate_and_foreign_payloads() {
+        let shards = split(&"x".repeat(100), 7, &[80, 80]).unwrap();
+        let duplicated = [shards[0].clone(), shards[0].clone()];
+
+        assert!(join(&duplicated).is_err());
+        assert!(join(&["Hello".to_string()]).is_err());
+        assert!(join(&["SHARD:zz:1/2:data".to_string()]).is_err());
+        assert!(join(&["SHARD:00000007:3/2:data".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,347 @@
+//! Reading and writing carriers too large to decode into memory at once
+//!
+//! A PNG or TIFF carrier is read a strip of rows at a time, and the encoded image is
+//! written the same way, so only the rows being passed through are ever held in memory
+
+use super::file::{ensure_parent_directory, validate_path};
+use crate::error::ApplicationError;
+use image::{ImageFormat, ImageReader};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Seek, Write};
+use std::path::Path;
+use tiff::decoder::{ChunkType, Decoder as TiffDecoder, DecodingResult};
+use tiff::encoder::{colortype, TiffEncoder, TiffValue};
+
+/// Channels of a streamed carrier's pixels, one byte each
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Samples {
+    Gray,
+    Rgb,
+    Rgba,
+}
+
+impl Samples {
+    /// Number of bytes per pixel
+    pub fn count(self) -> usize {
+        match self {
+            Samples::Gray => 1,
+            Samples::Rgb => 3,
+            Samples::Rgba => 4,
+        }
+    }
+}
+
+/// Reads the rows of a PNG or TIFF carrier in order, a strip at a time
+///
+/// Each item holds one or more whole rows: a single row for a PNG, and one of the
+/// image's strips for a TIFF
+pub struct StripReader {
+    pub width: u32,
+    pub height: u32,
+    pub samples: Samples,
+    source: Source,
+}
+
+enum Source {
+    Png(Box<png::Reader<BufReader<File>>>),
+    Tiff {
+        decoder: Box<TiffDecoder<BufReader<File>>>,
+        next: u32,
+        count: u32,
+    },
+}
+
+impl StripReader {
+    /// Opens a carrier for streaming, reading only its header
+    ///
+    /// Only carriers with 8 bits per channel can be streamed. A paletted PNG is read as
+    /// RGB (or RGBA when it has transparency), and a PNG with fewer bits as 8-bit
+    pub fn open(file_path: impl AsRef<Path>) -> Result<Self, ApplicationError> {
+        let file_path = file_path.as_ref();
+        validate_path(file_path)?;
+        match ImageReader::open(file_path)?
+            .with_guessed_format()?
+            .format()
+        {
+            Some(ImageFormat::Png) => Self::open_png(file_path),
+            Some(ImageFormat::Tiff) => Self::open_tiff(file_path),
+            _ => Err(ApplicationError::EncodingError(
+                "Only PNG and TIFF carriers can be streamed".to_string(),
+            )),
+        }
+    }
+
+    fn open_png(file_path: &Path) -> Result<Self, ApplicationError> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(file_path)?));
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let reader = decoder.read_info().map_err(png_decoding_error)?;
+        let (width, height) = (reader.info().width, reader.info().height);
+        if reader.info().interlaced {
+            return Err(ApplicationError::EncodingError(
+                "An interlaced PNG carrier cannot be streamed".to_string(),
+            ));
+        }
+        let samples = match reader.output_color_type() {
+            (png::ColorType::Grayscale, png::BitDepth::Eight) => Samples::Gray,
+            (png::ColorType::Rgb, png::BitDepth::Eight) => Samples::Rgb,
+            (png::ColorType::Rgba, png::BitDepth::Eight) => Samples::Rgba,
+            (color, depth) => {
+                return Err(unsupported_color(format!(
+                    "{:?} PNG with {} bits per channel",
+                    color, depth as u8
+                )))
+            }
+        };
+
+        Ok(Self {
+            width,
+            height,
+            samples,
+            source: Source::Png(Box::new(reader)),
+        })
+    }
+
+    fn open_tiff(file_path: &Path) -> Result<Self, ApplicationError> {
+        let mut decoder =
+            TiffDecoder::new(BufReader::new(File::open(file_path)?)).map_err(tiff_error)?;
+        if decoder.get_chunk_type() != ChunkType::Strip {
+            return Err(ApplicationError::EncodingError(
+                "A tiled TIFF carrier cannot be streamed".to_string(),
+            ));
+        }
+        let samples = match decoder.colortype().map_err(tiff_error)? {
+            tiff::ColorType::Gray(8) => Samples::Gray,
+            tiff::ColorType::RGB(8) => Samples::Rgb,
+            tiff::ColorType::RGBA(8) => Samples::Rgba,
+            color => return Err(unsupported_color(format!("{:?} TIFF", color))),
+        };
+        let (width, height) = decoder.dimensions().map_err(tiff_error)?;
+        let count = decoder.strip_count().map_err(tiff_error)?;
+
+        Ok(Self {
+            width,
+            height,
+            samples,
+            source: Source::Tiff {
+                decoder: Box::new(decoder),
+                next: 0,
+                count,
+            },
+        })
+    }
+
+    /// Number of bytes in one row
+    pub fn row_bytes(&self) -> usize {
+        self.width as usize * self.samples.count()
+    }
+}
+
+impl Iterator for StripReader {
+    type Item = Result<Vec<u8>, ApplicationError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.source {
+            Source::Png(reader) => reader
+                .next_row()
+                .map(|row| row.map(|row| row.data().to_vec()))
+                .map_err(png_decoding_error)
+                .transpose(),
+            Source::Tiff {
+                decoder,
+                next,
+                count,
+            } => {
+                if next == count {
+                    return None;
+                }
+                let strip = decoder.read_chunk(*next);
+                *next += 1;
+                Some(match strip {
+                    Ok(DecodingResult::U8(bytes)) => Ok(bytes),
+                    Ok(_) => Err(unsupported_color("TIFF with wider samples".to_string())),
+                    Err(e) => Err(tiff_error(e)),
+                })
+            }
+        }
+    }
+}
+
+/// Writes an image as PNG or TIFF, judging by the path's extension, from its rows in
+/// order; `strips` may split them into any number of whole rows each
+pub fn write_strips(
+    file_path: impl AsRef<Path>,
+    (width, height): (u32, u32),
+    samples: Samples,
+    strips: impl Iterator<Item = Result<Vec<u8>, ApplicationError>>,
+) -> Result<(), ApplicationError> {
+    let format = ImageFormat::from_path(&file_path).ok();
+    if !matches!(format, Some(ImageFormat::Png | ImageFormat::Tiff)) {
+        return Err(ApplicationError::EncodingError(
+            "A streamed image can only be saved as PNG or TIFF".to_string(),
+        ));
+    }
+    ensure_parent_directory(&file_path)?;
+    let mut file = BufWriter::new(File::create(file_path)?);
+    let size = (width, height);
+
+    match format {
+        Some(ImageFormat::Png) => write_png_strips(&mut file, size, samples, strips)?,
+        _ => match samples {
+            Samples::Gray => write_tiff_strips::<colortype::Gray8>(&mut file, size, strips)?,
+            Samples::Rgb => write_tiff_strips::<colortype::RGB8>(&mut file, size, strips)?,
+            Samples::Rgba => write_tiff_strips::<colortype::RGBA8>(&mut file, size, strips)?,
+        },
+    }
+    file.flush()?;
+    Ok(())
+}
+
+fn write_png_strips(
+    file: &mut impl Write,
+    (width, height): (u32, u32),
+    samples: Samples,
+    strips: impl Iterator<Item = Result<Vec<u8>, ApplicationError>>,
+) -> Result<(), ApplicationError> {
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(match samples {
+        Samples::Gray => png::ColorType::Grayscale,
+        Samples::Rgb => png::ColorType::Rgb,
+        Samples::Rgba => png::ColorType::Rgba,
+    });
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(png_encoding_error)?;
+    let mut stream = writer.stream_writer().map_err(png_encoding_error)?;
+    for strip in strips {
+        stream.write_all(&strip?)?;
+    }
+    stream.finish().map_err(png_encoding_error)?;
+    writer.finish().map_err(png_encoding_error)
+}
+
+/// Writes a TIFF in strips of the encoder's choosing, regrouping the incoming rows to fit
+fn write_tiff_strips<C: colortype::ColorType<Inner = u8>>(
+    file: &mut (impl Write + Seek),
+    (width, height): (u32, u32),
+    strips: impl Iterator<Item = Result<Vec<u8>, ApplicationError>>,
+) -> Result<(), ApplicationError>
+where
+    [u8]: TiffValue,
+{
+    let mut encoder = TiffEncoder::new(file).map_err(tiff_error)?;
+    let mut image = encoder.new_image::<C>(width, height).map_err(tiff_error)?;
+    let mut pending = Vec::new();
+
+    for strip in strips {
+        pending.extend_from_slice(&strip?);
+        let mut written = 0;
+        loop {
+            let size = image.next_strip_sample_count() as usize;
+            if size == 0 || pending.len() - written < size {
+                break;
+            }
+            image
+                .write_strip(&pending[written..written + size])
+                .map_err(tiff_error)?;
+            written += size;
+        }
+        pending.drain(..written);
+    }
+    image.finish().map_err(tiff_error)
+}
+
+fn unsupported_color(description: String) -> ApplicationError {
+    ApplicationError::EncodingError(format!(
+        "Only carriers with 8 bits per channel can be streamed, not a {}",
+        description
+    ))
+}
+
+fn png_decoding_error(e: png::DecodingError) -> ApplicationError {
+    ApplicationError::EncodingError(format!("PNG decoding failed: {}", e))
+}
+
+fn png_encoding_error(e: png::EncodingError) -> ApplicationError {
+    ApplicationError::EncodingError(format!("PNG encoding failed: {}", e))
+}
+
+fn tiff_error(e: tiff::TiffError) -> ApplicationError {
+    ApplicationError::EncodingError(format!("TIFF streaming failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(width: u32, height: u32, samples: Samples) -> Vec<Vec<u8>> {
+        let row_bytes = width as usize * samples.count();
+        (0..height as usize)
+            .map(|row| (0..row_bytes).map(|i| (row * 7 + i) as u8).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_strips_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, samples) in [
+            ("gray.png", Samples::Gray),
+            ("rgb.png", Samples::Rgb),
+            ("rgba.tiff", Samples::Rgba),
+            ("rgb.tif", Samples::Rgb),
+        ] {
+            let path = dir.path().join(name);
+            let expected = rows(13, 9, samples);
+            write_strips(&path, (13, 9), samples, expected.iter().cloned().map(Ok)).unwrap();
+
+            let reader = StripReader::open(&path).unwrap();
+            assert_eq!((reader.width, reader.height), (13, 9));
+            assert_eq!(reader.samples, samples);
+            let read: Vec<u8> = reader.flat_map(Result::unwrap).collect();
+            assert_eq!(read, expected.concat(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_tiff_rows_are_regrouped_into_strips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wide.tiff");
+        // Wide enough rows that the encoder splits the image into several strips
+        let expected = rows(40_000, 30, Samples::Gray);
+        let uneven = [expected[..7].concat(), expected[7..].concat()];
+        write_strips(
+            &path,
+            (40_000, 30),
+            Samples::Gray,
+            uneven.into_iter().map(Ok),
+        )
+        .unwrap();
+
+        let strips: Vec<Vec<u8>> = StripReader::open(&path)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert!(strips.len() > 1);
+        assert_eq!(strips.concat(), expected.concat());
+    }
+
+    #[test]
+    fn test_unsupported_carriers() {
+        let dir = tempfile::tempdir().unwrap();
+        let deep = dir.path().join("deep.png");
+        let jpeg = dir.path().join("photo.jpg");
+        image::ImageBuffer::<image::Rgb<u16>, _>::new(4, 4)
+            .save(&deep)
+            .unwrap();
+        image::RgbImage::new(4, 4).save(&jpeg).unwrap();
+
+        assert!(StripReader::open(&deep).is_err());
+        assert!(StripReader::open(&jpeg).is_err());
+        assert!(write_strips(
+            dir.path().join("out.bmp"),
+            (1, 1),
+            Samples::Gray,
+            std::iter::empty()
+        )
+        .is_err());
+    }
+}
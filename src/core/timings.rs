@@ -0,0 +1,104 @@
+//! Time spent in each phase of an operation, for the `--timings` report
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A step of encoding or decoding whose duration is reported
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Reading and decoding the carrier image
+    Load,
+    Encrypt,
+    Compress,
+    /// Writing the payload into the carrier's pixels
+    Embed,
+    /// Reading the payload out of the carrier's pixels
+    Extract,
+    Decompress,
+    Decrypt,
+    /// Writing the encoded image or the decoded message
+    Save,
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Load => "load",
+            Phase::Encrypt => "encrypt",
+            Phase::Compress => "compress",
+            Phase::Embed => "embed",
+            Phase::Extract => "extract",
+            Phase::Decompress => "decompress",
+            Phase::Decrypt => "decrypt",
+            Phase::Save => "save",
+        }
+    }
+}
+
+/// Durations of the phases an operation went through, in the order each first ran
+///
+/// A phase that runs more than once, such as loading every carrier of a split message,
+/// is reported as the sum of its runs
+#[derive(Debug, Default)]
+pub struct Timings {
+    phases: Mutex<Vec<(Phase, Duration)>>,
+}
+
+impl Timings {
+    /// Adds `elapsed` to the time spent in `phase`
+    pub fn record(&self, phase: Phase, elapsed: Duration) {
+        let Ok(mut phases) = self.phases.lock() else {
+            return;
+        };
+        match phases.iter_mut().find(|(recorded, _)| *recorded == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => phases.push((phase, elapsed)),
+        }
+    }
+
+    /// The phases recorded so far with the time spent in each
+    pub fn phases(&self) -> Vec<(Phase, Duration)> {
+        self.phases
+            .lock()
+            .map(|phases| phases.clone())
+            .unwrap_or_default()
+    }
+
+    /// One line per phase with its time in milliseconds, for printing after the operation
+    pub fn report(&self) -> String {
+        let mut report = String::from("Timings:\n");
+        for (phase, elapsed) in self.phases() {
+            report.push_str(&format!(
+                "  {:<10} {:>8.1} ms\n",
+                phase.name(),
+                elapsed.as_secs_f64() * 1000.0
+            ));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_phases_are_summed_in_first_run_order() {
+        let timings = Timings::default();
+        timings.record(Phase::Load, Duration::from_millis(3));
+        timings.record(Phase::Embed, Duration::from_millis(5));
+        timings.record(Phase::Load, Duration::from_millis(4));
+
+        assert_eq!(
+            timings.phases(),
+            [
+                (Phase::Load, Duration::from_millis(7)),
+                (Phase::Embed, Duration::from_millis(5)),
+            ]
+        );
+        assert_eq!(
+            timings.report(),
+            "Timings:\n  load            7.0 ms\n  embed           5.0 ms\n"
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use clap::ValueEnum;
+
+const BINARY_UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+const DECIMAL_UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+/// Unit system used when reporting sizes and capacities
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SizeUnits {
+    /// Plain byte counts
+    Bytes,
+    /// Powers of 1024 (KiB, MiB, GiB)
+    #[default]
+    Binary,
+    /// Powers of 1000 (KB, MB, GB)
+    Decimal,
+}
+
+/// Format a byte count using the selected unit system
+pub fn format_size(bytes: usize, units: SizeUnits) -> String {
+    let (base, labels) = match units {
+        SizeUnits::Bytes => return format!("{} B", bytes),
+        SizeUnits::Binary => (1024.0, BINARY_UNITS),
+        SizeUnits::Decimal => (1000.0, DECIMAL_UNITS),
+    };
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= base && unit < labels.len() - 1 {
+        value /= base;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, labels[0])
+    } else {
+        format!("{:.2} {}", value, labels[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_units() {
+        assert_eq!(format_size(512, SizeUnits::Binary), "512 B");
+        assert_eq!(format_size(1536, SizeUnits::Binary), "1.50 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024, SizeUnits::Binary), "3.00 MiB");
+    }
+
+    #[test]
+    fn test_decimal_units() {
+        assert_eq!(format_size(512, SizeUnits::Decimal), "512 B");
+        assert_eq!(format_size(1536, SizeUnits::Decimal), "1.54 KB");
+        assert_eq!(format_size(3 * 1024 * 1024, SizeUnits::Decimal), "3.15 MB");
+    }
+
+    #[test]
+    fn test_byte_units() {
+        assert_eq!(format_size(1536, SizeUnits::Bytes), "1536 B");
+    }
+}
@@ -1,14 +1,75 @@
+use super::util::{derive_key, generate_salt, KdfCost, SALT_SIZE};
 use crate::error::ApplicationError;
 use aes_gcm::{
-    aead::{rand_core::RngCore, Aead, KeyInit, OsRng},
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng, Payload},
     Aes256Gcm, Nonce,
 };
 use base64::{engine::general_purpose, Engine};
 
 const NONCE_SIZE: usize = 12;
 
-/// Encrypt plaintext data with a key using AES GCM mode, returning a base64-encoded string
-pub fn encrypt(data: &str, key: &[u8; 32]) -> Result<String, ApplicationError> {
+/// Encrypt plaintext data with a passphrase using AES GCM mode, returning a base64-encoded string
+///
+/// The key is derived with Argon2id at `cost` from the passphrase and a random salt,
+/// which is stored ahead of the nonce: `base64(salt || nonce || ciphertext)`
+pub fn encrypt(
+    data: &str,
+    passphrase: &[u8],
+    cost: KdfCost,
+    aad: &[u8],
+) -> Result<String, ApplicationError> {
+    let salt = generate_salt();
+    let key = derive_key(passphrase, &salt, cost)?;
+    let encrypted_data = seal(data, &key, aad)?;
+
+    let mut salted_data = Vec::with_capacity(SALT_SIZE + encrypted_data.len());
+    salted_data.extend_from_slice(&salt);
+    salted_data.extend_from_slice(&encrypted_data);
+
+    Ok(general_purpose::STANDARD.encode(salted_data))
+}
+
+/// Decrypt base64-encoded data produced by [`encrypt`] with the same passphrase and cost
+pub fn decrypt(
+    encoded_data: &str,
+    passphrase: &[u8],
+    cost: KdfCost,
+    aad: &[u8],
+) -> Result<String, ApplicationError> {
+    let salted_data = decode_base64(encoded_data)?;
+
+    if salted_data.len() < SALT_SIZE {
+        return Err(ApplicationError::DecryptionError(
+            "Encrypted data too short".to_string(),
+        ));
+    }
+
+    let (salt, encrypted_data) = salted_data.split_at(SALT_SIZE);
+    let key = derive_key(passphrase, salt, cost)?;
+    open(encrypted_data, &key, aad)
+}
+
+/// Encrypt plaintext data with a raw 32-byte key using AES GCM mode, returning a
+/// base64-encoded string of `nonce || ciphertext`
+pub fn encrypt_with_key(
+    data: &str,
+    key: &[u8; 32],
+    aad: &[u8],
+) -> Result<String, ApplicationError> {
+    Ok(general_purpose::STANDARD.encode(seal(data, key, aad)?))
+}
+
+/// Decrypt base64-encoded data produced by [`encrypt_with_key`] with the same raw key
+pub fn decrypt_with_key(
+    encoded_data: &str,
+    key: &[u8; 32],
+    aad: &[u8],
+) -> Result<String, ApplicationError> {
+    open(&decode_base64(encoded_data)?, key, aad)
+}
+
+/// Encrypt `data`, authenticating `aad` alongside it (an empty `aad` is the same as none)
+fn seal(data: &str, key: &[u8; 32], aad: &[u8]) -> Result<Vec<u8>, ApplicationError> {
     let cipher = Aes256Gcm::new(key.into());
 
     let mut nonce_bytes = [0u8; NONCE_SIZE];
@@ -16,26 +77,25 @@ pub fn encrypt(data: &str, key: &[u8; 32]) -> Result<String, ApplicationError> {
     let nonce = Nonce::from_slice(&nonce_bytes);
 
     let ciphertext = cipher
-        .encrypt(nonce, data.as_bytes())
+        .encrypt(
+            nonce,
+            Payload {
+                msg: data.as_bytes(),
+                aad,
+            },
+        )
         .map_err(|_| ApplicationError::EncryptionError("Encryption failed".to_string()))?;
 
     let mut encrypted_data = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
     encrypted_data.extend_from_slice(&nonce_bytes);
     encrypted_data.extend_from_slice(&ciphertext);
 
-    Ok(general_purpose::STANDARD.encode(encrypted_data))
+    Ok(encrypted_data)
 }
 
-/// Decrypt base64-encoded data with a key using AES GCM mode
-pub fn decrypt(encoded_data: &str, key: &[u8; 32]) -> Result<String, ApplicationError> {
+fn open(encrypted_data: &[u8], key: &[u8; 32], aad: &[u8]) -> Result<String, ApplicationError> {
     let cipher = Aes256Gcm::new(key.into());
 
-    let encrypted_data = general_purpose::STANDARD
-        .decode(encoded_data)
-        .map_err(|e| {
-            ApplicationError::DecryptionError(format!("Invalid base64 encoding: {}", e))
-        })?;
-
     if encrypted_data.len() < NONCE_SIZE {
         return Err(ApplicationError::DecryptionError(
             "Encrypted data too short".to_string(),
@@ -45,7 +105,13 @@ pub fn decrypt(encoded_data: &str, key: &[u8; 32]) -> Result<String, Application
     let (nonce, ciphertext) = encrypted_data.split_at(NONCE_SIZE);
 
     let decrypted_data = cipher
-        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
         .map_err(|e| ApplicationError::DecryptionError(format!("Decryption failed: {}", e)))?;
 
     String::from_utf8(decrypted_data).map_err(|e| {
@@ -53,6 +119,12 @@ pub fn decrypt(encoded_data: &str, key: &[u8; 32]) -> Result<String, Application
     })
 }
 
+fn decode_base64(encoded_data: &str) -> Result<Vec<u8>, ApplicationError> {
+    general_purpose::STANDARD
+        .decode(encoded_data)
+        .map_err(|e| ApplicationError::DecryptionError(format!("Invalid base64 encoding: {}", e)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -63,8 +135,9 @@ mod tests {
     fn test_encrypt_decrypt() {
         let key = [0u8; 32];
         let data = "Test message for encryption";
-        let encrypted_data = encrypt(data, &key).expect("Encryption failed");
-        let decrypted_data = decrypt(&encrypted_data, &key).expect("Decryption failed");
+        let encrypted_data = encrypt_with_key(data, &key, &[]).expect("Encryption failed");
+        let decrypted_data =
+            decrypt_with_key(&encrypted_data, &key, &[]).expect("Decryption failed");
 
         assert_eq!(data, decrypted_data);
     }
@@ -74,8 +147,8 @@ mod tests {
         let original_key = [0u8; 32];
         let invalid_key = [1u8; 32];
         let data = "This message will not decrypt properly";
-        let encrypted_data = encrypt(data, &original_key).expect("Encryption failed");
-        let result = decrypt(&encrypted_data, &invalid_key);
+        let encrypted_data = encrypt_with_key(data, &original_key, &[]).expect("Encryption failed");
+        let result = decrypt_with_key(&encrypted_data, &invalid_key, &[]);
 
         assert!(result.is_err());
     }
@@ -84,8 +157,9 @@ mod tests {
     fn test_encrypt_empty_string() {
         let key = [0u8; 32];
         let data = "";
-        let encrypted_data = encrypt(data, &key).expect("Encryption failed");
-        let decrypted_data = decrypt(&encrypted_data, &key).expect("Decryption failed");
+        let encrypted_data = encrypt_with_key(data, &key, &[]).expect("Encryption failed");
+        let decrypted_data =
+            decrypt_with_key(&encrypted_data, &key, &[]).expect("Decryption failed");
 
         assert_eq!(data, decrypted_data);
     }
@@ -95,9 +169,48 @@ mod tests {
         let mut key = [0u8; 32];
         OsRng.fill_bytes(&mut key);
         let data = "Testing encryption with a random key";
-        let encrypted_data = encrypt(data, &key).expect("Encryption failed");
-        let decrypted_data = decrypt(&encrypted_data, &key).expect("Decryption failed");
+        let encrypted_data = encrypt_with_key(data, &key, &[]).expect("Encryption failed");
+        let decrypted_data =
+            decrypt_with_key(&encrypted_data, &key, &[]).expect("Decryption failed");
+
+        assert_eq!(data, decrypted_data);
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_with_passphrase() {
+        let data = "Test message for passphrase encryption";
+        let encrypted_data =
+            encrypt(data, b"correct horse", KdfCost::Low, &[]).expect("Encryption failed");
+        let decrypted_data = decrypt(&encrypted_data, b"correct horse", KdfCost::Low, &[])
+            .expect("Decryption failed");
 
         assert_eq!(data, decrypted_data);
+        assert!(decrypt(&encrypted_data, b"wrong horse", KdfCost::Low, &[]).is_err());
+    }
+
+    #[test]
+    fn test_passphrase_encryption_is_salted() {
+        let data = "Same message, same passphrase";
+        let first = general_purpose::STANDARD
+            .decode(encrypt(data, b"passphrase", KdfCost::Low, &[]).unwrap())
+            .unwrap();
+        let second = general_purpose::STANDARD
+            .decode(encrypt(data, b"passphrase", KdfCost::Low, &[]).unwrap())
+            .unwrap();
+
+        assert_ne!(first[..SALT_SIZE], second[..SALT_SIZE]);
+    }
+
+    #[test]
+    fn test_associated_data_must_match() {
+        let key = [0u8; 32];
+        let data = "Bound to a 640x480 carrier";
+        let aad = [640u32.to_be_bytes(), 480u32.to_be_bytes()].concat();
+        let encrypted_data = encrypt_with_key(data, &key, &aad).expect("Encryption failed");
+
+        assert_eq!(decrypt_with_key(&encrypted_data, &key, &aad).unwrap(), data);
+        let resized = [640u32.to_be_bytes(), 481u32.to_be_bytes()].concat();
+        assert!(decrypt_with_key(&encrypted_data, &key, &resized).is_err());
+        assert!(decrypt_with_key(&encrypted_data, &key, &[]).is_err());
     }
 }
@@ -0,0 +1,209 @@
+use super::util::{key_to_bytes, KdfCost, SALT_SIZE};
+use super::{aes, chacha};
+use crate::error::ApplicationError;
+use clap::ValueEnum;
+use serde::Deserialize;
+
+const AES_TAG: char = '\u{1}';
+const CHACHA_TAG: char = '\u{2}';
+/// Bytes both ciphers seal alongside the plaintext: a 12-byte nonce and a 16-byte tag
+const SEAL_OVERHEAD: usize = 12 + 16;
+/// Key derivation tags hold the cost preset's id in their low nibble
+const KDF_TAGS: std::ops::RangeInclusive<char> = '\u{10}'..='\u{1f}';
+
+/// Authenticated cipher protecting the payload
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cipher {
+    /// AES-256-GCM, fastest on CPUs with AES instructions
+    #[default]
+    Aes,
+    /// ChaCha20-Poly1305, fast and constant-time without AES hardware
+    Chacha,
+}
+
+impl Cipher {
+    fn tag(self) -> char {
+        match self {
+            Cipher::Aes => AES_TAG,
+            Cipher::Chacha => CHACHA_TAG,
+        }
+    }
+}
+
+/// Encrypt `data` with `cipher`, prefixing the base64 ciphertext with a 1-byte cipher tag
+///
+/// With `raw_key` the key bytes are used as is; otherwise they are a passphrase,
+/// stretched at `cost`. Any cost but the default is recorded in a second tag byte.
+/// `aad` is authenticated but not encrypted, and must be given again to decrypt
+pub fn encrypt(
+    data: &str,
+    key: &[u8],
+    cipher: Cipher,
+    raw_key: bool,
+    cost: KdfCost,
+    aad: &[u8],
+) -> Result<String, ApplicationError> {
+    let ciphertext = match (cipher, raw_key) {
+        (Cipher::Aes, false) => aes::encrypt(data, key, cost, aad)?,
+        (Cipher::Aes, true) => aes::encrypt_with_key(data, &key_to_bytes(key)?, aad)?,
+        (Cipher::Chacha, false) => chacha::encrypt(data, key, cost, aad)?,
+        (Cipher::Chacha, true) => chacha::encrypt_with_key(data, &key_to_bytes(key)?, aad)?,
+    };
+
+    // Untagged passphrase ciphertexts use the default cost, so older releases can read them
+    let cost_tag = if raw_key || cost == KdfCost::default() {
+        String::new()
+    } else {
+        char::from(*KDF_TAGS.start() as u8 | cost.id()).to_string()
+    };
+    Ok(format!("{}{}{}", cipher.tag(), cost_tag, ciphertext))
+}
+
+/// Decrypt data produced by [`encrypt`], picking the cipher from its tag
+///
+/// Untagged data predates the tag and is always AES, and a passphrase without a cost
+/// tag was stretched at the default cost. When `expected` is given, data tagged with
+/// a different cipher is rejected
+pub fn decrypt(
+    data: &str,
+    key: &[u8],
+    expected: Option<Cipher>,
+    raw_key: bool,
+    aad: &[u8],
+) -> Result<String, ApplicationError> {
+    let (cipher, ciphertext) = match data.chars().next() {
+        Some(AES_TAG) => (Cipher::Aes, &data[AES_TAG.len_utf8()..]),
+        Some(CHACHA_TAG) => (Cipher::Chacha, &data[CHACHA_TAG.len_utf8()..]),
+        _ => (Cipher::Aes, data),
+    };
+    let (cost, ciphertext) = match ciphertext.chars().next() {
+        Some(tag) if KDF_TAGS.contains(&tag) => (
+            KdfCost::from_id(tag as u8 & 0x0f)?,
+            &ciphertext[tag.len_utf8()..],
+        ),
+        _ => (KdfCost::default(), ciphertext),
+    };
+
+    if let Some(expected) = expected.filter(|&expected| expected != cipher) {
+        return Err(ApplicationError::DecryptionError(format!(
+            "Message was encrypted with {:?}, not {:?}",
+            cipher, expected
+        )));
+    }
+
+    match (cipher, raw_key) {
+        (Cipher::Aes, false) => aes::decrypt(ciphertext, key, cost, aad),
+        (Cipher::Aes, true) => aes::decrypt_with_key(ciphertext, &key_to_bytes(key)?, aad),
+        (Cipher::Chacha, false) => chacha::decrypt(ciphertext, key, cost, aad),
+        (Cipher::Chacha, true) => chacha::decrypt_with_key(ciphertext, &key_to_bytes(key)?, aad),
+    }
+}
+
+/// Longest plaintext whose [`encrypt`] output fits in `capacity` bytes, with either cipher
+///
+/// The output is the cipher tag, the cost tag for a passphrase at any but the default
+/// `cost`, then the base64 of the sealed plaintext, preceded by the salt unless `raw_key`
+/// is set
+pub fn max_plaintext_len(capacity: usize, raw_key: bool, cost: KdfCost) -> usize {
+    let tags = if raw_key || cost == KdfCost::default() {
+        1
+    } else {
+        2
+    };
+    let salt = if raw_key { 0 } else { SALT_SIZE };
+    let sealed = capacity.saturating_sub(tags) / 4 * 3;
+    sealed.saturating_sub(salt + SEAL_OVERHEAD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_each_cipher() {
+        for cipher in [Cipher::Aes, Cipher::Chacha] {
+            for raw_key in [false, true] {
+                let encrypted =
+                    encrypt("secret", b"key", cipher, raw_key, KdfCost::Low, &[]).unwrap();
+
+                assert!(encrypted.starts_with(cipher.tag()));
+                assert_eq!(
+                    decrypt(&encrypted, b"key", None, raw_key, &[]).unwrap(),
+                    "secret"
+                );
+                assert!(decrypt(&encrypted, b"wrong key", None, raw_key, &[]).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_untagged_data_is_aes() {
+        let key_bytes = key_to_bytes(b"key").unwrap();
+        let legacy = aes::encrypt_with_key("old secret", &key_bytes, &[]).unwrap();
+
+        assert_eq!(
+            decrypt(&legacy, b"key", None, true, &[]).unwrap(),
+            "old secret"
+        );
+    }
+
+    #[test]
+    fn test_kdf_cost_is_tagged() {
+        for cost in [KdfCost::Low, KdfCost::High] {
+            let encrypted = encrypt("secret", b"key", Cipher::Aes, false, cost, &[]).unwrap();
+
+            assert_eq!(encrypted.chars().nth(1), Some(char::from(0x10 | cost.id())));
+            assert_eq!(
+                decrypt(&encrypted, b"key", None, false, &[]).unwrap(),
+                "secret"
+            );
+        }
+
+        let default = encrypt("secret", b"key", Cipher::Aes, false, KdfCost::Medium, &[]).unwrap();
+        assert!(!KDF_TAGS.contains(&default.chars().nth(1).unwrap()));
+    }
+
+    #[test]
+    fn test_unknown_kdf_cost() {
+        let encrypted = encrypt("secret", b"key", Cipher::Aes, false, KdfCost::Low, &[]).unwrap();
+        let unknown = encrypted.replacen('\u{11}', "\u{1f}", 1);
+
+        match decrypt(&unknown, b"key", None, false, &[]) {
+            Err(ApplicationError::DecryptionError(e)) => {
+                assert!(e.contains("Unknown key derivation parameters"))
+            }
+            other => panic!("Expected a decryption error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_expected_cipher_mismatch() {
+        let encrypted =
+            encrypt("secret", b"key", Cipher::Chacha, false, KdfCost::Low, &[]).unwrap();
+
+        assert!(decrypt(&encrypted, b"key", Some(Cipher::Aes), false, &[]).is_err());
+        assert!(decrypt(&encrypted, b"key", Some(Cipher::Chacha), false, &[]).is_ok());
+    }
+
+    #[test]
+    fn test_max_plaintext_len_fills_capacity() {
+        let encrypted_len = |len, raw_key, cost| {
+            encrypt(&"x".repeat(len), b"key", Cipher::Chacha, raw_key, cost, &[])
+                .unwrap()
+                .len()
+        };
+        for capacity in [0, 30, 41, 42, 100, 257] {
+            let longest = max_plaintext_len(capacity, true, KdfCost::default());
+
+            assert!(longest == 0 || encrypted_len(longest, true, KdfCost::default()) <= capacity);
+            assert!(encrypted_len(longest + 1, true, KdfCost::default()) > capacity);
+        }
+        for cost in [KdfCost::Low, KdfCost::default()] {
+            let longest = max_plaintext_len(200, false, cost);
+
+            assert!(encrypted_len(longest, false, cost) <= 200);
+            assert!(encrypted_len(longest + 1, false, cost) > 200);
+        }
+    }
+}
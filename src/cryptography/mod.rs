@@ -1,2 +1,4 @@
 pub mod aes;
+pub mod chacha;
+pub mod cipher;
 pub mod util;
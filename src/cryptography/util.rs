@@ -1,14 +1,89 @@
 use crate::error::ApplicationError;
 use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use argon2::{Algorithm, Argon2, Params, Version};
 use base64::{engine::general_purpose, Engine};
-use colored::*;
+use clap::ValueEnum;
+use log::warn;
+use sha2::{Digest, Sha256};
 
 const KEY_SIZE: usize = 32;
+pub const SALT_SIZE: usize = 16;
+const SEED_DOMAIN: &[u8] = b"mindbender/permutation-seed";
 
-/// Convert a string key into a fixed 32-byte array for AES-256 encryption
-pub fn key_to_bytes(key: &str) -> Result<[u8; 32], ApplicationError> {
-    let key_bytes = key.as_bytes();
+/// Argon2id parameter set used by [`derive_key`]
+///
+/// Higher costs make each guess at a passphrase slower and hungrier for memory, for
+/// the attacker and for every encode and decode alike
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum KdfCost {
+    /// 8 MiB and one pass, for slow or memory-starved machines
+    Low,
+    /// 19 MiB and two passes, the Argon2 defaults every older release used
+    #[default]
+    Medium,
+    /// 64 MiB and three passes
+    High,
+}
+
+impl KdfCost {
+    /// Memory (in KiB), passes and lanes of the preset
+    pub fn params(self) -> Params {
+        let (memory, passes) = match self {
+            KdfCost::Low => (8 * 1024, 1),
+            KdfCost::Medium => (Params::DEFAULT_M_COST, Params::DEFAULT_T_COST),
+            KdfCost::High => (64 * 1024, 3),
+        };
+        Params::new(memory, passes, 1, None).expect("Valid Argon2 parameters")
+    }
+
+    /// Identifier recorded with the ciphertext
+    pub fn id(self) -> u8 {
+        match self {
+            KdfCost::Low => 1,
+            KdfCost::Medium => 2,
+            KdfCost::High => 3,
+        }
+    }
+
+    /// Preset recorded as `id` by [`KdfCost::id`]
+    pub fn from_id(id: u8) -> Result<Self, ApplicationError> {
+        match id {
+            1 => Ok(KdfCost::Low),
+            2 => Ok(KdfCost::Medium),
+            3 => Ok(KdfCost::High),
+            _ => Err(ApplicationError::DecryptionError(format!(
+                "Unknown key derivation parameters (preset {}); the message may have been written by a newer mindbender",
+                id
+            ))),
+        }
+    }
+}
+
+/// Derive a 32-byte AES-256 key from a passphrase and salt using Argon2id with the
+/// parameters of `cost`
+pub fn derive_key(
+    passphrase: &[u8],
+    salt: &[u8],
+    cost: KdfCost,
+) -> Result<[u8; KEY_SIZE], ApplicationError> {
+    let mut key = [0u8; KEY_SIZE];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, cost.params())
+        .hash_password_into(passphrase, salt, &mut key)
+        .map_err(|e| ApplicationError::EncryptionError(format!("Key derivation failed: {}", e)))?;
+    Ok(key)
+}
+
+/// Generate a random salt for [`derive_key`]
+pub fn generate_salt() -> [u8; SALT_SIZE] {
+    let mut salt = [0u8; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
 
+/// Convert a key into a fixed 32-byte array for AES-256 encryption, as is
+///
+/// Only used with `--raw-key`; passphrases normally go through [`derive_key`]
+pub fn key_to_bytes(key_bytes: &[u8]) -> Result<[u8; 32], ApplicationError> {
     if key_bytes.len() > KEY_SIZE {
         return Err(ApplicationError::EncryptionError(format!(
             "Key length {} exceeds maximum of {} bytes",
@@ -18,7 +93,11 @@ pub fn key_to_bytes(key: &str) -> Result<[u8; 32], ApplicationError> {
     }
 
     if key_bytes.len() < KEY_SIZE {
-        println!("{}", "Warning: insecure key length".yellow());
+        warn!(
+            "Insecure key length: {} of {} bytes; the rest is zero padding",
+            key_bytes.len(),
+            KEY_SIZE
+        );
     }
 
     let mut result = [0u8; KEY_SIZE];
@@ -26,40 +105,139 @@ pub fn key_to_bytes(key: &str) -> Result<[u8; 32], ApplicationError> {
     Ok(result)
 }
 
-/// Generate an encryption key
-pub fn generate_key(length: Option<usize>) -> Result<String, ApplicationError> {
+/// How hard a key would be to guess, judged by [`assess_key_strength`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KeyStrength {
+    Weak,
+    Fair,
+    Strong,
+}
+
+impl KeyStrength {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeyStrength::Weak => "weak",
+            KeyStrength::Fair => "fair",
+            KeyStrength::Strong => "strong",
+        }
+    }
+}
+
+/// Rate a key by its length and how many character classes (lowercase, uppercase, digits,
+/// anything else) it mixes
+///
+/// Keys shorter than 8 characters, or under 16 drawn from a single class, are weak; keys of
+/// 16 characters mixing three classes, or of 24 characters whatever they contain, are strong.
+/// Bytes of a binary key that aren't valid UTF-8 count as replacement characters
+pub fn assess_key_strength(key: &[u8]) -> KeyStrength {
+    let key = String::from_utf8_lossy(key);
+    let length = key.chars().count();
+    let classes = [
+        key.chars().any(|c| c.is_lowercase()),
+        key.chars().any(|c| c.is_uppercase()),
+        key.chars().any(|c| c.is_numeric()),
+        key.chars().any(|c| !c.is_alphanumeric()),
+    ]
+    .iter()
+    .filter(|&&present| present)
+    .count();
+
+    if length < 8 || (length < 16 && classes < 2) {
+        KeyStrength::Weak
+    } else if length >= 24 || (length >= 16 && classes >= 3) {
+        KeyStrength::Strong
+    } else {
+        KeyStrength::Fair
+    }
+}
+
+/// Derive a stable 64-bit seed from a key, for key-dependent pixel permutations
+pub fn derive_seed(key: &[u8]) -> u64 {
+    let digest = Sha256::new()
+        .chain_update(SEED_DOMAIN)
+        .chain_update(key)
+        .finalize();
+
+    let mut seed = [0u8; 8];
+    seed.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(seed)
+}
+
+/// How [`generate_key`] writes out the key's random bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum KeyEncoding {
+    #[default]
+    Base64,
+    Hex,
+    /// The bytes themselves, for key files
+    Raw,
+}
+
+/// Generate an encryption key of `length` random bytes (32 by default), written out in
+/// `encoding`
+pub fn generate_key(
+    length: Option<usize>,
+    encoding: KeyEncoding,
+) -> Result<Vec<u8>, ApplicationError> {
     let key_length = length.unwrap_or(32);
     let mut key = vec![0u8; key_length];
     OsRng.fill_bytes(&mut key);
-    Ok(general_purpose::STANDARD.encode(key))
+    Ok(match encoding {
+        KeyEncoding::Base64 => general_purpose::STANDARD.encode(key).into_bytes(),
+        KeyEncoding::Hex => key
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+            .into_bytes(),
+        KeyEncoding::Raw => key,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_assess_key_strength() {
+        assert_eq!(assess_key_strength(b"abc"), KeyStrength::Weak);
+        assert_eq!(assess_key_strength(b"password"), KeyStrength::Weak);
+        assert_eq!(assess_key_strength(b"Tr0ub4dor&3"), KeyStrength::Fair);
+        assert_eq!(
+            assess_key_strength(b"sunset-harbor-42"),
+            KeyStrength::Strong
+        );
+        assert_eq!(
+            assess_key_strength(b"correct horse battery staple"),
+            KeyStrength::Strong
+        );
+        assert_eq!(
+            assess_key_strength(&generate_key(None, KeyEncoding::Base64).unwrap()),
+            KeyStrength::Strong
+        );
+    }
+
     #[test]
     fn test_exact_length_key() {
-        let key = "12345678901234567890123456789012";
+        let key = b"12345678901234567890123456789012";
         let result = key_to_bytes(key).unwrap();
 
         assert_eq!(result.len(), KEY_SIZE);
-        assert_eq!(&result, key.as_bytes());
+        assert_eq!(&result, key);
     }
 
     #[test]
     fn test_short_key() {
-        let key = "short-key";
+        let key = b"short-key";
         let result = key_to_bytes(key).unwrap();
 
         assert_eq!(result.len(), KEY_SIZE);
-        assert_eq!(&result[..key.len()], key.as_bytes());
+        assert_eq!(&result[..key.len()], key);
         assert!(result[key.len()..].iter().all(|&b| b == 0));
     }
 
     #[test]
     fn test_empty_key() {
-        let key = "";
+        let key = b"";
         let result = key_to_bytes(key).unwrap();
 
         assert_eq!(result.len(), KEY_SIZE);
@@ -68,7 +246,7 @@ mod tests {
 
     #[test]
     fn test_too_long_key() {
-        let key = "12345678901234567890123456789012X";
+        let key = b"12345678901234567890123456789012X";
         let result = key_to_bytes(key);
 
         assert!(result.is_err());
@@ -77,27 +255,107 @@ mod tests {
 
     #[test]
     fn test_unicode_key() {
-        let key = "🔑";
+        let key = "🔑".as_bytes();
         let result = key_to_bytes(key).unwrap();
 
         assert_eq!(result.len(), KEY_SIZE);
-        assert_eq!(&result[..4], key.as_bytes());
+        assert_eq!(&result[..4], key);
         assert!(result[4..].iter().all(|&b| b == 0));
     }
 
+    #[test]
+    fn test_derive_key_is_deterministic() {
+        let salt = [7u8; SALT_SIZE];
+
+        assert_eq!(
+            derive_key(b"passphrase", &salt, KdfCost::Medium).unwrap(),
+            derive_key(b"passphrase", &salt, KdfCost::Medium).unwrap()
+        );
+        assert_ne!(
+            derive_key(b"passphrase", &salt, KdfCost::Medium).unwrap(),
+            derive_key(b"other passphrase", &salt, KdfCost::Medium).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_derive_key_depends_on_salt() {
+        let first = derive_key(b"passphrase", &generate_salt(), KdfCost::Low).unwrap();
+        let second = derive_key(b"passphrase", &generate_salt(), KdfCost::Low).unwrap();
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_kdf_cost_params() {
+        assert_eq!(KdfCost::Medium.params(), Params::default());
+        assert_eq!(
+            (
+                KdfCost::Low.params().m_cost(),
+                KdfCost::Low.params().t_cost()
+            ),
+            (8 * 1024, 1)
+        );
+        assert_eq!(
+            (
+                KdfCost::High.params().m_cost(),
+                KdfCost::High.params().t_cost()
+            ),
+            (64 * 1024, 3)
+        );
+
+        for cost in [KdfCost::Low, KdfCost::Medium, KdfCost::High] {
+            assert_eq!(KdfCost::from_id(cost.id()).unwrap(), cost);
+        }
+        assert!(matches!(
+            KdfCost::from_id(9),
+            Err(ApplicationError::DecryptionError(_))
+        ));
+    }
+
+    #[test]
+    fn test_derive_key_depends_on_cost() {
+        let salt = [7u8; SALT_SIZE];
+
+        assert_ne!(
+            derive_key(b"passphrase", &salt, KdfCost::Low).unwrap(),
+            derive_key(b"passphrase", &salt, KdfCost::Medium).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_derive_seed() {
+        assert_eq!(derive_seed(b"key"), derive_seed(b"key"));
+        assert_ne!(derive_seed(b"key"), derive_seed(b"other key"));
+    }
+
     #[test]
     fn test_generate_key() {
-        let key = generate_key(None).unwrap();
+        let key = generate_key(None, KeyEncoding::Base64).unwrap();
         assert_eq!(general_purpose::STANDARD.decode(&key).unwrap().len(), 32);
     }
 
     #[test]
     fn test_generate_custom_length_key() {
         let length = 16;
-        let key = generate_key(Some(length)).unwrap();
+        let key = generate_key(Some(length), KeyEncoding::Base64).unwrap();
         assert_eq!(
             general_purpose::STANDARD.decode(&key).unwrap().len(),
             length
         );
     }
+
+    #[test]
+    fn test_generate_key_encodings() {
+        let length = 24;
+
+        let hex = String::from_utf8(generate_key(Some(length), KeyEncoding::Hex).unwrap()).unwrap();
+        let decoded: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        assert_eq!(decoded.len(), length);
+
+        let raw = generate_key(Some(length), KeyEncoding::Raw).unwrap();
+        assert_eq!(raw.len(), length);
+    }
 }
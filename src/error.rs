@@ -21,6 +21,15 @@ pub enum ApplicationError {
     #[error("Encoding error: {0}")]
     EncodingError(String),
 
+    #[error("Image too small: need {needed} bytes but only {available} available")]
+    InsufficientCapacity { needed: usize, available: usize },
+
     #[error("Decoding error: {0}")]
     DecodingError(String),
+
+    #[error("Verification error: {0}")]
+    VerificationError(String),
+
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
 }
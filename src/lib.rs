@@ -0,0 +1,12 @@
+//! Text steganography in images: the encoding, encryption and compression behind the
+//! `mindbender` command-line tool
+//!
+//! [`core::operations`] works on files, the way the CLI does, and on images already in
+//! memory with [`core::operations::encode_buffer`] and [`core::operations::decode_buffer`].
+
+pub mod core;
+pub mod cryptography;
+pub mod error;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod steganography;
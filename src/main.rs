@@ -1,74 +1,964 @@
 mod cli;
-mod core;
-mod cryptography;
-mod error;
-mod steganography;
 mod ui;
 
-use clap::Parser;
+#[cfg(feature = "server")]
+use mindbender::server;
+use mindbender::{core, cryptography, error, steganography};
+
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
 use cli::Cli;
 use colored::*;
+use core::activity::ActivityEntry;
+use core::file::ExistingOutput;
+use core::timings::Timings;
+use core::units::SizeUnits;
+use cryptography::util::{KeyEncoding, KeyStrength};
 use error::ApplicationError;
+use serde_json::json;
+use std::path::Path;
+use std::sync::Arc;
+use ui::cli::output::{print_json, OutputFormat};
+use ui::cli::progress::{FileProgress, ProgressTracker};
+
+/// Chi-square probability at which `detect` reports an image as carrying hidden data
+const DETECTION_THRESHOLD: f64 = 0.5;
 
 fn main() {
-    if let Err(e) = run() {
-        eprintln!("{} {}", "Error:".red(), e);
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let format = cli.format;
+
+    if let Err(e) = run(cli, &matches) {
+        match format {
+            OutputFormat::Text => eprintln!("{} {}", "Error:".red(), e),
+            OutputFormat::Json => print_json(&json!({ "error": e.to_string() })),
+        }
         std::process::exit(1);
     }
 }
 
-fn run() -> Result<(), ApplicationError> {
-    let cli = Cli::parse();
+fn run(cli: Cli, matches: &ArgMatches) -> Result<(), ApplicationError> {
+    init_logging(cli.debug, cli.quiet);
 
     match cli.command {
-        None => handle_tui_mode(),                 // @todo no args present => TUI
-        Some(command) => handle_cli_mode(command), // Args present => CLI
+        None => handle_tui_mode(),
+        Some(command) => {
+            let command = match (&cli.config, matches.subcommand()) {
+                (Some(config_path), Some((_, command_matches))) => {
+                    command.with_config(&core::config::load(config_path)?, command_matches)
+                }
+                _ => command,
+            };
+            // Args present => CLI
+            let mut entry = activity_entry(&command);
+            let progress_file = cli.progress_to_file.as_deref();
+            let timings = cli.timings.then(|| Arc::new(Timings::default()));
+            let result = handle_cli_mode(
+                command,
+                cli.size_units,
+                cli.format,
+                progress_file,
+                timings.as_ref(),
+                cli.dry_run,
+                cli.quiet,
+            )
+            .map(|payload_bytes| {
+                entry.payload_bytes = payload_bytes;
+            });
+            // Reported even when the operation failed, to show where it got to
+            if let Some(timings) = timings.filter(|timings| !timings.phases().is_empty()) {
+                eprint!("{}", timings.report());
+            }
+            match &cli.log_file {
+                Some(log_path) => {
+                    let logged = core::activity::append(log_path, &entry, &result);
+                    result.and(logged)
+                }
+                None => result,
+            }
+        }
     }
 }
 
-// @todo launch tui
+/// Log to stderr at a level set by how many times `--debug` was given:
+/// once for info, twice for debug, three times for trace; `--quiet` drops warnings
+fn init_logging(verbosity: u8, quiet: bool) {
+    let level = match verbosity {
+        0 if quiet => log::LevelFilter::Error,
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_module(env!("CARGO_CRATE_NAME"), level)
+        .format_timestamp_millis()
+        .init();
+}
+
+/// No subcommand was given, which is reserved for the interactive interface
+///
+/// The TUI is not part of this build yet, so point the user at the subcommands instead
 fn handle_tui_mode() -> Result<(), ApplicationError> {
-    todo!()
+    Cli::command().print_help()?;
+    eprintln!(
+        "\n{}",
+        "The interactive interface is not available yet; run one of the commands above.".yellow()
+    );
+    Ok(())
+}
+
+/// Terminal progress spinner, also recording to `progress_file` when one is given
+///
+/// The spinner is hidden in JSON mode, leaving the terminal to the JSON result, and with
+/// `--quiet`, which silences warnings as well. With `--timings`, phase durations are
+/// added to `timings`
+fn progress_tracker(
+    progress_file: Option<&Path>,
+    timings: Option<&Arc<Timings>>,
+    format: OutputFormat,
+    quiet: bool,
+) -> Result<ProgressTracker, ApplicationError> {
+    let tracker = match format {
+        _ if quiet => ProgressTracker::quiet(),
+        OutputFormat::Text => ProgressTracker::new(),
+        OutputFormat::Json => ProgressTracker::hidden(),
+    };
+    let tracker = match progress_file {
+        Some(path) => tracker.with_file(FileProgress::create(path)?),
+        None => tracker,
+    };
+    Ok(match timings {
+        Some(timings) => tracker.with_timings(Arc::clone(timings)),
+        None => tracker,
+    })
+}
+
+/// Key from `--key-file`, or from `--key`/`--key-stdin` (see [`ui::cli::prompt::resolve_key`])
+fn resolve_key(
+    key: Option<String>,
+    key_stdin: bool,
+    key_file: Option<String>,
+    confirm: bool,
+) -> Result<Option<Vec<u8>>, ApplicationError> {
+    match key_file {
+        Some(path) => core::file::read_key(&path).map(Some),
+        None => ui::cli::prompt::resolve_key(key, key_stdin, confirm)
+            .map(|key| key.map(String::into_bytes)),
+    }
+}
+
+/// Pixel shuffling seed: the explicit `--seed`, or one derived from the key with `--scatter`
+fn layout_seed(seed: Option<u64>, scatter: bool, key: Option<&[u8]>) -> Option<u64> {
+    match key {
+        Some(key) if scatter => Some(cryptography::util::derive_seed(key)),
+        _ => seed,
+    }
+}
+
+/// Rate the encryption key, if any, failing on a weak one under `--require-strong-key`
+fn key_strength(
+    key: Option<&[u8]>,
+    require_strong: bool,
+) -> Result<Option<KeyStrength>, ApplicationError> {
+    let strength = key.map(cryptography::util::assess_key_strength);
+    if require_strong && strength == Some(KeyStrength::Weak) {
+        return Err(ApplicationError::EncryptionError(
+            "Key is weak; use at least 16 characters mixing letters, digits and symbols, or run generate-key"
+                .to_string(),
+        ));
+    }
+    Ok(strength)
+}
+
+/// Print the key's [`KeyStrength`] after an encode, in yellow when it is weak
+fn print_key_strength(strength: Option<KeyStrength>) {
+    match strength {
+        Some(KeyStrength::Weak) => println!("{}", "Key strength: weak".yellow()),
+        Some(strength) => println!("Key strength: {}", strength.as_str()),
+        None => {}
+    }
+}
+
+/// What happens to an existing output file under `--no-clobber` and `--backup`
+fn existing_output(no_clobber: bool, backup: bool) -> ExistingOutput {
+    match (no_clobber, backup) {
+        (true, _) => ExistingOutput::Refuse,
+        (_, true) => ExistingOutput::Backup,
+        _ => ExistingOutput::Overwrite,
+    }
 }
 
-fn handle_cli_mode(command: cli::Commands) -> Result<(), ApplicationError> {
+/// Describe a command for the activity log, before it runs
+fn activity_entry(command: &cli::Commands) -> ActivityEntry {
     use cli::Commands;
-    use ui::cli::progress::ProgressTracker;
 
     match command {
         Commands::Encode {
             data_path,
+            carrier_path,
+            data_flag,
+            carrier_flag,
+            output_path,
+            ..
+        } => ActivityEntry {
+            carrier: cli::resolve_encode_inputs(
+                data_path.as_deref(),
+                carrier_path.as_deref(),
+                data_flag.as_deref(),
+                carrier_flag.as_deref(),
+            )
+            .ok()
+            .map(|(_, carrier)| carrier),
+            output: Some(output_path.clone()),
+            ..ActivityEntry::new("encode")
+        },
+        Commands::BatchEncode {
+            carrier_dir,
+            output_dir,
+            ..
+        } => ActivityEntry {
+            carrier: Some(carrier_dir.clone()),
+            output: Some(output_dir.clone()),
+            ..ActivityEntry::new("batch-encode")
+        },
+        Commands::Decode {
             carrier_path,
             output_path,
+            hexdump,
+            verify_against,
+            ..
+        } => ActivityEntry {
+            carrier: Some(carrier_path.clone()),
+            output: (!hexdump && verify_against.is_none()).then(|| output_path.clone()),
+            ..ActivityEntry::new("decode")
+        },
+        Commands::Verify { carrier_path, .. } => ActivityEntry {
+            carrier: Some(carrier_path.clone()),
+            ..ActivityEntry::new("verify")
+        },
+        Commands::Detect { carrier_path } => ActivityEntry {
+            carrier: Some(carrier_path.clone()),
+            ..ActivityEntry::new("detect")
+        },
+        Commands::Inspect { carrier_path } => ActivityEntry {
+            carrier: Some(carrier_path.clone()),
+            ..ActivityEntry::new("inspect")
+        },
+        Commands::DumpHeader { carrier_path, .. } => ActivityEntry {
+            carrier: Some(carrier_path.clone()),
+            ..ActivityEntry::new("dump-header")
+        },
+        Commands::Capacity { carrier_path } => ActivityEntry {
+            carrier: Some(carrier_path.clone()),
+            ..ActivityEntry::new("capacity")
+        },
+        #[cfg(feature = "server")]
+        Commands::Serve { .. } => ActivityEntry::new("serve"),
+        Commands::GenerateKey { output, .. } => ActivityEntry {
+            output: output.as_ref().map(|path| path.display().to_string()),
+            ..ActivityEntry::new("generate-key")
+        },
+    }
+}
+
+/// Run a CLI command, returning the size of the message it embedded or extracted, if any
+fn handle_cli_mode(
+    command: cli::Commands,
+    units: SizeUnits,
+    format: OutputFormat,
+    progress_file: Option<&Path>,
+    timings: Option<&Arc<Timings>>,
+    dry_run: bool,
+    quiet: bool,
+) -> Result<Option<usize>, ApplicationError> {
+    use cli::Commands;
+    use core::operations::{
+        CompressionOptions, DecodeFormat, DecodeOptions, Decoy, EncodeOptions, EncryptionOptions,
+        Progress, UnusedBits,
+    };
+    use core::units::format_size;
+    use steganography::header::Header;
+    use steganography::layout::Layout;
+    use steganography::lsb::RawFraming;
+
+    match command {
+        Commands::Encode {
+            data_path,
+            carrier_path,
+            data_flag,
+            carrier_flag,
+            split,
+            output_path,
+            output_format,
+            recursive,
             key,
+            key_stdin,
+            key_file,
+            raw_key,
+            require_strong_key,
+            bind_carrier,
+            cipher,
+            kdf_cost,
             compress,
+            no_compress: _,
+            compression,
+            compression_level,
+            headerless,
+            palette_safe,
+            seed,
+            scatter,
+            stride,
+            depth,
+            use_alpha,
+            region,
+            unused_bits,
+            fill,
+            decoy,
+            duress_key,
+            min_psnr,
+            diff_map,
+            keep_metadata,
+            streaming,
+            canonicalize_output,
+            no_clobber,
+            backup,
         } => {
-            let progress = ProgressTracker::new();
-            core::operations::encode(
+            let (data_path, carrier_path) = cli::resolve_encode_inputs(
+                data_path.as_deref(),
+                carrier_path.as_deref(),
+                data_flag.as_deref(),
+                carrier_flag.as_deref(),
+            )?;
+            if key_stdin && data_path == core::file::STDIO_SENTINEL {
+                return Err(ApplicationError::InvalidPathError(
+                    "The message and the key cannot both be read from stdin".to_string(),
+                ));
+            }
+            let key = resolve_key(key, key_stdin, key_file, true)?;
+            let key_strength = key_strength(key.as_deref(), require_strong_key)?;
+            let progress = progress_tracker(progress_file, timings, format, quiet)?;
+            let seed = layout_seed(seed, scatter, key.as_deref());
+            let options = EncodeOptions {
+                key,
+                encryption: EncryptionOptions {
+                    raw_key,
+                    cipher: Some(cipher),
+                    bind_carrier,
+                    kdf_cost,
+                },
+                compression: (compress || compression.is_some() || compression_level.is_some())
+                    .then(|| CompressionOptions {
+                        algorithm: compression.unwrap_or_default(),
+                        level: compression_level,
+                    }),
+                headerless,
+                layout: Layout {
+                    palette_safe,
+                    seed,
+                    stride,
+                    depth,
+                    use_alpha,
+                    region,
+                    ..Layout::default()
+                },
+                decoy: decoy.zip(duress_key).map(|(data_path, key)| Decoy {
+                    data_path,
+                    key: key.into_bytes(),
+                }),
+                unused_bits: if fill {
+                    UnusedBits::Randomize
+                } else {
+                    unused_bits
+                },
+                keep_metadata,
+                dry_run,
+                existing_output: existing_output(no_clobber, backup),
+                output_format,
+                recursive,
+                min_psnr,
+                diff_map,
+                streaming,
+            };
+            if !split.is_empty() {
+                let carrier_paths: Vec<String> =
+                    std::iter::once(carrier_path).chain(split).collect();
+                let summaries = core::operations::encode_split(
+                    &data_path,
+                    &carrier_paths,
+                    &output_path,
+                    &options,
+                    &progress,
+                )?;
+                let payload_bytes = summaries.iter().map(|summary| summary.payload_bytes).sum();
+                match format {
+                    OutputFormat::Text if quiet => {}
+                    OutputFormat::Text => {
+                        for summary in &summaries {
+                            println!(
+                                "Embedded {} of {} available capacity => {}{}",
+                                format_size(summary.payload_bytes, units),
+                                format_size(summary.capacity_bytes, units),
+                                summary.output_path.display(),
+                                if dry_run {
+                                    " (dry run, not written)"
+                                } else {
+                                    ""
+                                }
+                            );
+                        }
+                        print_key_strength(key_strength);
+                    }
+                    OutputFormat::Json => print_json(&json!({
+                        "operation": "encode",
+                        "bytes": payload_bytes,
+                        "compressed": summaries.iter().any(|summary| summary.compressed),
+                        "key_strength": key_strength.map(|strength| strength.as_str()),
+                        "dry_run": dry_run,
+                        "shards": summaries
+                            .iter()
+                            .map(|summary| json!({
+                                "output": summary.output_path,
+                                "bytes": summary.payload_bytes,
+                                "capacity": summary.capacity_bytes,
+                            }))
+                            .collect::<Vec<_>>(),
+                    })),
+                }
+                return Ok(Some(payload_bytes));
+            }
+            let summary = core::operations::encode(
                 &data_path,
                 &carrier_path,
                 &output_path,
+                &options,
+                &progress,
+            )?;
+            // A dry run writes nothing, so there is no file to resolve
+            let canonical_output = (canonicalize_output && !dry_run)
+                .then(|| core::file::canonical_path(&summary.output_path))
+                .transpose()?;
+            match format {
+                OutputFormat::Text => {
+                    if !quiet {
+                        println!(
+                            "Embedded {} of {} available capacity",
+                            format_size(summary.payload_bytes, units),
+                            format_size(summary.capacity_bytes, units)
+                        );
+                        print_key_strength(key_strength);
+                    }
+                    if dry_run && !quiet {
+                        println!("Dry run: would write {}", summary.output_path.display());
+                    }
+                    if headerless && !quiet {
+                        println!(
+                            "{}",
+                            format!(
+                                "Headerless payload is {} bytes; decode with --headerless --length {}",
+                                summary.payload_bytes, summary.payload_bytes
+                            )
+                            .yellow()
+                        );
+                    }
+                    if let Some(path) = canonical_output {
+                        println!("Output: {}", path.display());
+                    }
+                }
+                OutputFormat::Json => print_json(&json!({
+                    "operation": "encode",
+                    "output": canonical_output.unwrap_or(summary.output_path),
+                    "bytes": summary.payload_bytes,
+                    "capacity": summary.capacity_bytes,
+                    "compressed": summary.compressed,
+                    "headerless": headerless,
+                    "key_strength": key_strength.map(|strength| strength.as_str()),
+                    "dry_run": dry_run,
+                })),
+            }
+            Ok(Some(summary.payload_bytes))
+        }
+        Commands::BatchEncode {
+            data_path,
+            carrier_dir,
+            output_dir,
+            key,
+            key_stdin,
+            key_file,
+            raw_key,
+            require_strong_key,
+            cipher,
+            kdf_cost,
+            compress,
+            no_compress: _,
+            compression,
+            compression_level,
+        } => {
+            if key_stdin && data_path == core::file::STDIO_SENTINEL {
+                return Err(ApplicationError::InvalidPathError(
+                    "The message and the key cannot both be read from stdin".to_string(),
+                ));
+            }
+            let key = resolve_key(key, key_stdin, key_file, true)?;
+            key_strength(key.as_deref(), require_strong_key)?;
+            let progress = progress_tracker(progress_file, timings, format, quiet)?;
+            let options = EncodeOptions {
                 key,
-                compress,
+                encryption: EncryptionOptions {
+                    raw_key,
+                    cipher: Some(cipher),
+                    kdf_cost,
+                    ..EncryptionOptions::default()
+                },
+                compression: (compress || compression.is_some() || compression_level.is_some())
+                    .then(|| CompressionOptions {
+                        algorithm: compression.unwrap_or_default(),
+                        level: compression_level,
+                    }),
+                dry_run,
+                ..EncodeOptions::default()
+            };
+            let entries = core::operations::batch_encode(
+                &data_path,
+                &carrier_dir,
+                &output_dir,
+                &options,
                 &progress,
-            )
+            )?;
+
+            let failed: Vec<_> = entries
+                .iter()
+                .filter(|entry| entry.result.is_err() && !entry.is_skipped())
+                .collect();
+            let skipped = entries.iter().filter(|entry| entry.is_skipped()).count();
+            let encoded = entries.len() - failed.len() - skipped;
+            match format {
+                OutputFormat::Text if quiet => {}
+                OutputFormat::Text => {
+                    for entry in &entries {
+                        match &entry.result {
+                            Ok(summary) => println!(
+                                "{} {} => {}",
+                                "Encoded".green(),
+                                entry.carrier_path.display(),
+                                summary.output_path.display()
+                            ),
+                            Err(e) if entry.is_skipped() => {
+                                println!(
+                                    "{} {}: {}",
+                                    "Skipped".yellow(),
+                                    entry.carrier_path.display(),
+                                    e
+                                )
+                            }
+                            Err(e) => println!(
+                                "{} {}: {}",
+                                "Failed".red(),
+                                entry.carrier_path.display(),
+                                e
+                            ),
+                        }
+                    }
+                    println!(
+                        "Encoded {} of {} carriers ({} skipped, {} failed)",
+                        encoded,
+                        entries.len(),
+                        skipped,
+                        failed.len()
+                    );
+                    if dry_run {
+                        println!("Dry run: nothing was written to {}", output_dir);
+                    }
+                }
+                // A failed batch is reported as an error object instead, keeping stdout to one value
+                OutputFormat::Json if failed.is_empty() => print_json(&json!({
+                    "operation": "batch-encode",
+                    "encoded": encoded,
+                    "skipped": skipped,
+                    "dry_run": dry_run,
+                    "carriers": entries
+                        .iter()
+                        .map(|entry| match &entry.result {
+                            Ok(summary) => json!({
+                                "carrier": entry.carrier_path,
+                                "output": summary.output_path,
+                                "bytes": summary.payload_bytes,
+                            }),
+                            Err(e) => json!({
+                                "carrier": entry.carrier_path,
+                                "skipped": e.to_string(),
+                            }),
+                        })
+                        .collect::<Vec<_>>(),
+                })),
+                OutputFormat::Json => {}
+            }
+
+            if !failed.is_empty() {
+                return Err(ApplicationError::EncodingError(format!(
+                    "{} of {} carriers failed: {}",
+                    failed.len(),
+                    entries.len(),
+                    failed
+                        .iter()
+                        .map(|entry| entry.carrier_path.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )));
+            }
+            Ok(entries
+                .iter()
+                .find_map(|entry| entry.result.as_ref().ok())
+                .map(|summary| summary.payload_bytes))
         }
         Commands::Decode {
             carrier_path,
             output_path,
             key,
+            key_stdin,
+            key_file,
+            raw_key,
+            bind_carrier,
+            cipher,
             decompress,
+            hexdump,
+            width,
+            headerless: _,
+            length,
+            palette_safe,
+            seed,
+            region,
+            scatter,
+            canonicalize_output,
+            no_clobber,
+            backup,
+            verify_against,
+            join,
+            legacy_delimiter,
+            lossy_utf8,
+            message_format,
+            delimiter,
         } => {
-            let progress = ProgressTracker::new();
-            core::operations::decode(&carrier_path, &output_path, key, decompress, &progress)
+            let raw_lsb = match (message_format, delimiter) {
+                (DecodeFormat::Mindbender, None) => None,
+                (DecodeFormat::Mindbender, Some(_)) => {
+                    return Err(ApplicationError::DecodingError(
+                        "--delimiter only applies to --message-format raw-lsb".to_string(),
+                    ))
+                }
+                (DecodeFormat::RawLsb, None) => Some(RawFraming::LengthPrefix),
+                (DecodeFormat::RawLsb, Some(delimiter)) => {
+                    Some(RawFraming::Delimiter(delimiter.into_bytes()))
+                }
+            };
+            let key = resolve_key(key, key_stdin, key_file, false)?;
+            let progress = progress_tracker(progress_file, timings, format, quiet)?;
+            let seed = layout_seed(seed, scatter, key.as_deref());
+            let options = DecodeOptions {
+                key,
+                encryption: EncryptionOptions {
+                    raw_key,
+                    cipher,
+                    bind_carrier,
+                    ..EncryptionOptions::default()
+                },
+                decompress,
+                headerless_length: length,
+                legacy_delimiter,
+                layout: Layout {
+                    palette_safe,
+                    seed,
+                    region,
+                    ..Layout::default()
+                },
+                join,
+                lossy_utf8,
+                dry_run,
+                existing_output: existing_output(no_clobber, backup),
+                raw_lsb,
+            };
+            if let Some(reference_path) = verify_against {
+                let reference = core::file::read_bytes(&reference_path)?;
+                let message =
+                    core::operations::decode_message_bytes(&carrier_path, &options, &progress)?;
+                progress.finish_with_message("Decoding completed successfully");
+                core::compare::verify_bytes(&message, &reference)?;
+                match format {
+                    OutputFormat::Text => println!("Decoded message matches {}", reference_path),
+                    OutputFormat::Json => print_json(&json!({
+                        "operation": "decode",
+                        "bytes": message.len(),
+                        "matches": reference_path,
+                    })),
+                }
+                Ok(Some(message.len()))
+            } else if hexdump {
+                let message =
+                    core::operations::decode_message_bytes(&carrier_path, &options, &progress)?;
+                progress.finish_with_message("Decoding completed successfully");
+                let dump = ui::cli::hexdump::hexdump(&message, width);
+                match format {
+                    OutputFormat::Text => print!("{}", dump),
+                    OutputFormat::Json => print_json(&json!({
+                        "operation": "decode",
+                        "bytes": message.len(),
+                        "hexdump": dump,
+                    })),
+                }
+                Ok(Some(message.len()))
+            } else if format == OutputFormat::Json
+                && output_path == core::file::STDIO_SENTINEL
+                && !dry_run
+            {
+                // The message goes inside the JSON object, so stdout stays a single value
+                let message = core::operations::decode_message(&carrier_path, &options, &progress)?;
+                progress.finish_with_message("Decoding completed successfully");
+                print_json(&json!({
+                    "operation": "decode",
+                    "bytes": message.len(),
+                    "message": message,
+                }));
+                Ok(Some(message.len()))
+            } else {
+                let message_bytes =
+                    core::operations::decode(&carrier_path, &output_path, &options, &progress)?;
+                let canonical_output =
+                    (canonicalize_output && output_path != core::file::STDIO_SENTINEL && !dry_run)
+                        .then(|| core::file::canonical_path(&output_path))
+                        .transpose()?;
+                match format {
+                    OutputFormat::Text => {
+                        if dry_run && !quiet {
+                            println!(
+                                "Dry run: would write {} to {}",
+                                format_size(message_bytes, units),
+                                output_path
+                            );
+                        }
+                        if let Some(path) = canonical_output {
+                            println!("Output: {}", path.display());
+                        }
+                    }
+                    OutputFormat::Json => print_json(&json!({
+                        "operation": "decode",
+                        "output": canonical_output.unwrap_or_else(|| output_path.into()),
+                        "bytes": message_bytes,
+                        "dry_run": dry_run,
+                    })),
+                }
+                Ok(Some(message_bytes))
+            }
         }
-        Commands::GenerateKey { length, output } => {
-            let key = cryptography::util::generate_key(length)?;
-            match output {
-                Some(path) => core::file::write_text(&key, path.to_str().unwrap())?,
-                None => println!("Generated key: {}", key),
+        Commands::Verify {
+            carrier_path,
+            key,
+            key_stdin,
+            key_file,
+            raw_key,
+            bind_carrier,
+            decompress,
+            palette_safe,
+            seed,
+            region,
+            scatter,
+            join,
+        } => {
+            let key = resolve_key(key, key_stdin, key_file, false)?;
+            let progress = progress_tracker(progress_file, timings, format, quiet)?;
+            let seed = layout_seed(seed, scatter, key.as_deref());
+            let options = DecodeOptions {
+                key,
+                encryption: EncryptionOptions {
+                    raw_key,
+                    cipher: None,
+                    bind_carrier,
+                    ..EncryptionOptions::default()
+                },
+                decompress,
+                headerless_length: None,
+                legacy_delimiter: false,
+                layout: Layout {
+                    palette_safe,
+                    seed,
+                    region,
+                    ..Layout::default()
+                },
+                join,
+                lossy_utf8: false,
+                dry_run: false,
+                existing_output: ExistingOutput::Overwrite,
+                raw_lsb: None,
+            };
+            let message = core::operations::decode_message(&carrier_path, &options, &progress)?;
+            progress.finish_with_message("Verification completed successfully");
+            match format {
+                OutputFormat::Text => println!(
+                    "{} contains a decodable message ({} bytes)",
+                    carrier_path,
+                    message.len()
+                ),
+                OutputFormat::Json => print_json(&json!({
+                    "operation": "verify",
+                    "carrier": carrier_path,
+                    "bytes": message.len(),
+                })),
+            }
+            Ok(Some(message.len()))
+        }
+        Commands::Detect { carrier_path } => {
+            let image = core::image::load_image(&carrier_path)?;
+            let probability = steganography::analysis::lsb_chi_square(&image);
+            let suspicious = probability >= DETECTION_THRESHOLD;
+            match format {
+                OutputFormat::Text => {
+                    println!("Probability of hidden data: {:.1}%", probability * 100.0);
+                    if suspicious {
+                        println!(
+                            "{}",
+                            "The least significant bits look like embedded data".yellow()
+                        );
+                    } else {
+                        println!("{}", "No sign of LSB embedding".green());
+                    }
+                }
+                OutputFormat::Json => print_json(&json!({
+                    "operation": "detect",
+                    "carrier": carrier_path,
+                    "probability": probability,
+                    "suspicious": suspicious,
+                })),
+            }
+            Ok(None)
+        }
+        Commands::Inspect { carrier_path } => {
+            let info = core::operations::inspect(&carrier_path)?;
+            match format {
+                OutputFormat::Text => {
+                    println!("Dimensions: {}x{}", info.width, info.height);
+                    println!(
+                        "Format: {:?} ({})",
+                        info.format,
+                        if info.is_lossless {
+                            "lossless"
+                        } else {
+                            "lossy"
+                        }
+                    );
+                    println!("Capacity: {}", format_size(info.capacity_bytes, units));
+                    if info.has_payload {
+                        println!("{}", "Holds an embedded message".yellow());
+                    } else {
+                        println!("{}", "No embedded message found".green());
+                    }
+                }
+                OutputFormat::Json => print_json(&json!({
+                    "operation": "inspect",
+                    "carrier": carrier_path,
+                    "width": info.width,
+                    "height": info.height,
+                    "format": format!("{:?}", info.format),
+                    "lossless": info.is_lossless,
+                    "capacity_bytes": info.capacity_bytes,
+                    "has_payload": info.has_payload,
+                })),
+            }
+            Ok(None)
+        }
+        Commands::DumpHeader {
+            carrier_path,
+            palette_safe,
+            seed,
+            region,
+        } => {
+            let layout = Layout {
+                palette_safe,
+                seed,
+                region,
+                ..Layout::default()
+            };
+            let raw = match core::image::load_carrier(&carrier_path)? {
+                core::image::Carrier::Gray(image) => {
+                    steganography::lsb::read_header_bytes(&image, &layout)
+                }
+                core::image::Carrier::Color(image) => {
+                    steganography::lsb::read_header_bytes(&image, &layout)
+                }
+            };
+            let header = Header::parse(&raw).ok_or_else(|| {
+                ApplicationError::DecodingError(
+                    "No header found (legacy, headerless, or not a mindbender image)".to_string(),
+                )
+            })??;
+            let raw = &raw[..header.size().min(raw.len())];
+            match format {
+                OutputFormat::Text => print!("{}", ui::cli::header::dump_header(raw, &header)),
+                OutputFormat::Json => print_json(&ui::cli::header::header_json(raw, &header)),
+            }
+            Ok(None)
+        }
+        Commands::Capacity { carrier_path } => {
+            let capacity = core::operations::capacity(&carrier_path)?;
+            match format {
+                OutputFormat::Text => {
+                    println!(
+                        "Raw capacity:      {}",
+                        format_size(capacity.raw_bytes, units)
+                    );
+                    println!(
+                        "Usable capacity:   {} after the {}-byte header",
+                        format_size(capacity.usable_bytes, units),
+                        capacity.header_bytes
+                    );
+                    println!(
+                        "Encrypted message: {} at most, with a passphrase",
+                        format_size(capacity.encrypted_bytes, units)
+                    );
+                }
+                OutputFormat::Json => print_json(&json!({
+                    "operation": "capacity",
+                    "carrier": carrier_path,
+                    "raw_bytes": capacity.raw_bytes,
+                    "header_bytes": capacity.header_bytes,
+                    "usable_bytes": capacity.usable_bytes,
+                    "encrypted_bytes": capacity.encrypted_bytes,
+                })),
+            }
+            Ok(None)
+        }
+        #[cfg(feature = "server")]
+        Commands::Serve { listen } => server::serve(&listen).map(|_| None),
+        Commands::GenerateKey {
+            length,
+            encoding,
+            output,
+        } => {
+            use std::io::{IsTerminal, Write};
+
+            let key = cryptography::util::generate_key(length, encoding)?;
+            let raw = encoding == KeyEncoding::Raw;
+            match (output, format) {
+                (Some(path), OutputFormat::Text) => core::file::write_bytes(&key, &path)?,
+                (Some(path), OutputFormat::Json) => {
+                    core::file::write_bytes(&key, &path)?;
+                    print_json(&json!({ "operation": "generate-key", "output": path }));
+                }
+                (None, format)
+                    if raw && (format == OutputFormat::Json || std::io::stdout().is_terminal()) =>
+                {
+                    return Err(ApplicationError::InvalidPathError(
+                        "A raw key is binary; save it with --output or pipe it to a file"
+                            .to_string(),
+                    ));
+                }
+                (None, OutputFormat::Text) if raw => std::io::stdout().write_all(&key)?,
+                (None, OutputFormat::Text) => {
+                    println!("Generated key: {}", String::from_utf8_lossy(&key))
+                }
+                (None, OutputFormat::Json) => print_json(
+                    &json!({ "operation": "generate-key", "key": String::from_utf8_lossy(&key) }),
+                ),
             }
-            Ok(())
+            Ok(None)
         }
     }
 }
@@ -0,0 +1,194 @@
+//! TCP daemon exposing encode/decode over a simple length-prefixed protocol
+//!
+//! Every request is a single message on its own connection:
+//!
+//! | Field   | Size     | Description                                              |
+//! |---------|----------|----------------------------------------------------------|
+//! | op      | 1 byte   | `0x01` encode, `0x02` decode                             |
+//! | flags   | 1 byte   | bit 0: compress (encode) / decompress (decode)           |
+//! | key     | frame    | Encryption key bytes, empty for none                     |
+//! | carrier | frame    | Carrier image bytes in any supported format              |
+//! | payload | frame    | Message to hide as UTF-8 (encode only)                   |
+//!
+//! A frame is a 4-byte big-endian length followed by that many bytes.
+//!
+//! The response is a 1-byte status (`0x00` success, `0x01` error) followed by
+//! one frame holding the encoded PNG, the decoded message, or the error text.
+
+use crate::core::operations::{self, CompressionOptions, DecodeOptions, EncodeOptions};
+use crate::error::ApplicationError;
+use image::{ImageFormat, RgbImage};
+use std::io::{Cursor, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+pub const OP_ENCODE: u8 = 0x01;
+pub const OP_DECODE: u8 = 0x02;
+pub const FLAG_COMPRESS: u8 = 0b0000_0001;
+pub const STATUS_OK: u8 = 0x00;
+pub const STATUS_ERROR: u8 = 0x01;
+
+/// Upper bound on a single frame to keep a malicious client from exhausting memory
+const MAX_FRAME_SIZE: usize = 256 * 1024 * 1024;
+
+/// Listen on `addr` and serve encode/decode requests until the process is stopped
+pub fn serve(addr: &str) -> Result<(), ApplicationError> {
+    let listener = TcpListener::bind(addr)?;
+    println!("Listening on {}", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || {
+                    if let Err(e) = handle_connection(stream) {
+                        eprintln!("Connection error: {}", e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("Connection error: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Read one request from the stream and write back its response
+fn handle_connection(mut stream: TcpStream) -> Result<(), ApplicationError> {
+    let response = handle_request(&mut stream);
+
+    match response {
+        Ok(body) => {
+            stream.write_all(&[STATUS_OK])?;
+            write_frame(&mut stream, &body)?;
+        }
+        Err(e) => {
+            stream.write_all(&[STATUS_ERROR])?;
+            write_frame(&mut stream, e.to_string().as_bytes())?;
+        }
+    }
+
+    stream.flush()?;
+    Ok(())
+}
+
+fn handle_request(stream: &mut impl Read) -> Result<Vec<u8>, ApplicationError> {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header)?;
+    let [op, flags] = header;
+    let compress = flags & FLAG_COMPRESS != 0;
+
+    let key = read_frame(stream)?;
+    let key = (!key.is_empty()).then_some(key);
+    let carrier = load_image_from_bytes(&read_frame(stream)?)?;
+
+    match op {
+        OP_ENCODE => {
+            let message = read_frame(stream)?;
+            let options = EncodeOptions {
+                key,
+                compression: compress.then(CompressionOptions::default),
+                ..EncodeOptions::default()
+            };
+            write_png_bytes(&operations::encode_buffer(&message, &carrier, &options)?)
+        }
+        OP_DECODE => {
+            let options = DecodeOptions {
+                key,
+                decompress: compress,
+                ..DecodeOptions::default()
+            };
+            operations::decode_buffer(&carrier, &options)
+        }
+        _ => Err(ApplicationError::DecodingError(format!(
+            "Unknown operation 0x{:02x}",
+            op
+        ))),
+    }
+}
+
+/// Decode an in-memory image of any supported format and convert it to RgbImage format
+fn load_image_from_bytes(bytes: &[u8]) -> Result<RgbImage, ApplicationError> {
+    Ok(image::load_from_memory(bytes)?.to_rgb8())
+}
+
+/// Encode image data as an in-memory PNG
+fn write_png_bytes(image: &RgbImage) -> Result<Vec<u8>, ApplicationError> {
+    let mut bytes = Cursor::new(Vec::new());
+    image.write_to(&mut bytes, ImageFormat::Png)?;
+
+    Ok(bytes.into_inner())
+}
+
+/// Read a 4-byte big-endian length followed by that many bytes
+pub fn read_frame(stream: &mut impl Read) -> Result<Vec<u8>, ApplicationError> {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+
+    if length > MAX_FRAME_SIZE {
+        return Err(ApplicationError::DecodingError(format!(
+            "Frame of {} bytes exceeds maximum of {} bytes",
+            length, MAX_FRAME_SIZE
+        )));
+    }
+
+    let mut buffer = vec![0u8; length];
+    stream.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Write a 4-byte big-endian length followed by the bytes themselves
+pub fn write_frame(stream: &mut impl Write, bytes: &[u8]) -> Result<(), ApplicationError> {
+    let length = u32::try_from(bytes.len()).map_err(|_| {
+        ApplicationError::EncodingError("Frame too large for the protocol".to_string())
+    })?;
+    stream.write_all(&length.to_be_bytes())?;
+    stream.write_all(bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    fn request(op: u8, flags: u8, key: &str, carrier: &[u8], payload: Option<&[u8]>) -> Vec<u8> {
+        let mut bytes = vec![op, flags];
+        write_frame(&mut bytes, key.as_bytes()).unwrap();
+        write_frame(&mut bytes, carrier).unwrap();
+        if let Some(payload) = payload {
+            write_frame(&mut bytes, payload).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        let mut bytes = Vec::new();
+        write_frame(&mut bytes, b"frame").unwrap();
+
+        assert_eq!(&bytes[..4], &[0, 0, 0, 5]);
+        assert_eq!(read_frame(&mut Cursor::new(bytes)).unwrap(), b"frame");
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        let carrier = RgbImage::from_pixel(20, 20, Rgb([128, 64, 32]));
+        let carrier = write_png_bytes(&carrier).unwrap();
+
+        let encode = request(OP_ENCODE, FLAG_COMPRESS, "key", &carrier, Some(b"Hi!"));
+        let encoded = handle_request(&mut Cursor::new(encode)).expect("Encode failed");
+
+        let decode = request(OP_DECODE, FLAG_COMPRESS, "key", &encoded, None);
+        let decoded = handle_request(&mut Cursor::new(decode)).expect("Decode failed");
+
+        assert_eq!(decoded, b"Hi!");
+    }
+
+    #[test]
+    fn test_unknown_operation() {
+        let bytes = request(0x7f, 0, "", &[], None);
+
+        assert!(handle_request(&mut Cursor::new(bytes)).is_err());
+    }
+}
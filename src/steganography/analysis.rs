@@ -0,0 +1,362 @@
+use super::header::MAX_DEPTH;
+use image::{ImageBuffer, Pixel, Rgb, RgbImage};
+use rayon::prelude::*;
+
+/// Computes a Sobel gradient magnitude for every pixel, in raster order
+///
+/// The low [`MAX_DEPTH`] bits of each channel are ignored, so the map is the same before
+/// and after embedding at any depth and a decoder can reproduce it from the encoded image.
+/// Alpha is ignored as well, so an RGBA carrier yields the same map as its RGB pixels
+pub fn sobel_salience<P: Pixel<Subpixel = u8>>(image: &ImageBuffer<P, Vec<u8>>) -> Vec<u32> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let embedded_bits = (1u8 << MAX_DEPTH) - 1;
+    let intensity: Vec<i32> = image
+        .pixels()
+        .map(|pixel| {
+            pixel
+                .to_rgb()
+                .0
+                .iter()
+                .map(|&channel| (channel & !embedded_bits) as i32)
+                .sum()
+        })
+        .collect();
+
+    let at = |x: usize, y: usize, dx: isize, dy: isize| -> i32 {
+        let x = x.saturating_add_signed(dx).min(width - 1);
+        let y = y.saturating_add_signed(dy).min(height - 1);
+        intensity[y * width + x]
+    };
+
+    (0..width * height)
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            let gx = at(x, y, 1, -1) + 2 * at(x, y, 1, 0) + at(x, y, 1, 1)
+                - at(x, y, -1, -1)
+                - 2 * at(x, y, -1, 0)
+                - at(x, y, -1, 1);
+            let gy = at(x, y, -1, 1) + 2 * at(x, y, 0, 1) + at(x, y, 1, 1)
+                - at(x, y, -1, -1)
+                - 2 * at(x, y, 0, -1)
+                - at(x, y, 1, -1);
+            gx.unsigned_abs() + gy.unsigned_abs()
+        })
+        .collect()
+}
+
+/// Marks the pixels whose Sobel salience falls within the top `fraction` of the image
+///
+/// Pixels tied with the cutoff are not marked, so a flat image has no salient pixels
+pub fn salient_pixels<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    fraction: f64,
+) -> Vec<bool> {
+    let salience = sobel_salience(image);
+    let cutoff = (salience.len() as f64 * (1.0 - fraction)) as usize;
+
+    if cutoff == 0 {
+        return vec![true; salience.len()];
+    }
+
+    // Largest salience among the least salient pixels; anything above it is marked
+    let mut sorted = salience.clone();
+    let (_, &mut threshold, _) = sorted.select_nth_unstable(cutoff - 1);
+
+    salience.iter().map(|&value| value > threshold).collect()
+}
+
+/// Peak signal-to-noise ratio between two images of the same size, in decibels
+///
+/// Compares the color channels only, like [`lsb_chi_square`]; the higher the ratio, the
+/// less `b` departs from `a`, and identical images give infinity
+pub fn psnr<P: Pixel<Subpixel = u8>>(
+    a: &ImageBuffer<P, Vec<u8>>,
+    b: &ImageBuffer<P, Vec<u8>>,
+) -> f64 {
+    const PEAK: f64 = u8::MAX as f64;
+
+    let (squared_error, samples) = a
+        .pixels()
+        .zip(b.pixels())
+        .flat_map(|(a, b)| a.to_rgb().0.into_iter().zip(b.to_rgb().0))
+        .fold((0u64, 0u64), |(sum, count), (a, b)| {
+            (sum + (a.abs_diff(b) as u64).pow(2), count + 1)
+        });
+
+    if squared_error == 0 {
+        return f64::INFINITY;
+    }
+    let mse = squared_error as f64 / samples as f64;
+    10.0 * (PEAK * PEAK / mse).log10()
+}
+
+/// Highlights in red every pixel of `encoded` that differs from `original`
+///
+/// Unchanged pixels are drawn as a darkened grayscale copy of `original`, so the spread
+/// of the payload stands out against the picture
+pub fn change_map<P: Pixel<Subpixel = u8>>(
+    original: &ImageBuffer<P, Vec<u8>>,
+    encoded: &ImageBuffer<P, Vec<u8>>,
+) -> RgbImage {
+    const CHANGED: Rgb<u8> = Rgb([255, 0, 0]);
+
+    RgbImage::from_fn(original.width(), original.height(), |x, y| {
+        let (before, after) = (original.get_pixel(x, y), encoded.get_pixel(x, y));
+        if before.channels() != after.channels() {
+            CHANGED
+        } else {
+            Rgb([before.to_luma().0[0] / 2; 3])
+        }
+    })
+}
+
+/// Estimates the probability that the image's least significant bits carry hidden data
+///
+/// Uses the chi-square attack of Westfeld and Pfitzmann: LSB embedding swaps values
+/// within each pair `2k`/`2k + 1`, so an image full of random payload bits has
+/// near-equal counts within every pair, while a clean image usually does not. The
+/// result is the chi-square test's p-value for "the pairs are equalized", near 1 for
+/// fully embedded images and near 0 for clean ones. Only the color channels are
+/// counted, and a short message in a large image barely moves the score
+pub fn lsb_chi_square<P: Pixel<Subpixel = u8>>(image: &ImageBuffer<P, Vec<u8>>) -> f64 {
+    let mut histogram = [0u64; 256];
+    for pixel in image.pixels() {
+        for &channel in pixel.to_rgb().0.iter() {
+            histogram[channel as usize] += 1;
+        }
+    }
+
+    let mut statistic = 0.0;
+    let mut pairs = 0;
+    for pair in histogram.chunks_exact(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected > 0.0 {
+            statistic += (pair[0] as f64 - expected).powi(2) / expected;
+            pairs += 1;
+        }
+    }
+
+    if pairs == 0 {
+        return 0.0;
+    }
+    chi_square_survival(statistic, (pairs - 1).max(1) as f64)
+}
+
+/// Probability that a chi-square variable with `degrees` degrees of freedom exceeds `x`
+fn chi_square_survival(x: f64, degrees: f64) -> f64 {
+    1.0 - lower_regularized_gamma(degrees / 2.0, x / 2.0)
+}
+
+/// Regularized lower incomplete gamma function P(a, x)
+///
+/// Uses the series expansion below `a + 1` and a continued fraction above it,
+/// following Numerical Recipes
+fn lower_regularized_gamma(a: f64, x: f64) -> f64 {
+    const ITERATIONS: usize = 500;
+    const EPSILON: f64 = 1e-14;
+    const TINY: f64 = 1e-300;
+
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let prefactor = (a * x.ln() - x - ln_gamma(a)).exp();
+
+    if x < a + 1.0 {
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        for n in 1..ITERATIONS {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        (sum * prefactor).min(1.0)
+    } else {
+        // Modified Lentz evaluation of the continued fraction for Q(a, x)
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / TINY;
+        let mut d = 1.0 / b;
+        let mut fraction = d;
+        for n in 1..ITERATIONS {
+            let an = -(n as f64) * (n as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = b + an / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            fraction *= delta;
+            if (delta - 1.0).abs() < EPSILON {
+                break;
+            }
+        }
+        (1.0 - prefactor * fraction).max(0.0)
+    }
+}
+
+/// Natural logarithm of the gamma function, by the Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000000000190015, |sum, (i, c)| {
+            sum + c / (x + 1.0 + i as f64)
+        });
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::steganography::{
+        header::{PayloadInfo, HEADER_SIZE},
+        layout::Layout,
+        lsb,
+        util::payload_capacity_bits,
+    };
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    fn create_edge_image() -> RgbImage {
+        RgbImage::from_fn(8, 8, |x, _| {
+            if x < 4 {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        })
+    }
+
+    /// Smooth scene captured in 8-bit linear light and then gamma encoded, as a camera
+    /// does, which leaves the uneven value histogram typical of photographs
+    fn create_photo_like_image() -> RgbImage {
+        RgbImage::from_fn(64, 64, |x, y| {
+            let (x, y) = (x as f64 / 64.0, y as f64 / 64.0);
+            let shade = |phase: f64| {
+                let linear = 0.3 + 0.25 * (6.0 * x + phase).sin() + 0.2 * (4.0 * y - phase).cos();
+                let captured = (255.0 * linear.clamp(0.0, 1.0)).round() / 255.0;
+                (255.0 * captured.powf(1.0 / 2.2)).round() as u8
+            };
+            Rgb([shade(0.0), shade(1.0), shade(2.0)])
+        })
+    }
+
+    #[test]
+    fn test_flat_image_has_no_salience() {
+        let image = RgbImage::from_pixel(5, 5, Rgb([120, 120, 120]));
+
+        assert!(sobel_salience(&image).iter().all(|&value| value == 0));
+        assert!(salient_pixels(&image, 0.25).iter().all(|&salient| !salient));
+    }
+
+    #[test]
+    fn test_edge_is_salient() {
+        let image = create_edge_image();
+        let salient = salient_pixels(&image, 0.25);
+
+        assert!(salient[3] && salient[4]);
+        assert!(!salient[0] && !salient[7]);
+    }
+
+    #[test]
+    fn test_salience_ignores_lsb() {
+        let image = create_edge_image();
+        let mut modified = image.clone();
+        modified
+            .pixels_mut()
+            .for_each(|pixel| pixel.0.iter_mut().for_each(|channel| *channel ^= 1));
+
+        assert_eq!(sobel_salience(&image), sobel_salience(&modified));
+    }
+
+    #[test]
+    fn test_chi_square_survival() {
+        // Reference values of the chi-square distribution
+        assert!((chi_square_survival(3.841, 1.0) - 0.05).abs() < 1e-3);
+        assert!((chi_square_survival(18.307, 10.0) - 0.05).abs() < 1e-3);
+        assert!((chi_square_survival(10.0, 10.0) - 0.4405).abs() < 1e-3);
+        assert!((chi_square_survival(150.0, 127.0) - 0.0795).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_chi_square_scores() {
+        let blank = RgbImage::new(64, 64);
+        let photo = create_photo_like_image();
+        let mut encoded = photo.clone();
+        let capacity = payload_capacity_bits(&encoded, &Layout::default(), HEADER_SIZE) / 8;
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+        let message: String = (0..capacity)
+            .map(|_| rng.sample(rand::distributions::Alphanumeric) as char)
+            .collect();
+        lsb::encode(
+            &message,
+            &mut encoded,
+            &Layout::default(),
+            PayloadInfo::default(),
+        )
+        .unwrap();
+
+        assert!(lsb_chi_square(&blank) < 0.01);
+        assert!(lsb_chi_square(&photo) < 0.01);
+        assert!(lsb_chi_square(&encoded) > 0.5);
+    }
+
+    #[test]
+    fn test_psnr() {
+        let image = create_photo_like_image();
+        let mut flipped = image.clone();
+        flipped
+            .pixels_mut()
+            .for_each(|pixel| pixel.0.iter_mut().for_each(|channel| *channel ^= 1));
+
+        assert_eq!(psnr(&image, &image), f64::INFINITY);
+        // Every sample off by one gives an MSE of 1
+        assert!((psnr(&image, &flipped) - 48.13).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_change_map() {
+        let image = create_edge_image();
+        let mut encoded = image.clone();
+        encoded.get_pixel_mut(2, 5).0[1] ^= 1;
+        let map = change_map(&image, &encoded);
+
+        assert_eq!(map.dimensions(), image.dimensions());
+        assert_eq!(*map.get_pixel(2, 5), Rgb([255, 0, 0]));
+        assert_eq!(
+            map.pixels()
+                .filter(|&&pixel| pixel == Rgb([255, 0, 0]))
+                .count(),
+            1
+        );
+        assert_eq!(*map.get_pixel(6, 0), Rgb([127, 127, 127]));
+        assert!(change_map(&image, &image)
+            .pixels()
+            .all(|pixel| pixel.0[0] == pixel.0[1]));
+    }
+
+    #[test]
+    fn test_empty_image() {
+        let image = RgbImage::new(0, 0);
+
+        assert!(salient_pixels(&image, 0.25).is_empty());
+    }
+}
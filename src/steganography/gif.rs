@@ -0,0 +1,357 @@
+//! Embedding across the frames of GIF carriers, animated or not
+//!
+//! GIF pixels are indices into a palette of at most 256 colors, so the payload goes in the
+//! least significant bit of each index rather than of a color channel. Every palette is
+//! first sorted by luminance, so that flipping that bit swaps a pixel's color for the one
+//! next to it in brightness. Pixels in the pair of indices holding a frame's transparent
+//! color are skipped, so transparency never changes.
+//!
+//! The usable indices of all frames, in order, are gathered into a one-row grayscale
+//! buffer and written with [`lsb`] like any other carrier, header included, so the
+//! capacity is the sum over the frames.
+
+use super::header::{Header, PayloadInfo};
+use super::layout::Layout;
+use super::lsb;
+use crate::error::ApplicationError;
+use ::gif::{ColorOutput, DecodeOptions, Encoder, Frame, Repeat};
+use image::GrayImage;
+use std::borrow::Cow;
+use std::io::{Read, Write};
+
+const PALETTE_ENTRY_SIZE: usize = 3;
+
+/// The frames of a GIF with their palettes as indices, everything needed to write it back
+pub struct Animation {
+    pub width: u16,
+    pub height: u16,
+    pub global_palette: Option<Vec<u8>>,
+    pub repeat: Repeat,
+    pub frames: Vec<Frame<'static>>,
+}
+
+impl Animation {
+    /// Reads every frame of a GIF, deinterlaced
+    pub fn read(reader: impl Read) -> Result<Self, ApplicationError> {
+        let mut options = DecodeOptions::new();
+        options.set_color_output(ColorOutput::Indexed);
+        let mut decoder = options.read_info(reader).map_err(decoding_error)?;
+
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().map_err(decoding_error)? {
+            frames.push(frame.clone());
+        }
+
+        Ok(Self {
+            width: decoder.width(),
+            height: decoder.height(),
+            global_palette: decoder.global_palette().map(<[u8]>::to_vec),
+            repeat: decoder.repeat(),
+            frames,
+        })
+    }
+
+    /// Writes the frames back as a GIF, keeping their timing, disposal and looping
+    pub fn write(&self, writer: impl Write) -> Result<(), ApplicationError> {
+        let global_palette = self.global_palette.as_deref().unwrap_or_default();
+        let mut encoder = Encoder::new(writer, self.width, self.height, global_palette)
+            .map_err(encoding_error)?;
+        encoder.set_repeat(self.repeat).map_err(encoding_error)?;
+        for frame in &self.frames {
+            encoder.write_frame(frame).map_err(encoding_error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Encodes data into the palette indices of every frame (see the module documentation)
+///
+/// Only the layout's stride and seed apply: indices are always used one bit deep, and a
+/// GIF has no alpha channel
+pub fn encode(
+    data: &str,
+    animation: &mut Animation,
+    layout: &Layout,
+    info: PayloadInfo,
+) -> Result<Header, ApplicationError> {
+    if layout.depth > 1 || layout.use_alpha || layout.palette_safe {
+        return Err(ApplicationError::EncodingError(
+            "GIF carriers only support a depth of 1, without alpha or --palette-safe".to_string(),
+        ));
+    }
+
+    sort_palettes(animation);
+    let mut indices = carrier_indices(animation);
+    let header = lsb::encode(data, &mut indices, layout, info)?;
+    scatter_indices(animation, &indices);
+
+    Ok(header)
+}
+
+/// The usable palette indices of every frame, in order, as a one-row grayscale image that
+/// [`lsb`] can read the payload from
+pub fn carrier_indices(animation: &Animation) -> GrayImage {
+    let indices: Vec<u8> = animation
+        .frames
+        .iter()
+        .flat_map(|frame| {
+            frame
+                .buffer
+                .iter()
+                .copied()
+                .filter(|&index| is_usable(frame, index))
+        })
+        .collect();
+
+    GrayImage::from_raw(indices.len() as u32, 1, indices).unwrap_or_default()
+}
+
+/// Writes the indices gathered by [`carrier_indices`] back into their frames
+fn scatter_indices(animation: &mut Animation, indices: &GrayImage) {
+    let mut indices = indices.as_raw().iter();
+    for frame in &mut animation.frames {
+        let mut buffer = frame.buffer.to_vec();
+        for index in buffer.iter_mut() {
+            if is_usable(frame, *index) {
+                if let Some(&embedded) = indices.next() {
+                    *index = embedded;
+                }
+            }
+        }
+        frame.buffer = Cow::Owned(buffer);
+    }
+}
+
+/// Whether a pixel can carry a bit: flipping it must not move it into or out of the
+/// transparent color
+fn is_usable(frame: &Frame, index: u8) -> bool {
+    frame
+        .transparent
+        .is_none_or(|transparent| index >> 1 != transparent >> 1)
+}
+
+/// Sorts the global palette and every local one by luminance, remapping the frames'
+/// indices so that no pixel changes color
+fn sort_palettes(animation: &mut Animation) {
+    let global_map = animation.global_palette.as_mut().map(sort_palette);
+    for frame in &mut animation.frames {
+        let map = match frame.palette.as_mut() {
+            Some(palette) => sort_palette(palette),
+            None => match &global_map {
+                Some(map) => map.clone(),
+                None => continue,
+            },
+        };
+        let remap = |index: u8| map.get(index as usize).copied().unwrap_or(index);
+        frame.buffer = Cow::Owned(frame.buffer.iter().map(|&index| remap(index)).collect());
+        frame.transparent = frame.transparent.map(remap);
+    }
+}
+
+/// Sorts the palette by luminance, padded to an even number of colors so that every
+/// index has a partner, and returns the new index of each old one
+fn sort_palette(palette: &mut Vec<u8>) -> Vec<u8> {
+    let mut colors: Vec<[u8; PALETTE_ENTRY_SIZE]> = palette
+        .chunks_exact(PALETTE_ENTRY_SIZE)
+        .map(|color| [color[0], color[1], color[2]])
+        .collect();
+    if colors.len() % 2 == 1 {
+        colors.push(colors[colors.len() - 1]);
+    }
+
+    let mut order: Vec<usize> = (0..colors.len()).collect();
+    order.sort_by_key(|&i| {
+        let [r, g, b] = colors[i].map(u32::from);
+        299 * r + 587 * g + 114 * b
+    });
+
+    let mut map = vec![0u8; colors.len()];
+    for (new, &old) in order.iter().enumerate() {
+        map[old] = new as u8;
+    }
+    *palette = order.iter().flat_map(|&old| colors[old]).collect();
+    map
+}
+
+fn decoding_error(e: ::gif::DecodingError) -> ApplicationError {
+    ApplicationError::DecodingError(format!("Invalid GIF: {}", e))
+}
+
+fn encoding_error(e: ::gif::EncodingError) -> ApplicationError {
+    ApplicationError::EncodingError(format!("Could not write GIF: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::gif::DisposalMethod;
+    use std::io::Cursor;
+
+    /// A 16-color gradient palette, deliberately stored from bright to dark
+    fn palette() -> Vec<u8> {
+        (0..16u8)
+            .rev()
+            .flat_map(|i| [i * 16, i * 16, i * 16])
+            .collect()
+    }
+
+    fn create_animation(frame_count: u16) -> Animation {
+        let frames = (0..frame_count)
+            .map(|n| Frame {
+                delay: 10 + n,
+                dispose: DisposalMethod::Keep,
+                width: 20,
+                height: 20,
+                buffer: Cow::Owned((0..400).map(|i| ((i + n as usize) % 16) as u8).collect()),
+                ..Frame::default()
+            })
+            .collect();
+
+        Animation {
+            width: 20,
+            height: 20,
+            global_palette: Some(palette()),
+            repeat: Repeat::Infinite,
+            frames,
+        }
+    }
+
+    fn round_trip(animation: &Animation) -> Animation {
+        let mut bytes = Vec::new();
+        animation.write(&mut bytes).unwrap();
+        Animation::read(Cursor::new(bytes)).unwrap()
+    }
+
+    fn colors(animation: &Animation) -> Vec<Vec<[u8; 3]>> {
+        animation
+            .frames
+            .iter()
+            .map(|frame| {
+                let palette = frame
+                    .palette
+                    .as_ref()
+                    .or(animation.global_palette.as_ref())
+                    .unwrap();
+                frame
+                    .buffer
+                    .iter()
+                    .map(|&i| {
+                        let i = i as usize * 3;
+                        [palette[i], palette[i + 1], palette[i + 2]]
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_encode_decode_three_frames() {
+        let mut animation = create_animation(3);
+        let data = "x".repeat(100);
+        encode(
+            &data,
+            &mut animation,
+            &Layout::default(),
+            PayloadInfo::default(),
+        )
+        .unwrap();
+
+        let decoded = round_trip(&animation);
+        assert_eq!(decoded.frames.len(), 3);
+        assert_eq!(
+            decoded.frames.iter().map(|f| f.delay).collect::<Vec<_>>(),
+            [10, 11, 12]
+        );
+        assert_eq!(decoded.repeat, Repeat::Infinite);
+        let bytes = lsb::decode(&carrier_indices(&decoded), &Layout::default()).unwrap();
+        assert_eq!(bytes, data.as_bytes());
+    }
+
+    #[test]
+    fn test_capacity_sums_frames() {
+        let single = carrier_indices(&create_animation(1));
+        let triple = carrier_indices(&create_animation(3));
+
+        assert_eq!(single.width(), 400);
+        assert_eq!(triple.width(), 3 * 400);
+
+        // 400 indices hold the 10-byte header and 40 bytes, but not 41
+        let mut animation = create_animation(1);
+        assert!(encode(
+            &"x".repeat(40),
+            &mut animation,
+            &Layout::default(),
+            PayloadInfo::default()
+        )
+        .is_ok());
+        let mut animation = create_animation(1);
+        assert!(encode(
+            &"x".repeat(41),
+            &mut animation,
+            &Layout::default(),
+            PayloadInfo::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_colors_move_to_a_neighbouring_shade() {
+        let original = create_animation(1);
+        let mut animation = create_animation(1);
+        encode(
+            &"x".repeat(40),
+            &mut animation,
+            &Layout::default(),
+            PayloadInfo::default(),
+        )
+        .unwrap();
+
+        for (before, after) in colors(&original)[0].iter().zip(&colors(&animation)[0]) {
+            assert!(before[0].abs_diff(after[0]) <= 16);
+        }
+    }
+
+    #[test]
+    fn test_transparent_pixels_are_untouched() {
+        let mut animation = create_animation(1);
+        animation.frames[0].transparent = Some(0);
+        let transparent_before: Vec<bool> = colors(&animation)[0]
+            .iter()
+            .map(|&color| color == [240, 240, 240])
+            .collect();
+
+        encode(
+            &"x".repeat(30),
+            &mut animation,
+            &Layout::default(),
+            PayloadInfo::default(),
+        )
+        .unwrap();
+        let transparent = animation.frames[0].transparent.unwrap();
+        let transparent_after: Vec<bool> = animation.frames[0]
+            .buffer
+            .iter()
+            .map(|&index| index == transparent)
+            .collect();
+
+        assert_eq!(transparent_before, transparent_after);
+        let bytes = lsb::decode(&carrier_indices(&animation), &Layout::default()).unwrap();
+        assert_eq!(bytes, "x".repeat(30).as_bytes());
+    }
+
+    #[test]
+    fn test_rejects_deeper_layouts() {
+        let layout = Layout {
+            depth: 2,
+            ..Layout::default()
+        };
+
+        assert!(encode(
+            "x",
+            &mut create_animation(1),
+            &layout,
+            PayloadInfo::default()
+        )
+        .is_err());
+    }
+}
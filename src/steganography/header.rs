@@ -0,0 +1,352 @@
+//! Header embedded ahead of every payload
+//!
+//! | Field    | Size     | Description                                       |
+//! |----------|----------|---------------------------------------------------|
+//! | magic    | 2 bytes  | `MB`, identifying a mindbender payload            |
+//! | version  | 1 byte   | Format version, currently `3`                     |
+//! | flags    | 1 byte   | Bit 0: payload also uses the alpha channel        |
+//! |          |          | Bit 1: a checksum follows the length              |
+//! |          |          | Bit 2: the payload is encrypted                   |
+//! |          |          | Bit 3: a region follows the length                |
+//! | stride   | 1 byte   | Payload occupies every Nth pixel                  |
+//! | depth    | 1 byte   | Least significant bits used per channel (1–4)     |
+//! | length   | 4 bytes  | Payload length in bytes, big-endian               |
+//! | region   | 16 bytes | x, y, width and height of the embedding region as |
+//! |          |          | big-endian u32s, only if flagged                  |
+//! | checksum | 32 bytes | SHA-256 of the original message, only if flagged  |
+//!
+//! Version 1 headers lack the depth byte and always use a depth of 1. Versions before 3
+//! never carry a region or a checksum, nor record whether the payload is encrypted.
+//!
+//! The header always fills the first pixels of the layout in order, so the decoder
+//! can read it before it knows how the payload itself is spread. A header written into
+//! a region fills the first pixels of the region.
+
+use super::layout::Region;
+use crate::error::ApplicationError;
+
+pub const MAGIC: [u8; 2] = *b"MB";
+pub const VERSION: u8 = 3;
+/// Size of the fixed fields, which is the whole header unless it carries a checksum
+pub const HEADER_SIZE: usize = 10;
+pub const REGION_SIZE: usize = 16;
+pub const CHECKSUM_SIZE: usize = 32;
+/// Size of a header carrying a region and a checksum, the largest there is
+pub const MAX_HEADER_SIZE: usize = HEADER_SIZE + REGION_SIZE + CHECKSUM_SIZE;
+pub const MAX_DEPTH: u8 = 4;
+/// Flag set when the payload also occupies the alpha channel of RGBA carriers
+pub const FLAG_ALPHA: u8 = 0b0000_0001;
+/// Flag set when a checksum of the original message follows the fixed fields
+pub const FLAG_CHECKSUM: u8 = 0b0000_0010;
+/// Flag set when the payload was encrypted with a key
+pub const FLAG_ENCRYPTED: u8 = 0b0000_0100;
+/// Flag set when the region the payload is confined to follows the fixed fields
+pub const FLAG_REGION: u8 = 0b0000_1000;
+
+const V1_HEADER_SIZE: usize = 9;
+
+/// What a header records about the payload itself, besides where it is stored
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PayloadInfo {
+    /// SHA-256 of the original message, see [`FLAG_CHECKSUM`]
+    pub checksum: Option<[u8; CHECKSUM_SIZE]>,
+    /// See [`FLAG_ENCRYPTED`]
+    pub encrypted: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub version: u8,
+    pub flags: u8,
+    pub stride: u8,
+    pub depth: u8,
+    pub length: u32,
+    pub region: Option<Region>,
+    pub checksum: Option<[u8; CHECKSUM_SIZE]>,
+}
+
+impl Header {
+    pub fn new(stride: u8, depth: u8, length: u32) -> Self {
+        Self {
+            version: VERSION,
+            flags: 0,
+            stride,
+            depth,
+            length,
+            region: None,
+            checksum: None,
+        }
+    }
+
+    /// The header recording `region`, see [`FLAG_REGION`]
+    pub fn with_region(self, region: Region) -> Self {
+        Self {
+            flags: self.flags | FLAG_REGION,
+            region: Some(region),
+            ..self
+        }
+    }
+
+    /// The header recording `checksum`, see [`FLAG_CHECKSUM`]
+    pub fn with_checksum(self, checksum: [u8; CHECKSUM_SIZE]) -> Self {
+        Self {
+            flags: self.flags | FLAG_CHECKSUM,
+            checksum: Some(checksum),
+            ..self
+        }
+    }
+
+    /// Whether the payload is encrypted, or `None` when the format version doesn't record it
+    pub fn is_encrypted(self) -> Option<bool> {
+        (self.version >= 3).then_some(self.flags & FLAG_ENCRYPTED != 0)
+    }
+
+    /// Number of bytes the header occupies in its format version
+    pub fn size(self) -> usize {
+        if self.version == 1 {
+            return V1_HEADER_SIZE;
+        }
+        HEADER_SIZE
+            + self.region.map_or(0, |_| REGION_SIZE)
+            + self.checksum.map_or(0, |_| CHECKSUM_SIZE)
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.size());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&[self.version, self.flags, self.stride, self.depth]);
+        bytes.extend_from_slice(&self.length.to_be_bytes());
+        if let Some(region) = self.region {
+            for value in [region.x, region.y, region.width, region.height] {
+                bytes.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        if let Some(checksum) = self.checksum {
+            bytes.extend_from_slice(&checksum);
+        }
+        bytes
+    }
+
+    /// Parse a header, or `None` if the bytes don't start with the magic (e.g. a legacy image)
+    pub fn parse(bytes: &[u8]) -> Option<Result<Self, ApplicationError>> {
+        if bytes.len() < V1_HEADER_SIZE || bytes[..2] != MAGIC {
+            return None;
+        }
+
+        let header = match bytes[2] {
+            1 => Self {
+                version: 1,
+                flags: bytes[3],
+                stride: bytes[4],
+                depth: 1,
+                length: read_u32(bytes, 5)?,
+                region: None,
+                checksum: None,
+            },
+            _ => Self {
+                version: bytes[2],
+                flags: bytes[3],
+                stride: bytes[4],
+                depth: bytes[5],
+                length: read_u32(bytes, 6)?,
+                region: None,
+                checksum: None,
+            },
+        };
+        if header.version < 3 {
+            return Some(header.validate());
+        }
+        Some(header.parse_optional_fields(bytes).and_then(Self::validate))
+    }
+
+    /// Reads the fields that follow the fixed ones when the flags announce them
+    fn parse_optional_fields(self, bytes: &[u8]) -> Result<Self, ApplicationError> {
+        let cut_off = |field: &str| {
+            ApplicationError::DecodingError(format!("Header is cut off before its {}", field))
+        };
+        let mut offset = HEADER_SIZE;
+
+        let region = if self.flags & FLAG_REGION != 0 {
+            let value = |index: usize| read_u32(bytes, offset + 4 * index);
+            let region = match (value(0), value(1), value(2), value(3)) {
+                (Some(x), Some(y), Some(width), Some(height)) => Region {
+                    x,
+                    y,
+                    width,
+                    height,
+                },
+                _ => return Err(cut_off("region")),
+            };
+            offset += REGION_SIZE;
+            Some(region)
+        } else {
+            None
+        };
+        let checksum = if self.flags & FLAG_CHECKSUM != 0 {
+            let checksum = bytes
+                .get(offset..offset + CHECKSUM_SIZE)
+                .ok_or_else(|| cut_off("checksum"))?;
+            checksum.try_into().ok()
+        } else {
+            None
+        };
+
+        Ok(Self {
+            region,
+            checksum,
+            ..self
+        })
+    }
+
+    fn validate(self) -> Result<Self, ApplicationError> {
+        if self.version > VERSION {
+            return Err(ApplicationError::DecodingError(format!(
+                "Format version {} was written by a newer mindbender (this one reads up to {}); upgrade to decode it",
+                self.version, VERSION
+            )));
+        }
+        if self.version == 0 {
+            return Err(ApplicationError::DecodingError(
+                "Unsupported format version 0".to_string(),
+            ));
+        }
+        if self.stride == 0 {
+            return Err(ApplicationError::DecodingError(
+                "Header declares a stride of 0".to_string(),
+            ));
+        }
+        if !(1..=MAX_DEPTH).contains(&self.depth) {
+            return Err(ApplicationError::DecodingError(format!(
+                "Header declares an unsupported depth of {} bits",
+                self.depth
+            )));
+        }
+        if self
+            .region
+            .is_some_and(|region| region.width == 0 || region.height == 0)
+        {
+            return Err(ApplicationError::DecodingError(
+                "Header declares an empty region".to_string(),
+            ));
+        }
+
+        Ok(self)
+    }
+}
+
+/// Big-endian u32 at `at`, if the bytes reach that far
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    bytes
+        .get(at..at + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_round_trip() {
+        let header = Header::new(3, 2, 0x0102_0304);
+        let bytes = header.to_bytes();
+
+        assert_eq!(bytes, [b'M', b'B', VERSION, 0, 3, 2, 1, 2, 3, 4]);
+        assert_eq!(Header::parse(&bytes).unwrap().unwrap(), header);
+    }
+
+    #[test]
+    fn test_header_with_checksum_round_trip() {
+        let header = Header::new(1, 1, 5).with_checksum([7; CHECKSUM_SIZE]);
+        let bytes = header.to_bytes();
+
+        assert_eq!(header.size(), HEADER_SIZE + CHECKSUM_SIZE);
+        assert_eq!(bytes.len(), HEADER_SIZE + CHECKSUM_SIZE);
+        assert_eq!(bytes[3], FLAG_CHECKSUM);
+        assert_eq!(Header::parse(&bytes).unwrap().unwrap(), header);
+        assert!(Header::parse(&bytes[..HEADER_SIZE]).unwrap().is_err());
+    }
+
+    #[test]
+    fn test_header_with_region_round_trip() {
+        let region = Region {
+            x: 300,
+            y: 2,
+            width: 70_000,
+            height: 9,
+        };
+        let header = Header::new(1, 1, 5)
+            .with_region(region)
+            .with_checksum([7; CHECKSUM_SIZE]);
+        let bytes = header.to_bytes();
+
+        assert_eq!(header.size(), MAX_HEADER_SIZE);
+        assert_eq!(bytes.len(), MAX_HEADER_SIZE);
+        assert_eq!(bytes[3], FLAG_REGION | FLAG_CHECKSUM);
+        assert_eq!(Header::parse(&bytes).unwrap().unwrap(), header);
+        assert!(Header::parse(&bytes[..HEADER_SIZE + 8]).unwrap().is_err());
+
+        let empty = Header::new(1, 1, 5).with_region(Region { width: 0, ..region });
+        assert!(Header::parse(&empty.to_bytes()).unwrap().is_err());
+    }
+
+    #[test]
+    fn test_version_2_ignores_checksum_flag() {
+        let mut bytes = Header::new(1, 1, 5).to_bytes();
+        bytes[2] = 2;
+        bytes[3] = FLAG_CHECKSUM;
+        let header = Header::parse(&bytes).unwrap().unwrap();
+
+        assert_eq!(header.checksum, None);
+        assert_eq!(header.size(), HEADER_SIZE);
+    }
+
+    #[test]
+    fn test_encrypted_flag() {
+        let mut header = Header::new(1, 1, 5);
+        assert_eq!(header.is_encrypted(), Some(false));
+
+        header.flags |= FLAG_ENCRYPTED;
+        let parsed = Header::parse(&header.to_bytes()).unwrap().unwrap();
+        assert_eq!(parsed.is_encrypted(), Some(true));
+
+        let legacy = Header {
+            version: 2,
+            ..parsed
+        };
+        assert_eq!(legacy.is_encrypted(), None);
+    }
+
+    #[test]
+    fn test_missing_magic() {
+        assert!(Header::parse(b"Hello, world!").is_none());
+        assert!(Header::parse(&MAGIC).is_none());
+    }
+
+    #[test]
+    fn test_invalid_fields() {
+        let mut bytes = Header::new(1, 1, 5).to_bytes();
+        bytes[2] = VERSION + 1;
+        assert!(Header::parse(&bytes).unwrap().is_err());
+
+        let bytes = Header::new(0, 1, 5).to_bytes();
+        assert!(Header::parse(&bytes).unwrap().is_err());
+
+        for depth in [0, MAX_DEPTH + 1] {
+            let bytes = Header::new(1, depth, 5).to_bytes();
+            assert!(Header::parse(&bytes).unwrap().is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_version_1() {
+        let header = Header::parse(&[b'M', b'B', 1, 0, 2, 0, 0, 0, 7])
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(header.version, 1);
+        assert_eq!(header.size(), 9);
+        assert_eq!(header.stride, 2);
+        assert_eq!(header.depth, 1);
+        assert_eq!(header.length, 7);
+    }
+}
@@ -0,0 +1,402 @@
+use super::analysis::salient_pixels;
+use image::{ImageBuffer, Pixel};
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use std::fmt;
+use std::str::FromStr;
+
+const SALIENT_FRACTION: f64 = 0.25;
+
+/// Describes which channel samples of a carrier hold payload bits, and in what order
+///
+/// The decoder must use the same layout as the encoder to find the payload
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// Skip the most perceptually salient pixels (strong edges), leaving them untouched
+    pub palette_safe: bool,
+    /// Restrict embedding to the pixels `p` where `p % count == index`, as `(index, count)`
+    pub slot: Option<(usize, usize)>,
+    /// Shuffle the pixel order with a PRNG seeded from this value
+    pub seed: Option<u64>,
+    /// Use only every Nth pixel, leaving the ones in between untouched
+    pub stride: usize,
+    /// Number of least significant bits each channel holds (1–4)
+    pub depth: usize,
+    /// Also embed in the alpha channel of carriers that have one
+    pub use_alpha: bool,
+    /// Embed only in the pixels inside this rectangle, leaving the rest untouched
+    pub region: Option<Region>,
+}
+
+/// A rectangle of pixels, given by its top-left corner and its size
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// Whether the region is not empty and lies entirely within a `width`x`height` image
+    pub fn fits(&self, width: u32, height: u32) -> bool {
+        let within = |start: u32, size: u32, limit: u32| {
+            size > 0 && start.checked_add(size).is_some_and(|end| end <= limit)
+        };
+        within(self.x, self.width, width) && within(self.y, self.height, height)
+    }
+
+    /// Indices of the region's pixels in raster order, in an image of `width`x`height`
+    /// pixels; any part of the region outside the image is left out
+    fn pixels(self, width: usize, height: usize) -> impl Iterator<Item = usize> {
+        let span = |start: u32, size: u32, limit: usize| {
+            let start = start as usize;
+            start.min(limit)..start.saturating_add(size as usize).min(limit)
+        };
+        let columns = span(self.x, self.width, width);
+        span(self.y, self.height, height)
+            .flat_map(move |row| row * width + columns.start..row * width + columns.end)
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} at ({}, {})",
+            self.width, self.height, self.x, self.y
+        )
+    }
+}
+
+/// Parses `x,y,width,height`
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected x,y,width,height in pixels, got '{}'", s);
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        match values[..] {
+            [x, y, width, height] => Ok(Self {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            palette_safe: false,
+            slot: None,
+            seed: None,
+            stride: 1,
+            depth: 1,
+            use_alpha: false,
+            region: None,
+        }
+    }
+}
+
+impl Layout {
+    /// Whether payload bits fill every channel in raster order, one bit per channel
+    pub fn is_sequential(&self) -> bool {
+        !self.palette_safe
+            && self.slot.is_none()
+            && self.seed.is_none()
+            && self.region.is_none()
+            && self.stride == 1
+            && self.depth == 1
+    }
+
+    /// Whether the layout visits pixels in raster order, so that the channels of an
+    /// image's first rows are the first channels of the whole image
+    pub fn is_raster_order(&self) -> bool {
+        !self.palette_safe && self.slot.is_none() && self.seed.is_none() && self.region.is_none()
+    }
+
+    /// Number of leading pixels of a raster order layout that the first `channels`
+    /// channels after `skip` pixels span
+    pub fn pixels_spanned<P: Pixel>(&self, skip: usize, channels: usize) -> usize {
+        let (_, usable) = self.pixel_channels::<P>();
+        match channels.div_ceil(usable) {
+            0 => skip,
+            pixels => skip + (pixels - 1) * self.stride.max(1) + 1,
+        }
+    }
+
+    /// Number of channels a raster order layout has after `skip` pixels, in an image of
+    /// `pixel_count` pixels, without visiting them
+    pub fn channels_within<P: Pixel>(&self, skip: usize, pixel_count: usize) -> usize {
+        let (_, usable) = self.pixel_channels::<P>();
+        pixel_count
+            .saturating_sub(skip)
+            .div_ceil(self.stride.max(1))
+            * usable
+    }
+
+    /// Samples per pixel of `P`, and how many of them (from the first) may hold payload bits
+    ///
+    /// The alpha sample of RGBA pixels is only used with [`Layout::use_alpha`]
+    pub fn pixel_channels<P: Pixel>(&self) -> (usize, usize) {
+        let samples = P::CHANNEL_COUNT as usize;
+        let usable = if samples == 4 && !self.use_alpha {
+            3
+        } else {
+            samples
+        };
+        (samples, usable)
+    }
+
+    /// Sample indices that hold payload bits, in the order the bits are written
+    ///
+    /// Each sample holds [`Layout::depth`] bits
+    pub fn channels<P: Pixel<Subpixel = u8>>(
+        &self,
+        image: &ImageBuffer<P, Vec<u8>>,
+    ) -> Box<dyn Iterator<Item = usize>> {
+        self.channels_after(image, 0)
+    }
+
+    /// Sample indices that hold payload bits once the first `skip` usable pixels are set
+    /// aside (e.g. for a header); the stride applies only to the pixels that follow
+    pub fn channels_after<P: Pixel<Subpixel = u8>>(
+        &self,
+        image: &ImageBuffer<P, Vec<u8>>,
+        skip: usize,
+    ) -> Box<dyn Iterator<Item = usize>> {
+        let (samples, usable) = self.pixel_channels::<P>();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+
+        let mut pixels: Box<dyn Iterator<Item = usize>> = match self.region {
+            Some(region) => Box::new(region.pixels(width, height)),
+            None => Box::new(0..width * height),
+        };
+        if self.palette_safe {
+            let salient = salient_pixels(image, SALIENT_FRACTION);
+            pixels = Box::new(pixels.filter(move |&pixel| !salient[pixel]));
+        }
+        if let Some((index, count)) = self.slot {
+            pixels = Box::new(pixels.filter(move |&pixel| pixel % count == index));
+        }
+        if let Some(seed) = self.seed {
+            // ChaCha20 keeps the permutation stable across `rand` releases,
+            // which matters because images encoded today must decode later
+            let mut shuffled: Vec<usize> = pixels.collect();
+            shuffled.shuffle(&mut ChaCha20Rng::seed_from_u64(seed));
+            pixels = Box::new(shuffled.into_iter());
+        }
+
+        Box::new(
+            pixels
+                .skip(skip)
+                .step_by(self.stride.max(1))
+                .flat_map(move |pixel| pixel * samples..pixel * samples + usable),
+        )
+    }
+
+    /// Number of payload bits the carrier can hold under this layout
+    pub fn capacity_bits<P: Pixel<Subpixel = u8>>(&self, image: &ImageBuffer<P, Vec<u8>>) -> usize {
+        self.channels(image).count() * self.depth.max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+    use std::collections::HashSet;
+
+    const CHANNELS_PER_PIXEL: usize = 3;
+
+    #[test]
+    fn test_sequential_layout() {
+        let image = RgbImage::new(2, 2);
+        let layout = Layout::default();
+
+        assert!(layout.is_sequential());
+        assert_eq!(
+            layout.channels(&image).collect::<Vec<_>>(),
+            (0..12).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_palette_safe_skips_salient_pixels() {
+        let image = RgbImage::from_fn(8, 8, |x, _| {
+            if x < 4 {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        });
+        let layout = Layout {
+            palette_safe: true,
+            ..Layout::default()
+        };
+        let channels: Vec<usize> = layout.channels(&image).collect();
+
+        assert!(layout.capacity_bits(&image) < image.as_flat_samples().samples.len());
+        assert!(!channels.contains(&(3 * CHANNELS_PER_PIXEL)));
+        assert!(channels.contains(&0));
+    }
+
+    #[test]
+    fn test_slots_are_disjoint() {
+        let image = RgbImage::new(5, 5);
+        let slot = |index| Layout {
+            slot: Some((index, 2)),
+            seed: Some(index as u64),
+            ..Layout::default()
+        };
+        let first: HashSet<usize> = slot(0).channels(&image).collect();
+        let second: HashSet<usize> = slot(1).channels(&image).collect();
+
+        assert!(first.is_disjoint(&second));
+        assert_eq!(first.len() + second.len(), 75);
+    }
+
+    #[test]
+    fn test_stride_skips_pixels() {
+        let image = RgbImage::new(3, 2);
+        let layout = Layout {
+            stride: 2,
+            ..Layout::default()
+        };
+
+        assert!(!layout.is_sequential());
+        assert_eq!(
+            layout.channels(&image).collect::<Vec<_>>(),
+            [0, 1, 2, 6, 7, 8, 12, 13, 14]
+        );
+        assert_eq!(
+            layout.channels_after(&image, 1).collect::<Vec<_>>(),
+            [3, 4, 5, 9, 10, 11, 15, 16, 17]
+        );
+    }
+
+    #[test]
+    fn test_region_restricts_pixels() {
+        let image = RgbImage::new(4, 3);
+        let layout = Layout {
+            region: Some(Region {
+                x: 2,
+                y: 1,
+                width: 2,
+                height: 2,
+            }),
+            ..Layout::default()
+        };
+
+        assert!(!layout.is_sequential());
+        let pixels: Vec<usize> = layout
+            .channels(&image)
+            .step_by(CHANNELS_PER_PIXEL)
+            .map(|channel| channel / CHANNELS_PER_PIXEL)
+            .collect();
+        assert_eq!(pixels, [6, 7, 10, 11]);
+    }
+
+    #[test]
+    fn test_region_fits_and_parses() {
+        let region: Region = "2, 1,2,2".parse().unwrap();
+
+        assert_eq!(
+            region,
+            Region {
+                x: 2,
+                y: 1,
+                width: 2,
+                height: 2
+            }
+        );
+        assert!(region.fits(4, 3));
+        assert!(!region.fits(3, 3));
+        assert!(!"0,0,0,2".parse::<Region>().unwrap().fits(4, 3));
+        assert!(!"1,0,4294967295,2".parse::<Region>().unwrap().fits(4, 3));
+        assert!("1,2,3".parse::<Region>().is_err());
+        assert!("a,b,c,d".parse::<Region>().is_err());
+    }
+
+    #[test]
+    fn test_alpha_only_with_use_alpha() {
+        let image = RgbaImage::new(2, 1);
+        let with_alpha = Layout {
+            use_alpha: true,
+            ..Layout::default()
+        };
+
+        assert_eq!(
+            Layout::default().channels(&image).collect::<Vec<_>>(),
+            [0, 1, 2, 4, 5, 6]
+        );
+        assert_eq!(
+            with_alpha.channels(&image).collect::<Vec<_>>(),
+            (0..8).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            with_alpha
+                .channels(&RgbImage::new(2, 1))
+                .collect::<Vec<_>>(),
+            (0..6).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_depth_multiplies_capacity() {
+        let image = RgbImage::new(3, 2);
+        let layout = Layout {
+            depth: 3,
+            ..Layout::default()
+        };
+
+        assert!(!layout.is_sequential());
+        assert_eq!(layout.capacity_bits(&image), 3 * 18);
+    }
+
+    #[test]
+    fn test_seed_is_deterministic() {
+        let image = RgbImage::new(4, 4);
+        let seeded = |seed| Layout {
+            seed: Some(seed),
+            ..Layout::default()
+        };
+        let first: Vec<usize> = seeded(7).channels(&image).collect();
+
+        assert_eq!(first, seeded(7).channels(&image).collect::<Vec<_>>());
+        assert_ne!(first, seeded(8).channels(&image).collect::<Vec<_>>());
+        assert_ne!(first, (0..48).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_raster_order_arithmetic_matches_channels() {
+        let image = RgbaImage::new(7, 5);
+        let layout = Layout {
+            stride: 3,
+            ..Layout::default()
+        };
+        let channels: Vec<usize> = layout.channels_after(&image, 4).collect();
+
+        assert!(layout.is_raster_order());
+        assert_eq!(layout.channels_within::<Rgba<u8>>(4, 35), channels.len());
+        for taken in 1..channels.len() {
+            let last_pixel = channels[taken - 1] / 4;
+            assert_eq!(layout.pixels_spanned::<Rgba<u8>>(4, taken), last_pixel + 1);
+        }
+        assert_eq!(layout.pixels_spanned::<Rgba<u8>>(4, 0), 4);
+        assert!(!Layout {
+            seed: Some(1),
+            ..Layout::default()
+        }
+        .is_raster_order());
+    }
+}
@@ -1,113 +1,1299 @@
-use super::util::is_sufficient_capacity;
+use super::header::{Header, PayloadInfo, FLAG_ALPHA, FLAG_ENCRYPTED, MAX_DEPTH, MAX_HEADER_SIZE};
+use super::layout::Layout;
+use super::util::{image_capacity_bytes, is_degenerate, is_sufficient_capacity};
 use crate::error::ApplicationError;
-use image::{Pixel, RgbImage};
+use image::{ImageBuffer, Pixel};
+use log::trace;
+use rand::Rng;
 use rayon::prelude::*;
 
 const NULL_DELIMITER: char = '\0';
 const BITS_PER_BYTE: usize = 8;
+/// Color channels per pixel available to the header, which never uses alpha
+const HEADER_CHANNELS_PER_PIXEL: usize = 3;
+/// Size of the big-endian length that precedes a raw LSB message
+const RAW_LENGTH_SIZE: usize = 4;
 
-/// Encodes text data into an image using LSB (Least Significant Bit) steganography
-pub fn encode(data: &str, image: &mut RgbImage) -> Result<(), ApplicationError> {
-    let data_with_delimiter = format!("{}{}", data, NULL_DELIMITER);
+/// Number of leading layout pixels a header of `size` bytes occupies in a carrier with
+/// `P` pixels: one bit per color channel, so a single luma channel needs three times the
+/// pixels an RGB one does
+pub fn header_pixels<P: Pixel>(size: usize) -> usize {
+    let channels = (P::CHANNEL_COUNT as usize).min(HEADER_CHANNELS_PER_PIXEL);
+    (size * BITS_PER_BYTE).div_ceil(channels)
+}
 
-    if !is_sufficient_capacity(&data_with_delimiter, image) {
+/// Encodes text data into an image using LSB (Least Significant Bit) steganography
+///
+/// A [`Header`] recording the payload length, stride, depth and the payload's `info` fills
+/// the first pixels of the layout, and the payload follows in the low `depth` bits of every
+/// `stride`th pixel after it. Higher depths hold more data but make the changes more visible
+pub fn encode<P: Pixel<Subpixel = u8>>(
+    data: &str,
+    image: &mut ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+    info: PayloadInfo,
+) -> Result<Header, ApplicationError> {
+    ensure_has_pixels(image, ApplicationError::EncodingError)?;
+    ensure_region_fits(image, layout, ApplicationError::EncodingError)?;
+    if layout.use_alpha && P::CHANNEL_COUNT != 4 {
         return Err(ApplicationError::EncodingError(
-            "Image too small to encode data".to_string(),
+            "Carrier image has no alpha channel".to_string(),
         ));
     }
 
+    let stride = u8::try_from(layout.stride).map_err(|_| {
+        ApplicationError::EncodingError(format!("Stride {} exceeds 255", layout.stride))
+    })?;
+    let depth = u8::try_from(layout.depth)
+        .ok()
+        .filter(|depth| (1..=MAX_DEPTH).contains(depth))
+        .ok_or_else(|| {
+            ApplicationError::EncodingError(format!(
+                "Depth must be between 1 and {} bits, got {}",
+                MAX_DEPTH, layout.depth
+            ))
+        })?;
+    let length = u32::try_from(data.len()).map_err(|_| {
+        ApplicationError::EncodingError("Message too large for the header".to_string())
+    })?;
+
+    let mut header = Header::new(stride, depth, length);
+    if layout.use_alpha {
+        header.flags |= FLAG_ALPHA;
+    }
+    if info.encrypted {
+        header.flags |= FLAG_ENCRYPTED;
+    }
+    if let Some(region) = layout.region {
+        header = header.with_region(region);
+    }
+    if let Some(checksum) = info.checksum {
+        header = header.with_checksum(checksum);
+    }
+    if !is_sufficient_capacity(data, image, layout, header.size()) {
+        return Err(ApplicationError::InsufficientCapacity {
+            needed: header.size() + data.len(),
+            available: image_capacity_bytes(image, layout, header.size()),
+        });
+    }
+
+    trace!("Writing {:?}", header);
+    embed_bytes(&header.to_bytes(), image, &header_layout(layout), 0);
+    embed_bytes(
+        data.as_bytes(),
+        image,
+        layout,
+        header_pixels::<P>(header.size()),
+    );
+
+    Ok(header)
+}
+
+/// Overwrites every least significant bit that the payload behind `header`, written by
+/// [`encode`], left unused with a random bit
+///
+/// Leftovers of an earlier, longer payload otherwise stay behind the new one, and
+/// the boundary where they end gives away that the carrier was edited twice
+pub fn randomize_unused<P: Pixel<Subpixel = u8>>(
+    image: &mut ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+    header: &Header,
+) {
+    let mut rng = rand::thread_rng();
+    let depth = layout.depth.max(1);
+    let mask = low_bits_mask(depth);
+    let channels: Vec<usize> = layout
+        .channels_after(image, header_pixels::<P>(header.size()))
+        .skip((header.length as usize * BITS_PER_BYTE).div_ceil(depth))
+        .collect();
     let image_data = image.as_flat_samples_mut().samples;
 
-    image_data
-        .par_chunks_mut(BITS_PER_BYTE)
-        .zip(data_with_delimiter.as_bytes().par_iter())
-        .for_each(|(chunk, &data_byte)| {
-            chunk.iter_mut().enumerate().for_each(|(i, pixel_byte)| {
-                let bit = (data_byte >> (BITS_PER_BYTE - 1 - i)) & 1;
-                *pixel_byte = (*pixel_byte & !1) | bit;
-            });
+    for index in channels {
+        image_data[index] = (image_data[index] & !mask) | rng.gen::<u8>() & mask;
+    }
+}
+
+/// Encodes text data into an image without any delimiter or framing
+///
+/// Every available bit goes to the payload, so the exact byte length must be
+/// tracked out-of-band and passed to [`decode_headerless`]
+pub fn encode_headerless<P: Pixel<Subpixel = u8>>(
+    data: &str,
+    image: &mut ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+) -> Result<(), ApplicationError> {
+    ensure_has_pixels(image, ApplicationError::EncodingError)?;
+    ensure_region_fits(image, layout, ApplicationError::EncodingError)?;
+    if data.len() * BITS_PER_BYTE > layout.capacity_bits(image) {
+        return Err(ApplicationError::InsufficientCapacity {
+            needed: data.len(),
+            available: layout.capacity_bits(image) / BITS_PER_BYTE,
         });
+    }
+
+    embed_bytes(data.as_bytes(), image, layout, 0);
 
     Ok(())
 }
 
-/// Decodes text data from an image that was encoded using LSB steganography
-pub fn decode(image: &RgbImage) -> Result<String, ApplicationError> {
-    let mut bits = Vec::with_capacity(image.width() as usize * image.height() as usize * 3);
+/// Decodes the data embedded in an image using LSB steganography, without checking that
+/// it is valid UTF-8
+///
+/// The stride, depth and use of alpha are taken from the header rather than from `layout`. Images
+/// without a header are read in the legacy format, up to the first null byte. A header
+/// written into a region sits in that region's first pixels, so `layout` needs the same
+/// region to find it
+pub fn decode<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+) -> Result<Vec<u8>, ApplicationError> {
+    decode_with_header(image, layout).map(|(bytes, _)| bytes)
+}
 
-    image
-        .pixels()
-        .flat_map(|pixel| pixel.channels().iter())
-        .for_each(|&channel| bits.push(channel & 1));
+/// [`decode`], also returning the header the payload was read with, if there was one
+pub fn decode_with_header<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+) -> Result<(Vec<u8>, Option<Header>), ApplicationError> {
+    ensure_has_pixels(image, ApplicationError::DecodingError)?;
+    ensure_region_fits(image, layout, ApplicationError::DecodingError)?;
+    // Only look as far as the first byte; counting the full capacity walks every channel
+    let first_byte_channels = layout.channels(image).take(BITS_PER_BYTE).count();
+    if first_byte_channels * layout.depth.max(1) < BITS_PER_BYTE {
+        return Err(ApplicationError::DecodingError(
+            "Image too small to contain a message".to_string(),
+        ));
+    }
 
-    let mut bytes = Vec::with_capacity(bits.len() / BITS_PER_BYTE);
-    for byte_bits in bits.chunks(BITS_PER_BYTE) {
-        if byte_bits.len() != BITS_PER_BYTE {
-            break;
+    let header = match Header::parse(&read_header_bytes(image, layout)) {
+        Some(Ok(header)) => header,
+        // A legacy message may itself start with the magic, as plaintext beginning with "MB"
+        // or a base64 nonce that happens to, so it is only an error if it isn't one either
+        Some(Err(e)) => {
+            return match decode_legacy_text(image, layout) {
+                Some(bytes) => {
+                    trace!("Invalid header, read the legacy delimited format instead");
+                    Ok((bytes, None))
+                }
+                None => Err(e),
+            };
+        }
+        None => {
+            trace!("No header found, reading the legacy delimited format");
+            return Ok((decode_legacy(image, layout), None));
+        }
+    };
+    trace!("Read {:?}", header);
+    if layout.region.is_some() && header.region != layout.region {
+        return Err(ApplicationError::DecodingError(
+            "The header found in the region records a different region".to_string(),
+        ));
+    }
+
+    let payload_layout = Layout {
+        stride: header.stride as usize,
+        depth: header.depth as usize,
+        use_alpha: header.flags & FLAG_ALPHA != 0,
+        region: header.region,
+        ..layout.clone()
+    };
+    let length = header.length as usize;
+    // Reject an impossible length before extracting anything, without walking the layout
+    let limit = max_payload_bytes(image, &payload_layout, header.size());
+    if length > limit {
+        return Err(ApplicationError::DecodingError(format!(
+            "Header declares {} bytes but the image holds at most {}",
+            length, limit
+        )));
+    }
+    let bytes = extract_bytes(
+        image,
+        &payload_layout,
+        header_pixels::<P>(header.size()),
+        length,
+    );
+    if bytes.len() < length {
+        return Err(ApplicationError::DecodingError(format!(
+            "Header declares {} bytes but the image only holds {}",
+            length,
+            bytes.len()
+        )));
+    }
+
+    Ok((bytes, Some(header)))
+}
+
+/// Upper bound on the payload bytes the layout can hold after a header of `header_size`
+/// bytes, computed from the image's dimensions alone
+///
+/// Strides, seeds and palette-safe layouts only ever use fewer channels than this
+fn max_payload_bytes<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+    header_size: usize,
+) -> usize {
+    let (_, usable) = layout.pixel_channels::<P>();
+    let pixel_count = image.width() as usize * image.height() as usize;
+    pixel_count.saturating_sub(header_pixels::<P>(header_size)) * usable * layout.depth.max(1)
+        / BITS_PER_BYTE
+}
+
+/// Reads enough raw bytes from the first pixels of the layout for the largest header,
+/// without validating them
+pub fn read_header_bytes<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+) -> Vec<u8> {
+    extract_bytes(image, &header_layout(layout), 0, MAX_HEADER_SIZE)
+}
+
+/// Decodes a message written before headers existed, terminated by a null byte
+fn decode_legacy<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+) -> Vec<u8> {
+    // Stops at the delimiter instead of extracting the whole image first
+    serial_bytes(image, layout, 0)
+        .take_while(|&byte| byte != NULL_DELIMITER as u8)
+        .collect()
+}
+
+/// Decodes a message in the format of releases before the header, up to its null byte,
+/// without looking for a header first
+///
+/// [`decode`] already falls back to this format, but only for messages that read as text;
+/// this reads one whatever it holds
+pub fn decode_legacy_delimited<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+) -> Result<Vec<u8>, ApplicationError> {
+    ensure_has_pixels(image, ApplicationError::DecodingError)?;
+    ensure_region_fits(image, layout, ApplicationError::DecodingError)?;
+    Ok(decode_legacy(image, layout))
+}
+
+/// [`decode_legacy`], but only if the message ends with the delimiter and reads as text
+///
+/// Legacy messages were always text, so bytes that are not valid UTF-8 or hold control
+/// characters other than line breaks and tabs are taken not to be one
+fn decode_legacy_text<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+) -> Option<Vec<u8>> {
+    let mut terminated = false;
+    let bytes: Vec<u8> = serial_bytes(image, layout, 0)
+        .take_while(|&byte| {
+            terminated = byte == NULL_DELIMITER as u8;
+            !terminated
+        })
+        .collect();
+    let text = std::str::from_utf8(&bytes).ok()?;
+    let is_text = !text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'));
+    (terminated && is_text).then_some(bytes)
+}
+
+/// Decodes exactly `length` bytes from an image encoded without framing, without checking
+/// that they are valid UTF-8
+pub fn decode_headerless<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    length: usize,
+    layout: &Layout,
+) -> Result<Vec<u8>, ApplicationError> {
+    ensure_has_pixels(image, ApplicationError::DecodingError)?;
+    ensure_region_fits(image, layout, ApplicationError::DecodingError)?;
+    let available = layout.capacity_bits(image) / BITS_PER_BYTE;
+    if length > available {
+        return Err(ApplicationError::DecodingError(format!(
+            "Requested {} bytes but the image only holds {}",
+            length, available
+        )));
+    }
+
+    Ok(extract_bytes(image, layout, 0, length))
+}
+
+/// How a message embedded by another LSB tool marks where it ends, see [`decode_raw`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RawFraming {
+    /// The message follows its length in bytes, as a 32-bit big-endian integer
+    LengthPrefix,
+    /// The message runs up to the first occurrence of these bytes
+    Delimiter(Vec<u8>),
+}
+
+/// Decodes a message embedded by another LSB tool, with none of mindbender's framing
+///
+/// Bits are read from the low `depth` bits of the layout's channels (red, green, then
+/// blue of each pixel in raster order) and packed into bytes most significant bit first.
+/// The length prefix or delimiter is not part of the returned bytes
+pub fn decode_raw<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+    framing: &RawFraming,
+) -> Result<Vec<u8>, ApplicationError> {
+    ensure_has_pixels(image, ApplicationError::DecodingError)?;
+    ensure_region_fits(image, layout, ApplicationError::DecodingError)?;
+    let mut bytes = serial_bytes(image, layout, 0);
+
+    match framing {
+        RawFraming::LengthPrefix => {
+            let prefix: Vec<u8> = bytes.by_ref().take(RAW_LENGTH_SIZE).collect();
+            let prefix: [u8; RAW_LENGTH_SIZE] = prefix.try_into().map_err(|_| {
+                ApplicationError::DecodingError("Image too small to contain a message".to_string())
+            })?;
+            let length = u32::from_be_bytes(prefix) as usize;
+            // Bounded by the image, whatever the prefix claims
+            let message: Vec<u8> = bytes.take(length).collect();
+            if message.len() < length {
+                return Err(ApplicationError::DecodingError(format!(
+                    "Length prefix declares {} bytes but the image only holds {}",
+                    length,
+                    message.len()
+                )));
+            }
+            Ok(message)
+        }
+        RawFraming::Delimiter(delimiter) => {
+            if delimiter.is_empty() {
+                return Err(ApplicationError::DecodingError(
+                    "The delimiter cannot be empty".to_string(),
+                ));
+            }
+            let mut message = Vec::new();
+            for byte in bytes {
+                message.push(byte);
+                if message.ends_with(delimiter) {
+                    message.truncate(message.len() - delimiter.len());
+                    return Ok(message);
+                }
+            }
+            Err(ApplicationError::DecodingError(
+                "Delimiter not found in the image".to_string(),
+            ))
         }
+    }
+}
 
-        let byte = byte_bits.iter().fold(0u8, |acc, &bit| (acc << 1) | bit);
+/// The header is always written pixel after pixel, one bit per color channel, whatever
+/// the payload's stride, depth and use of alpha
+pub fn header_layout(layout: &Layout) -> Layout {
+    Layout {
+        stride: 1,
+        depth: 1,
+        use_alpha: false,
+        ..layout.clone()
+    }
+}
+
+/// Mask selecting the `depth` least significant bits of a sample
+fn low_bits_mask(depth: usize) -> u8 {
+    ((1u16 << depth) - 1) as u8
+}
+
+/// Rejects carriers with zero width or height, reporting them through `error`
+fn ensure_has_pixels<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    error: fn(String) -> ApplicationError,
+) -> Result<(), ApplicationError> {
+    if is_degenerate(image) {
+        return Err(error(format!(
+            "Carrier image has no pixels ({}x{})",
+            image.width(),
+            image.height()
+        )));
+    }
+    Ok(())
+}
+
+/// Rejects a layout region that does not lie within the carrier, reporting it through `error`
+fn ensure_region_fits<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+    error: fn(String) -> ApplicationError,
+) -> Result<(), ApplicationError> {
+    match layout.region {
+        Some(region) if !region.fits(image.width(), image.height()) => Err(error(format!(
+            "Region {} does not fit in the {}x{} carrier",
+            region,
+            image.width(),
+            image.height()
+        ))),
+        _ => Ok(()),
+    }
+}
 
-        if byte == 0 {
+/// Writes the bits of `bytes` into the low `depth` bits of the layout's channels,
+/// starting after its first `skip` pixels; a final partial group is padded with zeros
+fn embed_bytes<P: Pixel<Subpixel = u8>>(
+    bytes: &[u8],
+    image: &mut ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+    skip: usize,
+) {
+    let channels = layout.channels_after(image, skip);
+    let (samples, usable) = layout.pixel_channels::<P>();
+    let image_data = image.as_flat_samples_mut().samples;
+
+    if layout.is_sequential() && usable == samples {
+        image_data[skip * samples..]
+            .par_chunks_mut(BITS_PER_BYTE)
+            .zip(bytes.par_iter())
+            .for_each(|(chunk, &data_byte)| {
+                chunk.iter_mut().enumerate().for_each(|(i, pixel_byte)| {
+                    let bit = (data_byte >> (BITS_PER_BYTE - 1 - i)) & 1;
+                    *pixel_byte = (*pixel_byte & !1) | bit;
+                });
+            });
+        return;
+    }
+
+    let depth = layout.depth.max(1);
+    let mask = low_bits_mask(depth);
+    let mut bits = bytes
+        .iter()
+        .flat_map(|&data_byte| (0..BITS_PER_BYTE).rev().map(move |i| (data_byte >> i) & 1))
+        .peekable();
+    for index in channels {
+        if bits.peek().is_none() {
             break;
         }
-        bytes.push(byte);
+        let value = (0..depth).fold(0u8, |acc, _| (acc << 1) | bits.next().unwrap_or(0));
+        image_data[index] = (image_data[index] & !mask) | value;
     }
+}
+
+/// Reassembles up to `length` bytes from the low `depth` bits of the layout's
+/// channels, starting after its first `skip` pixels
+fn extract_bytes<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+    skip: usize,
+    length: usize,
+) -> Vec<u8> {
+    let image_data = image.as_flat_samples().samples;
+    let (samples, usable) = layout.pixel_channels::<P>();
 
+    if layout.is_sequential() && usable == samples {
+        // Every channel holds one bit, so each byte comes from the next 8 samples
+        let start = (skip * samples).min(image_data.len());
+        let end = start.saturating_add(length.saturating_mul(BITS_PER_BYTE));
+        return image_data[start..end.min(image_data.len())]
+            .par_chunks_exact(BITS_PER_BYTE)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .fold(0u8, |acc, &sample| (acc << 1) | (sample & 1))
+            })
+            .collect();
+    }
+
+    extract_bytes_serial(image, layout, skip, length)
+}
+
+/// [`extract_bytes`] for any layout, visiting the layout's channels one at a time
+fn extract_bytes_serial<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+    skip: usize,
+    length: usize,
+) -> Vec<u8> {
+    serial_bytes(image, layout, skip).take(length).collect()
+}
+
+/// The bytes held by the layout's channels after `skip` pixels, read lazily so that only
+/// the bytes taken are ever extracted; a trailing partial byte is dropped
+fn serial_bytes<'a, P: Pixel<Subpixel = u8>>(
+    image: &'a ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+    skip: usize,
+) -> impl Iterator<Item = u8> + 'a {
+    let image_data = image.as_flat_samples().samples;
+    let depth = layout.depth.max(1);
+    let mut bits = layout.channels_after(image, skip).flat_map(move |index| {
+        let sample = image_data[index];
+        (0..depth).rev().map(move |i| (sample >> i) & 1)
+    });
+
+    std::iter::from_fn(move || {
+        (0..BITS_PER_BYTE).try_fold(0u8, |acc, _| Some((acc << 1) | bits.next()?))
+    })
+}
+
+/// Decoded bytes as text, failing on invalid UTF-8
+pub fn bytes_to_string(bytes: Vec<u8>) -> Result<String, ApplicationError> {
     String::from_utf8(bytes).map_err(|e| {
         ApplicationError::DecodingError(format!("Invalid UTF-8 sequence in decoded data: {}", e))
     })
 }
 
+/// Decoded bytes as text, replacing invalid UTF-8 (such as a character cut short by
+/// corruption) with U+FFFD; also returns how many bytes were replaced
+pub fn bytes_to_string_lossy(bytes: Vec<u8>) -> (String, usize) {
+    let replaced = bytes.utf8_chunks().map(|chunk| chunk.invalid().len()).sum();
+    (String::from_utf8_lossy(&bytes).into_owned(), replaced)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use image::{Rgb, RgbImage};
+    use crate::steganography::analysis::salient_pixels;
+    use crate::steganography::header::{CHECKSUM_SIZE, HEADER_SIZE, VERSION};
+    use crate::steganography::layout::Region;
+    use crate::steganography::util::payload_capacity_bits;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    /// Leading pixels a header of the current format occupies in a color carrier
+    const HEADER_PIXELS: usize = (HEADER_SIZE * BITS_PER_BYTE).div_ceil(HEADER_CHANNELS_PER_PIXEL);
+
+    fn decode_text<P: Pixel<Subpixel = u8>>(
+        image: &ImageBuffer<P, Vec<u8>>,
+        layout: &Layout,
+    ) -> Result<String, ApplicationError> {
+        decode(image, layout).and_then(bytes_to_string)
+    }
 
     fn create_blank_image(width: u32, height: u32) -> RgbImage {
         RgbImage::from_pixel(width, height, Rgb([0, 0, 0]))
     }
 
+    /// Textured carrier with a strong vertical edge down the middle
+    fn create_textured_image(width: u32, height: u32) -> RgbImage {
+        let mut state = 0x2545_f491u32;
+        RgbImage::from_fn(width, height, |x, _| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let noise = (state >> 24) as u8 % 16;
+            let base = if x < width / 2 { 40 } else { 200 };
+            Rgb([base + noise, base + noise / 2, base + noise / 3])
+        })
+    }
+
     #[test]
     fn test_encode_decode() {
         let mut image = create_blank_image(10, 10);
         let data = "Hello, World!";
-        encode(data, &mut image).expect("Encoding failed");
-        let decoded_data = decode(&image).expect("Decoding failed");
+        encode(data, &mut image, &Layout::default(), PayloadInfo::default())
+            .expect("Encoding failed");
+        let decoded_data = decode_text(&image, &Layout::default()).expect("Decoding failed");
 
         assert_eq!(data, decoded_data);
     }
 
+    #[test]
+    fn test_encode_decode_with_checksum() {
+        let mut image = create_blank_image(40, 40);
+        let data = "Hello, World!";
+        let written = encode(
+            data,
+            &mut image,
+            &Layout::default(),
+            PayloadInfo {
+                checksum: Some([9; CHECKSUM_SIZE]),
+                ..PayloadInfo::default()
+            },
+        )
+        .unwrap();
+        let (decoded, header) = decode_with_header(&image, &Layout::default()).unwrap();
+
+        assert_eq!(decoded, data.as_bytes());
+        assert_eq!(header, Some(written));
+        assert_eq!(written.checksum, Some([9; CHECKSUM_SIZE]));
+
+        let mut small = create_blank_image(10, 10);
+        assert!(encode(data, &mut small, &Layout::default(), PayloadInfo::default()).is_ok());
+        assert!(encode(
+            data,
+            &mut small,
+            &Layout::default(),
+            PayloadInfo {
+                checksum: Some([9; CHECKSUM_SIZE]),
+                ..PayloadInfo::default()
+            }
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_insufficient_capacity() {
         let mut image = create_blank_image(1, 1);
         let data = "This message is too long to fit";
-        let result = encode(data, &mut image);
+        let result = encode(data, &mut image, &Layout::default(), PayloadInfo::default());
 
-        assert!(result.is_err());
+        match result.unwrap_err() {
+            ApplicationError::InsufficientCapacity { needed, available } => {
+                assert_eq!(needed, HEADER_SIZE + data.len());
+                assert_eq!(available, 0);
+            }
+            error => panic!("Unexpected error: {}", error),
+        }
+
+        let mut image = create_blank_image(10, 10);
+        let data = "x".repeat(38);
+        assert_eq!(
+            encode(
+                &data,
+                &mut image,
+                &Layout::default(),
+                PayloadInfo::default()
+            )
+            .unwrap_err()
+            .to_string(),
+            "Image too small: need 48 bytes but only 37 available"
+        );
         assert_eq!(
-            result.unwrap_err().to_string(),
-            "Encoding error: Image too small to encode data"
+            encode_headerless(&data, &mut image, &Layout::default())
+                .unwrap_err()
+                .to_string(),
+            "Image too small: need 38 bytes but only 37 available"
         );
     }
 
     #[test]
     fn test_encode_empty_string() {
-        let mut image = create_blank_image(5, 5);
+        let mut image = create_blank_image(6, 6);
         let data = "";
-        encode(data, &mut image).expect("Encoding failed");
-        let decoded_data = decode(&image).expect("Decoding failed");
+        encode(data, &mut image, &Layout::default(), PayloadInfo::default())
+            .expect("Encoding failed");
+        let decoded_data = decode_text(&image, &Layout::default()).expect("Decoding failed");
 
         assert_eq!(data, decoded_data);
     }
 
+    #[test]
+    fn test_zero_area_carrier() {
+        for (width, height) in [(0, 0), (0, 4), (4, 0)] {
+            let mut image = create_blank_image(width, height);
+            for data in ["", "A"] {
+                let result = encode(data, &mut image, &Layout::default(), PayloadInfo::default());
+                assert_eq!(
+                    result.unwrap_err().to_string(),
+                    format!(
+                        "Encoding error: Carrier image has no pixels ({}x{})",
+                        width, height
+                    )
+                );
+            }
+            assert!(encode_headerless("", &mut image, &Layout::default()).is_err());
+            assert!(decode_text(&image, &Layout::default()).is_err());
+            assert!(decode_headerless(&image, 0, &Layout::default()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_single_pixel_carrier() {
+        let mut image = create_blank_image(1, 1);
+
+        for data in ["", "A"] {
+            let result = encode(data, &mut image, &Layout::default(), PayloadInfo::default());
+            assert_eq!(
+                result.unwrap_err().to_string(),
+                format!(
+                    "Image too small: need {} bytes but only 0 available",
+                    HEADER_SIZE + data.len()
+                )
+            );
+        }
+        assert_eq!(
+            decode_text(&image, &Layout::default())
+                .unwrap_err()
+                .to_string(),
+            "Decoding error: Image too small to contain a message"
+        );
+        assert_eq!(
+            decode_headerless(&image, 0, &Layout::default()).unwrap(),
+            b""
+        );
+    }
+
     #[test]
     fn test_encode_decode_with_delimiter() {
         let mut image = create_blank_image(10, 10);
         let data = "Message with delimiter test";
-        encode(data, &mut image).expect("Encoding failed");
-        let decoded_data = decode(&image).expect("Decoding failed");
+        encode(data, &mut image, &Layout::default(), PayloadInfo::default())
+            .expect("Encoding failed");
+        let decoded_data = decode_text(&image, &Layout::default()).expect("Decoding failed");
 
         assert_eq!(data, decoded_data);
     }
+
+    #[test]
+    fn test_encode_decode_with_stride() {
+        for stride in [2, 3] {
+            // Every LSB starts at 1, so any zero bit written lands as a visible change
+            let original = RgbImage::from_pixel(20, 20, Rgb([1, 1, 1]));
+            let mut image = original.clone();
+            let layout = Layout {
+                stride,
+                ..Layout::default()
+            };
+            let data = "Strided message";
+            encode(data, &mut image, &layout, PayloadInfo::default()).expect("Encoding failed");
+
+            let changed: Vec<bool> = original
+                .pixels()
+                .zip(image.pixels())
+                .skip(HEADER_PIXELS)
+                .map(|(a, b)| a != b)
+                .collect();
+            assert!(changed.iter().step_by(stride).any(|&changed| changed));
+            assert!(changed
+                .iter()
+                .enumerate()
+                .all(|(i, &changed)| i % stride == 0 || !changed));
+
+            // The stride is read back from the header
+            let decoded_data = decode_text(&image, &Layout::default()).expect("Decoding failed");
+            assert_eq!(data, decoded_data);
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_with_depth() {
+        for depth in [2, 3] {
+            let original = create_textured_image(16, 16);
+            let mut image = original.clone();
+            let layout = Layout {
+                depth,
+                ..Layout::default()
+            };
+            let data = "Deeper message packing several bits into every channel";
+            encode(data, &mut image, &layout, PayloadInfo::default()).expect("Encoding failed");
+
+            // Only the low `depth` bits of each sample may change
+            let untouched = !low_bits_mask(depth);
+            assert!(original
+                .as_raw()
+                .iter()
+                .zip(image.as_raw())
+                .all(|(a, b)| a & untouched == b & untouched));
+
+            // The depth is read back from the header
+            let decoded_data = decode_text(&image, &Layout::default()).expect("Decoding failed");
+            assert_eq!(data, decoded_data);
+        }
+    }
+
+    #[test]
+    fn test_depth_increases_capacity() {
+        let data = "x".repeat(40);
+        let mut image = create_blank_image(16, 8);
+        assert!(encode(
+            &data,
+            &mut image,
+            &Layout::default(),
+            PayloadInfo::default()
+        )
+        .is_err());
+
+        let layout = Layout {
+            depth: 2,
+            ..Layout::default()
+        };
+        encode(&data, &mut image, &layout, PayloadInfo::default()).expect("Encoding failed");
+        assert_eq!(decode_text(&image, &Layout::default()).unwrap(), data);
+    }
+
+    #[test]
+    fn test_invalid_depth() {
+        let mut image = create_blank_image(10, 10);
+        for depth in [0, 5] {
+            let layout = Layout {
+                depth,
+                ..Layout::default()
+            };
+            assert!(encode("A", &mut image, &layout, PayloadInfo::default()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_decode_version_1_header() {
+        let mut image = create_blank_image(10, 10);
+        let v1_header = [b'M', b'B', 1, 0, 1, 0, 0, 0, 2];
+        embed_bytes(&v1_header, &mut image, &Layout::default(), 0);
+        embed_bytes(b"v1", &mut image, &Layout::default(), 24);
+
+        assert_eq!(decode_text(&image, &Layout::default()).unwrap(), "v1");
+    }
+
+    #[test]
+    fn test_decode_rejects_future_version() {
+        let mut image = create_blank_image(10, 10);
+        encode(
+            "From the future",
+            &mut image,
+            &Layout::default(),
+            PayloadInfo::default(),
+        )
+        .unwrap();
+        let mut header = Header::new(1, 1, 15).to_bytes();
+        header[2] = VERSION + 1;
+        embed_bytes(&header, &mut image, &Layout::default(), 0);
+
+        match decode(&image, &Layout::default()) {
+            Err(ApplicationError::DecodingError(message)) => assert!(message.contains("newer")),
+            other => panic!("Expected a version error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_with_seed() {
+        let seeded = |seed| Layout {
+            seed: Some(seed),
+            ..Layout::default()
+        };
+        let original = RgbImage::from_pixel(32, 32, Rgb([1, 1, 1]));
+        let mut image = original.clone();
+        let data = "Scattered message";
+        encode(data, &mut image, &seeded(42), PayloadInfo::default()).expect("Encoding failed");
+
+        // A sequential payload would leave everything past its first pixels untouched
+        let sequential_end = HEADER_PIXELS + (data.len() * BITS_PER_BYTE).div_ceil(3);
+        assert!(original
+            .pixels()
+            .zip(image.pixels())
+            .skip(sequential_end)
+            .any(|(a, b)| a != b));
+
+        assert_eq!(decode_text(&image, &seeded(42)).unwrap(), data);
+        assert_ne!(decode_text(&image, &seeded(43)).ok().as_deref(), Some(data));
+        assert_ne!(
+            decode_text(&image, &Layout::default()).ok().as_deref(),
+            Some(data)
+        );
+    }
+
+    #[test]
+    fn test_encode_decode_with_alpha() {
+        // Fully opaque, as most screenshots are
+        let original = RgbaImage::from_pixel(12, 12, Rgba([10, 20, 30, 255]));
+        let mut image = original.clone();
+        let layout = Layout {
+            use_alpha: true,
+            ..Layout::default()
+        };
+        let data = "Alpha-borne message filling well past the color channels";
+        encode(data, &mut image, &layout, PayloadInfo::default()).expect("Encoding failed");
+
+        let alpha_changed = original
+            .pixels()
+            .zip(image.pixels())
+            .filter(|(a, b)| a[3] != b[3])
+            .count();
+        assert!(alpha_changed > 0);
+        assert!(image.pixels().all(|pixel| pixel[3] >= 254));
+
+        // Use of alpha is read back from the header
+        assert_eq!(decode_text(&image, &Layout::default()).unwrap(), data);
+    }
+
+    #[test]
+    fn test_alpha_adds_a_third_of_capacity() {
+        let image = RgbaImage::new(10, 10);
+        let with_alpha = Layout {
+            use_alpha: true,
+            ..Layout::default()
+        };
+        let without = payload_capacity_bits(&image, &Layout::default(), HEADER_SIZE);
+
+        assert_eq!(
+            payload_capacity_bits(&image, &with_alpha, HEADER_SIZE),
+            without * 4 / 3
+        );
+    }
+
+    #[test]
+    fn test_alpha_requires_alpha_channel() {
+        let mut image = create_blank_image(10, 10);
+        let layout = Layout {
+            use_alpha: true,
+            ..Layout::default()
+        };
+
+        assert_eq!(
+            encode("A", &mut image, &layout, PayloadInfo::default())
+                .unwrap_err()
+                .to_string(),
+            "Encoding error: Carrier image has no alpha channel"
+        );
+    }
+
+    #[test]
+    fn test_rgba_without_alpha_matches_rgb() {
+        let mut rgb = create_textured_image(16, 16);
+        let mut rgba: RgbaImage = image::DynamicImage::ImageRgb8(rgb.clone()).into_rgba8();
+        let layout = Layout {
+            palette_safe: true,
+            seed: Some(3),
+            ..Layout::default()
+        };
+        encode(
+            "Same bits either way",
+            &mut rgb,
+            &layout,
+            PayloadInfo::default(),
+        )
+        .expect("Encoding failed");
+        encode(
+            "Same bits either way",
+            &mut rgba,
+            &layout,
+            PayloadInfo::default(),
+        )
+        .expect("Encoding failed");
+
+        assert_eq!(image::DynamicImage::ImageRgba8(rgba).into_rgb8(), rgb);
+    }
+
+    #[test]
+    fn test_encode_decode_grayscale() {
+        let mut image = image::GrayImage::from_fn(20, 20, |x, y| image::Luma([(x * 12 + y) as u8]));
+        for layout in [
+            Layout::default(),
+            Layout {
+                seed: Some(9),
+                depth: 2,
+                ..Layout::default()
+            },
+        ] {
+            encode("Luma only", &mut image, &layout, PayloadInfo::default())
+                .expect("Encoding failed");
+
+            assert_eq!(decode_text(&image, &layout).unwrap(), "Luma only");
+        }
+    }
+
+    #[test]
+    fn test_randomize_unused_hides_previous_payload() {
+        let ones_ratio = |image: &RgbImage, channels: &[usize]| {
+            let samples = image.as_flat_samples().samples;
+            let ones = channels.iter().filter(|&&i| samples[i] & 1 == 1).count();
+            ones as f64 / channels.len() as f64
+        };
+        let layout = Layout::default();
+        let mut image = create_blank_image(64, 64);
+        let old_payload = "A".repeat(600);
+        encode(&old_payload, &mut image, &layout, PayloadInfo::default()).expect("Encoding failed");
+
+        let new_payload = "short";
+        let header = encode(new_payload, &mut image, &layout, PayloadInfo::default())
+            .expect("Encoding failed");
+        randomize_unused(&mut image, &layout, &header);
+
+        // Compare the LSB plane just inside and just past the old payload's end
+        let payload_channels: Vec<usize> = layout.channels_after(&image, HEADER_PIXELS).collect();
+        let boundary = old_payload.len() * BITS_PER_BYTE;
+        let before = ones_ratio(&image, &payload_channels[boundary - 2000..boundary]);
+        let after = ones_ratio(&image, &payload_channels[boundary..boundary + 2000]);
+
+        assert!((before - after).abs() < 0.1, "{} vs {}", before, after);
+        assert!((after - 0.5).abs() < 0.1);
+        assert_eq!(decode_text(&image, &layout).unwrap(), new_payload);
+    }
+
+    #[test]
+    fn test_encode_decode_with_null_bytes() {
+        let mut image = create_blank_image(20, 20);
+        let data = "\0leading, em\0bedded and trailing\0";
+        encode(data, &mut image, &Layout::default(), PayloadInfo::default())
+            .expect("Encoding failed");
+        let decoded_data = decode_text(&image, &Layout::default()).expect("Decoding failed");
+
+        assert_eq!(data, decoded_data);
+    }
+
+    #[test]
+    fn test_decode_small_and_large_payloads() {
+        let layouts = [
+            Layout::default(),
+            Layout {
+                seed: Some(11),
+                depth: 2,
+                ..Layout::default()
+            },
+        ];
+        for layout in layouts {
+            let mut image = create_textured_image(200, 200);
+            let capacity = payload_capacity_bits(&image, &layout, HEADER_SIZE) / BITS_PER_BYTE;
+            for length in [1, capacity / 2, capacity] {
+                let data: String = ('a'..='z').cycle().take(length).collect();
+                encode(&data, &mut image, &layout, PayloadInfo::default()).unwrap();
+
+                assert_eq!(decode_text(&image, &layout).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_length_beyond_capacity() {
+        let mut image = create_blank_image(10, 10);
+        embed_bytes(
+            &Header::new(1, 1, u32::MAX).to_bytes(),
+            &mut image,
+            &Layout::default(),
+            0,
+        );
+
+        match decode(&image, &Layout::default()) {
+            Err(ApplicationError::DecodingError(message)) => assert!(message.contains("at most")),
+            other => panic!("Expected a capacity error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_region_leaves_outside_pixels_untouched() {
+        let original = create_textured_image(40, 40);
+        let mut image = original.clone();
+        let region = Region {
+            x: 24,
+            y: 24,
+            width: 16,
+            height: 16,
+        };
+        let layout = Layout {
+            region: Some(region),
+            ..Layout::default()
+        };
+        let header = encode("In the corner", &mut image, &layout, PayloadInfo::default()).unwrap();
+
+        assert_eq!(header.region, Some(region));
+        assert_eq!(decode_text(&image, &layout).unwrap(), "In the corner");
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if x < region.x || y < region.y {
+                assert_eq!(
+                    pixel,
+                    original.get_pixel(x, y),
+                    "pixel ({}, {}) changed",
+                    x,
+                    y
+                );
+            }
+        }
+        assert_ne!(image, original);
+    }
+
+    #[test]
+    fn test_region_must_fit_and_hold_the_payload() {
+        let mut image = create_blank_image(10, 10);
+        let layout = |region: &str| Layout {
+            region: Some(region.parse().unwrap()),
+            ..Layout::default()
+        };
+
+        assert!(encode("Hi", &mut image, &layout("5,5,6,5"), PayloadInfo::default()).is_err());
+        assert!(decode(&image, &layout("0,0,0,1")).is_err());
+        assert!(matches!(
+            encode("Hi", &mut image, &layout("0,0,5,5"), PayloadInfo::default()),
+            Err(ApplicationError::InsufficientCapacity { .. })
+        ));
+    }
+
+    /// Writes `bytes` into the LSBs of the R, G and B channels in raster order, MSB
+    /// first, as other LSB tools do
+    fn embed_raw(bytes: &[u8], image: &mut RgbImage) {
+        let bits = bytes
+            .iter()
+            .flat_map(|&byte| (0..BITS_PER_BYTE).rev().map(move |i| (byte >> i) & 1));
+        for (channel, bit) in image.iter_mut().zip(bits) {
+            *channel = (*channel & !1) | bit;
+        }
+    }
+
+    #[test]
+    fn test_decode_raw_with_length_prefix() {
+        let mut image = create_textured_image(10, 10);
+        embed_raw(
+            &[0, 0, 0, 5, b'h', b'e', b'l', b'l', b'o', b'!'],
+            &mut image,
+        );
+
+        let decoded = decode_raw(&image, &Layout::default(), &RawFraming::LengthPrefix);
+        assert_eq!(decoded.unwrap(), b"hello");
+
+        embed_raw(&[0, 0, 1, 0], &mut image);
+        assert!(decode_raw(&image, &Layout::default(), &RawFraming::LengthPrefix).is_err());
+    }
+
+    #[test]
+    fn test_decode_raw_with_delimiter() {
+        let mut image = create_textured_image(10, 10);
+        embed_raw(b"hello$$$world", &mut image);
+        let framing = |delimiter: &[u8]| RawFraming::Delimiter(delimiter.to_vec());
+
+        assert_eq!(
+            decode_raw(&image, &Layout::default(), &framing(b"$$$")).unwrap(),
+            b"hello"
+        );
+        assert!(decode_raw(&image, &Layout::default(), &framing(b"##")).is_err());
+        assert!(decode_raw(&image, &Layout::default(), &framing(b"")).is_err());
+    }
+
+    #[test]
+    fn test_decode_legacy_delimited_image() {
+        let mut image = create_blank_image(10, 10);
+        embed_bytes(b"Old message\0", &mut image, &Layout::default(), 0);
+
+        assert_eq!(
+            decode_text(&image, &Layout::default()).expect("Decoding failed"),
+            "Old message"
+        );
+    }
+
+    #[test]
+    fn test_decode_legacy_message_starting_with_magic() {
+        // Reads as a header of version 'a', which would be rejected as too new
+        let mut image = create_blank_image(10, 10);
+        embed_bytes(b"MBa legacy message\0", &mut image, &Layout::default(), 0);
+
+        assert_eq!(
+            decode_text(&image, &Layout::default()).expect("Decoding failed"),
+            "MBa legacy message"
+        );
+        let (_, header) = decode_with_header(&image, &Layout::default()).unwrap();
+        assert!(header.is_none());
+    }
+
+    #[test]
+    fn test_encode_decode_headerless() {
+        let mut image = create_blank_image(10, 10);
+        let data = "Headerless payload";
+        encode_headerless(data, &mut image, &Layout::default()).expect("Encoding failed");
+        let decoded_data =
+            decode_headerless(&image, data.len(), &Layout::default()).expect("Decoding failed");
+
+        assert_eq!(data.as_bytes(), decoded_data);
+    }
+
+    #[test]
+    fn test_headerless_uses_full_capacity() {
+        let mut image = create_blank_image(8, 1);
+        let data = "abc";
+        encode_headerless(data, &mut image, &Layout::default()).expect("Encoding failed");
+
+        assert!(encode(data, &mut image, &Layout::default(), PayloadInfo::default()).is_err());
+        assert_eq!(
+            decode_headerless(&image, 3, &Layout::default()).unwrap(),
+            data.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_decode_headerless_length_exceeds_capacity() {
+        let image = create_blank_image(2, 2);
+
+        assert!(decode_headerless(&image, 10, &Layout::default()).is_err());
+    }
+
+    #[test]
+    fn test_encode_decode_palette_safe() {
+        let mut image = create_textured_image(32, 32);
+        let layout = Layout {
+            palette_safe: true,
+            ..Layout::default()
+        };
+        let data = "Palette-safe payload that spans a good part of the carrier image";
+        encode(data, &mut image, &layout, PayloadInfo::default()).expect("Encoding failed");
+        let decoded_data = decode_text(&image, &layout).expect("Decoding failed");
+
+        assert_eq!(data, decoded_data);
+    }
+
+    #[test]
+    fn test_encode_decode_palette_safe_at_every_depth() {
+        for depth in 1..=MAX_DEPTH as usize {
+            let original = create_textured_image(32, 32);
+            let mut image = original.clone();
+            let layout = Layout {
+                palette_safe: true,
+                depth,
+                ..Layout::default()
+            };
+            let data = "x"
+                .repeat(payload_capacity_bits(&original, &layout, MAX_HEADER_SIZE) / BITS_PER_BYTE);
+            encode(&data, &mut image, &layout, PayloadInfo::default()).expect("Encoding failed");
+
+            assert_eq!(
+                salient_pixels(&image, 0.25),
+                salient_pixels(&original, 0.25)
+            );
+            assert_eq!(decode_text(&image, &layout).expect("Decoding failed"), data);
+        }
+    }
+
+    #[test]
+    fn test_palette_safe_spares_salient_pixels() {
+        let original = create_textured_image(32, 32);
+        let mut image = original.clone();
+        let layout = Layout {
+            palette_safe: true,
+            ..Layout::default()
+        };
+        let data =
+            "x".repeat(payload_capacity_bits(&original, &layout, HEADER_SIZE) / BITS_PER_BYTE);
+        encode(&data, &mut image, &layout, PayloadInfo::default()).expect("Encoding failed");
+
+        let salient = salient_pixels(&original, 0.25);
+        let modified: Vec<bool> = original
+            .pixels()
+            .zip(image.pixels())
+            .map(|(a, b)| a != b)
+            .collect();
+        let rate = |pixels: Vec<bool>| {
+            pixels.iter().filter(|&&changed| changed).count() as f64 / pixels.len() as f64
+        };
+        let salient_rate = rate(
+            modified
+                .iter()
+                .zip(&salient)
+                .filter(|(_, &is_salient)| is_salient)
+                .map(|(&changed, _)| changed)
+                .collect(),
+        );
+        let overall_rate = rate(modified);
+
+        assert!(salient.iter().any(|&is_salient| is_salient));
+        assert!(salient_rate < overall_rate);
+    }
+
+    #[test]
+    fn test_bytes_ending_mid_character() {
+        let mut image = create_blank_image(10, 10);
+        encode_headerless("a🦀", &mut image, &Layout::default()).unwrap();
+        let bytes = decode_headerless(&image, 3, &Layout::default()).unwrap();
+
+        assert!(bytes_to_string(bytes.clone()).is_err());
+        assert_eq!(bytes_to_string_lossy(bytes), ("a\u{FFFD}".to_string(), 2));
+        assert_eq!(
+            bytes_to_string_lossy("a🦀".as_bytes().to_vec()),
+            ("a🦀".to_string(), 0)
+        );
+    }
+
+    #[test]
+    fn test_parallel_extraction_matches_serial() {
+        let mut image = create_textured_image(40, 30);
+        encode(
+            "Parallel and serial agree",
+            &mut image,
+            &Layout::default(),
+            PayloadInfo::default(),
+        )
+        .unwrap();
+
+        let layout = Layout::default();
+        for (skip, length) in [
+            (0, 0),
+            (0, HEADER_SIZE),
+            (HEADER_PIXELS, 25),
+            (5, usize::MAX),
+        ] {
+            assert_eq!(
+                extract_bytes(&image, &layout, skip, length),
+                extract_bytes_serial(&image, &layout, skip, length),
+                "skip {} length {}",
+                skip,
+                length
+            );
+        }
+    }
 }
@@ -1,2 +1,6 @@
+pub mod analysis;
+pub mod gif;
+pub mod header;
+pub mod layout;
 pub mod lsb;
 pub mod util;
@@ -1,21 +1,70 @@
-use image::RgbImage;
+use super::layout::Layout;
+use super::lsb::{header_layout, header_pixels};
+use image::{ImageBuffer, Pixel};
+
+const BITS_PER_BYTE: usize = 8;
+
+/// Checks if an image has no pixels at all (zero width or height)
+pub fn is_degenerate<P: Pixel>(image: &ImageBuffer<P, Vec<P::Subpixel>>) -> bool {
+    image.width() == 0 || image.height() == 0
+}
 
 /// Checks if an image has sufficient capacity to store the given text (LSB)
-pub fn is_sufficient_capacity(text: &str, image: &RgbImage) -> bool {
-    const BITS_PER_CHAR: usize = 8;
-    const DELIMITER_SIZE: usize = 1;
+///
+/// The layout's first pixels are reserved for a header of `header_size` bytes, and the
+/// text has to fit in the pixels that follow
+pub fn is_sufficient_capacity<P: Pixel<Subpixel = u8>>(
+    text: &str,
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+    header_size: usize,
+) -> bool {
+    (header_size + text.len()) * BITS_PER_BYTE <= image_capacity_bits(image, layout, header_size)
+}
+
+/// Number of bits the carrier can hold with the layout, header of `header_size` bytes
+/// included
+///
+/// When not even the header fits, this is the part of it that does
+pub fn image_capacity_bits<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+    header_size: usize,
+) -> usize {
+    let header_bits = header_layout(layout)
+        .capacity_bits(image)
+        .min(header_size * BITS_PER_BYTE);
 
-    let text_length = text.len() + DELIMITER_SIZE;
-    let total_bits_needed = text_length * BITS_PER_CHAR;
-    let available_bits = image.as_flat_samples().samples.len();
+    header_bits + payload_capacity_bits(image, layout, header_size)
+}
 
-    total_bits_needed <= available_bits
+/// Number of whole bytes the carrier can hold with the layout, header included
+pub fn image_capacity_bytes<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+    header_size: usize,
+) -> usize {
+    image_capacity_bits(image, layout, header_size) / BITS_PER_BYTE
+}
+
+/// Number of payload bits the carrier can hold after a header of `header_size` bytes, at
+/// the layout's depth
+pub fn payload_capacity_bits<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    layout: &Layout,
+    header_size: usize,
+) -> usize {
+    layout
+        .channels_after(image, header_pixels::<P>(header_size))
+        .count()
+        * layout.depth.max(1)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use image::Rgb;
+    use crate::steganography::header::{CHECKSUM_SIZE, HEADER_SIZE};
+    use image::{Rgb, RgbImage};
 
     fn create_test_image(width: u32, height: u32) -> RgbImage {
         RgbImage::from_pixel(width, height, Rgb([0, 0, 0]))
@@ -26,7 +75,12 @@ mod tests {
         let image = create_test_image(2, 2);
         let text = "A";
 
-        assert!(!is_sufficient_capacity(text, &image));
+        assert!(!is_sufficient_capacity(
+            text,
+            &image,
+            &Layout::default(),
+            HEADER_SIZE
+        ));
     }
 
     #[test]
@@ -34,7 +88,12 @@ mod tests {
         let image = create_test_image(10, 10);
         let text = "Hello!";
 
-        assert!(is_sufficient_capacity(text, &image));
+        assert!(is_sufficient_capacity(
+            text,
+            &image,
+            &Layout::default(),
+            HEADER_SIZE
+        ));
     }
 
     #[test]
@@ -42,15 +101,202 @@ mod tests {
         let image = create_test_image(2, 2);
         let text = "Too long for this image size";
 
-        assert!(!is_sufficient_capacity(text, &image));
+        assert!(!is_sufficient_capacity(
+            text,
+            &image,
+            &Layout::default(),
+            HEADER_SIZE
+        ));
     }
 
     #[test]
     fn test_unicode() {
-        let image = create_test_image(5, 5);
+        let image = create_test_image(8, 8);
         let text = "🦀";
 
-        assert!(is_sufficient_capacity(text, &image));
+        assert!(is_sufficient_capacity(
+            text,
+            &image,
+            &Layout::default(),
+            HEADER_SIZE
+        ));
+    }
+
+    #[test]
+    fn test_is_degenerate() {
+        assert!(is_degenerate(&create_test_image(0, 0)));
+        assert!(is_degenerate(&create_test_image(0, 10)));
+        assert!(!is_degenerate(&create_test_image(1, 1)));
+    }
+
+    #[test]
+    fn test_empty_text_needs_room_for_header() {
+        assert!(!is_sufficient_capacity(
+            "",
+            &create_test_image(1, 1),
+            &Layout::default(),
+            HEADER_SIZE
+        ));
+        assert!(!is_sufficient_capacity(
+            "",
+            &create_test_image(26, 1),
+            &Layout::default(),
+            HEADER_SIZE
+        ));
+        assert!(is_sufficient_capacity(
+            "",
+            &create_test_image(27, 1),
+            &Layout::default(),
+            HEADER_SIZE
+        ));
+
+        // A checksum adds 32 bytes, one bit per channel
+        assert!(!is_sufficient_capacity(
+            "",
+            &create_test_image(111, 1),
+            &Layout::default(),
+            HEADER_SIZE + CHECKSUM_SIZE
+        ));
+        assert!(is_sufficient_capacity(
+            "",
+            &create_test_image(112, 1),
+            &Layout::default(),
+            HEADER_SIZE + CHECKSUM_SIZE
+        ));
+    }
+
+    #[test]
+    fn test_stride_divides_capacity() {
+        let image = create_test_image(10, 10);
+        let strided = Layout {
+            stride: 2,
+            ..Layout::default()
+        };
+
+        assert_eq!(
+            payload_capacity_bits(&image, &Layout::default(), HEADER_SIZE),
+            73 * 3
+        );
+        assert_eq!(payload_capacity_bits(&image, &strided, HEADER_SIZE), 37 * 3);
+        assert!(is_sufficient_capacity(
+            &"x".repeat(13),
+            &image,
+            &strided,
+            HEADER_SIZE
+        ));
+        assert!(!is_sufficient_capacity(
+            &"x".repeat(14),
+            &image,
+            &strided,
+            HEADER_SIZE
+        ));
+    }
+
+    #[test]
+    fn test_depth_multiplies_capacity() {
+        let image = create_test_image(10, 10);
+        let deep = Layout {
+            depth: 3,
+            ..Layout::default()
+        };
+
+        assert_eq!(
+            payload_capacity_bits(&image, &deep, HEADER_SIZE),
+            73 * 3 * 3
+        );
+        assert!(is_sufficient_capacity(
+            &"x".repeat(82),
+            &image,
+            &deep,
+            HEADER_SIZE
+        ));
+        assert!(!is_sufficient_capacity(
+            &"x".repeat(83),
+            &image,
+            &deep,
+            HEADER_SIZE
+        ));
+    }
+
+    #[test]
+    fn test_image_capacity_includes_header() {
+        let image = create_test_image(10, 10);
+
+        assert_eq!(
+            image_capacity_bits(&image, &Layout::default(), HEADER_SIZE),
+            HEADER_SIZE * BITS_PER_BYTE + 73 * 3
+        );
+        assert_eq!(
+            image_capacity_bytes(&image, &Layout::default(), HEADER_SIZE),
+            10 + 73 * 3 / 8
+        );
+        assert_eq!(
+            image_capacity_bytes(&create_test_image(1, 1), &Layout::default(), HEADER_SIZE),
+            0
+        );
+        assert_eq!(
+            image_capacity_bytes(&create_test_image(8, 1), &Layout::default(), HEADER_SIZE),
+            3
+        );
+    }
+
+    #[test]
+    fn test_grayscale_capacity_counts_one_channel() {
+        let image = image::GrayImage::new(10, 10);
+
+        // The 80 header bits take one pixel each, leaving 20 single-channel pixels
+        assert_eq!(header_pixels::<image::Luma<u8>>(HEADER_SIZE), 80);
+        assert_eq!(
+            image_capacity_bits(&image, &Layout::default(), HEADER_SIZE),
+            80 + 20
+        );
+        assert!(is_sufficient_capacity(
+            "ab",
+            &image,
+            &Layout::default(),
+            HEADER_SIZE
+        ));
+        assert!(!is_sufficient_capacity(
+            "abc",
+            &image,
+            &Layout::default(),
+            HEADER_SIZE
+        ));
+    }
+
+    #[test]
+    fn test_image_capacity_of_empty_image() {
+        let image = create_test_image(0, 0);
+
+        assert_eq!(
+            image_capacity_bits(&image, &Layout::default(), HEADER_SIZE),
+            0
+        );
+        assert_eq!(
+            image_capacity_bytes(&image, &Layout::default(), HEADER_SIZE),
+            0
+        );
+        assert!(!is_sufficient_capacity(
+            "",
+            &image,
+            &Layout::default(),
+            HEADER_SIZE
+        ));
+    }
+
+    #[test]
+    fn test_image_capacity_of_large_image() {
+        let image = create_test_image(2000, 1500);
+        let capacity = image_capacity_bits(&image, &Layout::default(), HEADER_SIZE);
+
+        assert_eq!(
+            capacity,
+            HEADER_SIZE * BITS_PER_BYTE + (2000 * 1500 - header_pixels::<Rgb<u8>>(HEADER_SIZE)) * 3
+        );
+        assert_eq!(
+            image_capacity_bytes(&image, &Layout::default(), HEADER_SIZE),
+            capacity / BITS_PER_BYTE
+        );
     }
 
     #[test]
@@ -58,6 +304,11 @@ mod tests {
         let image = create_test_image(1, 1);
         let text = "A";
 
-        assert!(!is_sufficient_capacity(text, &image));
+        assert!(!is_sufficient_capacity(
+            text,
+            &image,
+            &Layout::default(),
+            HEADER_SIZE
+        ));
     }
 }
@@ -0,0 +1,141 @@
+use crate::steganography::header::Header;
+use serde_json::{json, Value};
+
+/// Render the raw header bytes followed by one line per parsed field
+pub fn dump_header(raw: &[u8], header: &Header) -> String {
+    let absent = format!("not recorded (format v{})", header.version);
+    let checksum = match header.checksum {
+        Some(checksum) => to_hex(&checksum, ""),
+        None if header.version >= 3 => "none".to_string(),
+        None => absent.clone(),
+    };
+
+    let fields = [
+        ("Raw", to_hex(raw, " ")),
+        ("Magic", String::from_utf8_lossy(&raw[..2]).into_owned()),
+        ("Version", header.version.to_string()),
+        ("Flags", format!("{:#010b}", header.flags)),
+        ("Stride", header.stride.to_string()),
+        ("Length", format!("{} bytes", header.length)),
+        (
+            "Region",
+            match header.region {
+                Some(region) => region.to_string(),
+                None if header.version >= 3 => "whole image".to_string(),
+                None => absent.clone(),
+            },
+        ),
+        ("Checksum", checksum),
+        (
+            "Encrypted",
+            match header.is_encrypted() {
+                Some(true) => "yes".to_string(),
+                Some(false) => "no".to_string(),
+                None => absent.clone(),
+            },
+        ),
+        ("Cipher id", absent.clone()),
+        (
+            "Bit depth",
+            if header.version >= 2 {
+                format!("{} bits per channel", header.depth)
+            } else {
+                absent
+            },
+        ),
+    ];
+
+    fields
+        .iter()
+        .map(|(name, value)| format!("{:<10} {}\n", format!("{}:", name), value))
+        .collect()
+}
+
+/// Describe the header as a JSON object, with `null` for fields its format version lacks
+pub fn header_json(raw: &[u8], header: &Header) -> Value {
+    json!({
+        "operation": "dump-header",
+        "raw": to_hex(raw, " "),
+        "version": header.version,
+        "flags": header.flags,
+        "stride": header.stride,
+        "length": header.length,
+        "depth": (header.version >= 2).then_some(header.depth),
+        "region": header.region.map(|region| json!({
+            "x": region.x,
+            "y": region.y,
+            "width": region.width,
+            "height": region.height,
+        })),
+        "checksum": header.checksum.map(|checksum| to_hex(&checksum, "")),
+        "encrypted": header.is_encrypted(),
+    })
+}
+
+fn to_hex(bytes: &[u8], separator: &str) -> String {
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    hex.join(separator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_header() {
+        let header = Header::new(2, 3, 13);
+        let dump = dump_header(&header.to_bytes(), &header);
+
+        assert!(dump.starts_with("Raw:       4d 42 03 00 02 03 00 00 00 0d\n"));
+        assert!(dump.contains("Magic:     MB\n"));
+        assert!(dump.contains("Flags:     0b00000000\n"));
+        assert!(dump.contains("Stride:    2\n"));
+        assert!(dump.contains("Length:    13 bytes\n"));
+        assert!(dump.contains("Region:    whole image\n"));
+        assert!(dump.contains("Checksum:  none\n"));
+        assert!(dump.contains("Encrypted: no\n"));
+        assert!(dump.contains("Bit depth: 3 bits per channel\n"));
+    }
+
+    #[test]
+    fn test_dump_header_checksum() {
+        let header = Header::new(1, 1, 13).with_checksum([0xab; 32]);
+        let dump = dump_header(&header.to_bytes(), &header);
+        assert!(dump.contains(&format!("Checksum:  {}\n", "ab".repeat(32))));
+        assert!(dump.contains("Flags:     0b00000010\n"));
+
+        let legacy = Header {
+            version: 2,
+            ..Header::new(1, 1, 13)
+        };
+        let dump = dump_header(&legacy.to_bytes(), &legacy);
+        assert!(dump.contains("Checksum:  not recorded (format v2)\n"));
+    }
+
+    #[test]
+    fn test_dump_header_region() {
+        let region = "24,16,8,8".parse().unwrap();
+        let header = Header::new(1, 1, 13).with_region(region);
+
+        let dump = dump_header(&header.to_bytes(), &header);
+        assert!(dump.contains("Region:    8x8 at (24, 16)\n"));
+        assert!(dump.contains("Flags:     0b00001000\n"));
+
+        let value = header_json(&header.to_bytes(), &header);
+        assert_eq!(value["region"]["x"], 24);
+        assert_eq!(value["region"]["height"], 8);
+    }
+
+    #[test]
+    fn test_header_json() {
+        let header = Header::new(2, 3, 13);
+        let value = header_json(&header.to_bytes(), &header);
+
+        assert_eq!(value["raw"], "4d 42 03 00 02 03 00 00 00 0d");
+        assert_eq!(value["stride"], 2);
+        assert_eq!(value["depth"], 3);
+        assert_eq!(value["length"], 13);
+        assert!(value["checksum"].is_null());
+        assert!(value["region"].is_null());
+    }
+}
@@ -0,0 +1,66 @@
+/// Format bytes as a classic offset/hex/ASCII dump with `width` bytes per line
+pub fn hexdump(bytes: &[u8], width: usize) -> String {
+    let width = width.max(1);
+    let hex_width = width.saturating_mul(3) - 1;
+
+    bytes
+        .chunks(width)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let hex = chunk
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let ascii: String = chunk
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+
+            format!(
+                "{:08x}  {:<hex_width$}  |{}|\n",
+                line * width,
+                hex,
+                ascii,
+                hex_width = hex_width
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hexdump_known_payload() {
+        let dump = hexdump(b"Hello, world!", 8);
+
+        assert_eq!(
+            dump,
+            "00000000  48 65 6c 6c 6f 2c 20 77  |Hello, w|\n\
+             00000008  6f 72 6c 64 21           |orld!|\n"
+        );
+    }
+
+    #[test]
+    fn test_hexdump_non_printable() {
+        let dump = hexdump(&[0x00, 0x41, 0x0a, 0xff], 16);
+
+        assert_eq!(
+            dump,
+            format!("00000000  00 41 0a ff{}  |.A..|\n", " ".repeat(36))
+        );
+    }
+
+    #[test]
+    fn test_hexdump_empty() {
+        assert_eq!(hexdump(&[], 16), "");
+    }
+}
@@ -1,2 +1,6 @@
 pub mod ascii;
+pub mod header;
+pub mod hexdump;
+pub mod output;
 pub mod progress;
+pub mod prompt;
@@ -0,0 +1,17 @@
+use clap::ValueEnum;
+use serde_json::Value;
+
+/// How command results are printed to stdout
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable messages
+    #[default]
+    Text,
+    /// A single JSON object per command, for scripts
+    Json,
+}
+
+/// Print a command result as one line of JSON
+pub fn print_json(value: &Value) {
+    println!("{}", value);
+}
@@ -1,13 +1,24 @@
 use crate::core::operations::Progress;
+use crate::core::timings::{Phase, Timings};
+use crate::error::ApplicationError;
 use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::time::Duration;
+use serde_json::json;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
 const PROGRESS_CHARS: &str = "⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏";
 const PROGRESS_INTERVAL: u64 = 80;
 
 pub struct ProgressTracker {
     progress: ProgressBar,
+    file: Option<FileProgress>,
+    /// Print warnings to stderr; they are recorded to the file either way
+    show_warnings: bool,
+    timings: Option<Arc<Timings>>,
 }
 
 impl ProgressTracker {
@@ -20,7 +31,42 @@ impl ProgressTracker {
         );
         progress.enable_steady_tick(Duration::from_millis(PROGRESS_INTERVAL));
 
-        Self { progress }
+        Self {
+            progress,
+            file: None,
+            show_warnings: true,
+            timings: None,
+        }
+    }
+
+    /// Tracker that draws nothing on the terminal, still recording to a file if given one
+    pub fn hidden() -> Self {
+        Self {
+            progress: ProgressBar::hidden(),
+            file: None,
+            show_warnings: true,
+            timings: None,
+        }
+    }
+
+    /// [`ProgressTracker::hidden`] that doesn't print warnings either
+    pub fn quiet() -> Self {
+        Self {
+            show_warnings: false,
+            ..Self::hidden()
+        }
+    }
+
+    /// Also record every phase to `file`, alongside the terminal spinner
+    pub fn with_file(mut self, file: FileProgress) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    /// Also add how long each phase took to `timings`, for the `--timings` report
+    pub fn with_timings(mut self, timings: Arc<Timings>) -> Self {
+        self.timings = Some(timings);
+        self
     }
 }
 
@@ -28,10 +74,112 @@ impl Progress for ProgressTracker {
     fn update(&self, message: &str) {
         let styled_message = message.bright_green().bold().italic().to_string();
         self.progress.set_message(styled_message);
+        if let Some(file) = &self.file {
+            file.update(message);
+        }
+    }
+
+    fn warn(&self, message: &str) {
+        // Print above the spinner rather than replacing its message
+        if self.show_warnings {
+            self.progress
+                .suspend(|| eprintln!("{}", format!("Warning: {}", message).yellow()));
+        }
+        if let Some(file) = &self.file {
+            file.warn(message);
+        }
     }
 
     fn finish_with_message(&self, message: &str) {
         self.progress
             .finish_with_message(message.green().bold().to_string());
+        if let Some(file) = &self.file {
+            file.finish_with_message(message);
+        }
+    }
+
+    fn timing(&self, phase: Phase, elapsed: Duration) {
+        if let Some(timings) = &self.timings {
+            timings.record(phase, elapsed);
+        }
+    }
+}
+
+/// Progress sink writing one JSON record per phase, for monitoring without a terminal
+///
+/// Each line is an object with `phase`, `percent` and an RFC 3339 `timestamp`.
+/// Operations report named phases rather than fractions, so `percent` is `null`
+/// until the final record, which reports `100`
+pub struct FileProgress {
+    file: Mutex<File>,
+}
+
+impl FileProgress {
+    /// Create (or truncate) the file at `path` to receive progress records
+    pub fn create(path: &Path) -> Result<Self, ApplicationError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        Ok(Self {
+            file: Mutex::new(File::create(path)?),
+        })
+    }
+
+    fn record(&self, phase: &str, percent: Option<u8>) {
+        let record = json!({
+            "phase": phase,
+            "percent": percent,
+            "timestamp": humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+        });
+
+        // Progress reporting must never abort the operation itself
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(file, "{}", record).and_then(|_| file.flush());
+        }
+    }
+}
+
+impl Progress for FileProgress {
+    fn update(&self, message: &str) {
+        self.record(message, None);
+    }
+
+    fn warn(&self, message: &str) {
+        self.record(&format!("Warning: {}", message), None);
+    }
+
+    fn finish_with_message(&self, message: &str) {
+        self.record(message, Some(100));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_file_progress_writes_json_lines() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("progress.jsonl");
+
+        let progress = FileProgress::create(&path).unwrap();
+        progress.update("Loading carrier image...");
+        progress.finish_with_message("Done");
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["phase"], "Loading carrier image...");
+        assert!(records[0]["percent"].is_null());
+        assert!(records[0]["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert_eq!(records[1]["phase"], "Done");
+        assert_eq!(records[1]["percent"], 100);
     }
 }
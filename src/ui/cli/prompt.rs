@@ -0,0 +1,132 @@
+use crate::error::ApplicationError;
+use std::io::{self, BufRead};
+
+/// `--key` value that asks for the key on the terminal instead of taking it literally
+pub const PROMPT_SENTINEL: &str = "-";
+
+/// Resolve a `--key` argument, prompting on the terminal without echo when it is `-`
+///
+/// With `from_stdin` (`--key-stdin`) the key is read from stdin instead, so scripts can
+/// pass it without a terminal. With `confirm`, a prompted key must be typed twice,
+/// which guards against typos when encoding
+pub fn resolve_key(
+    key: Option<String>,
+    from_stdin: bool,
+    confirm: bool,
+) -> Result<Option<String>, ApplicationError> {
+    if from_stdin {
+        return read_key_line(io::stdin().lock()).map(Some);
+    }
+
+    match key.as_deref() {
+        Some(PROMPT_SENTINEL) => {
+            read_key(|prompt| rpassword::prompt_password(prompt), confirm).map(Some)
+        }
+        _ => Ok(key),
+    }
+}
+
+/// Read a key from the first line of `reader`, without its line ending
+fn read_key_line(mut reader: impl BufRead) -> Result<String, ApplicationError> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let key = line.trim_end_matches(['\r', '\n']);
+    if key.is_empty() {
+        return Err(ApplicationError::EncryptionError(
+            "Key must not be empty".to_string(),
+        ));
+    }
+
+    Ok(key.to_string())
+}
+
+/// Ask for a key through `read_line`, which shows a prompt and returns the typed line
+fn read_key(
+    mut read_line: impl FnMut(&str) -> io::Result<String>,
+    confirm: bool,
+) -> Result<String, ApplicationError> {
+    let key = read_line("Enter key: ")?;
+    if key.is_empty() {
+        return Err(ApplicationError::EncryptionError(
+            "Key must not be empty".to_string(),
+        ));
+    }
+
+    if confirm && read_line("Confirm key: ")? != key {
+        return Err(ApplicationError::EncryptionError(
+            "Keys do not match".to_string(),
+        ));
+    }
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reader answering each prompt with the next scripted line, recording the prompts shown
+    fn scripted<'a>(
+        answers: &'a [&'a str],
+        prompts: &'a mut Vec<String>,
+    ) -> impl FnMut(&str) -> io::Result<String> + 'a {
+        let mut answers = answers.iter();
+        move |prompt| {
+            prompts.push(prompt.to_string());
+            Ok(answers.next().expect("Unexpected prompt").to_string())
+        }
+    }
+
+    #[test]
+    fn test_literal_key_is_kept() {
+        let key = resolve_key(Some("secret".to_string()), false, true).unwrap();
+        assert_eq!(key, Some("secret".to_string()));
+
+        assert_eq!(resolve_key(None, false, true).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_key_line() {
+        let key = read_key_line("secret key\r\nmessage\n".as_bytes()).unwrap();
+        assert_eq!(key, "secret key");
+
+        assert!(read_key_line("".as_bytes()).is_err());
+        assert!(read_key_line("\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_read_key_single() {
+        let mut prompts = Vec::new();
+        let key = read_key(scripted(&["secret"], &mut prompts), false).unwrap();
+
+        assert_eq!(key, "secret");
+        assert_eq!(prompts, ["Enter key: "]);
+    }
+
+    #[test]
+    fn test_read_key_confirmed() {
+        let mut prompts = Vec::new();
+        let key = read_key(scripted(&["secret", "secret"], &mut prompts), true).unwrap();
+
+        assert_eq!(key, "secret");
+        assert_eq!(prompts, ["Enter key: ", "Confirm key: "]);
+    }
+
+    #[test]
+    fn test_read_key_mismatch() {
+        let mut prompts = Vec::new();
+        let result = read_key(scripted(&["secret", "secert"], &mut prompts), true);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Encryption error: Keys do not match"
+        );
+    }
+
+    #[test]
+    fn test_read_key_empty() {
+        let mut prompts = Vec::new();
+
+        assert!(read_key(scripted(&[""], &mut prompts), false).is_err());
+    }
+}
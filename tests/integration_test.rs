@@ -1,4 +1,5 @@
 use assert_cmd::Command;
+use predicates::prelude::PredicateBooleanExt;
 use std::fs;
 use tempfile::tempdir;
 
@@ -105,7 +106,7 @@ fn test_encode_decode_with_lossy_image() -> Result<(), Box<dyn std::error::Error
         ])
         .assert()
         .success()
-        .stdout(predicates::str::contains(
+        .stderr(predicates::str::contains(
             "Warning: Carrier image is lossy. Converting to lossless format...",
         ));
 
@@ -249,6 +250,112 @@ fn test_encode_overwrites_existing_file() -> Result<(), Box<dyn std::error::Erro
     Ok(())
 }
 
+#[test]
+fn test_no_clobber_refuses_existing_output() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Hello, world!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+    fs::write(&encoded_image_path, "Existing file content")?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--no-clobber",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid path error"))
+        .stderr(predicates::str::contains("already exists"));
+    assert_eq!(fs::read(&encoded_image_path)?, b"Existing file content");
+
+    fs::remove_file(&encoded_image_path)?;
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--no-clobber",
+        ])
+        .assert()
+        .success();
+
+    fs::write(&decoded_text_path, "Existing message")?;
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--no-clobber",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("already exists"));
+    assert_eq!(fs::read_to_string(&decoded_text_path)?, "Existing message");
+
+    Ok(())
+}
+
+#[test]
+fn test_backup_renames_existing_output() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Hello, world!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+    fs::write(&encoded_image_path, "Existing file content")?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--backup",
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read(temp_dir.path().join("encoded.png.bak"))?,
+        b"Existing file content"
+    );
+    assert!(image::open(&encoded_image_path).is_ok());
+
+    fs::write(&decoded_text_path, "Existing message")?;
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--backup",
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("decoded.txt.bak"))?,
+        "Existing message"
+    );
+    assert_eq!(fs::read_to_string(&decoded_text_path)?, "Hello, world!");
+
+    Ok(())
+}
+
 #[test]
 fn test_encode_with_non_image_file() -> Result<(), Box<dyn std::error::Error>> {
     let temp_dir = tempdir()?;
@@ -298,7 +405,7 @@ fn test_encode_with_insufficient_capacity() -> Result<(), Box<dyn std::error::Er
         .assert()
         .failure()
         .stderr(predicates::str::contains(
-            "Encoding error: Image too small to encode data",
+            "Image too small: need 89 bytes but only 0 available",
         ));
 
     Ok(())
@@ -463,3 +570,3179 @@ fn test_compression_decompression_large_data() -> Result<(), Box<dyn std::error:
 
     Ok(())
 }
+
+#[test]
+fn test_decode_hexdump_to_stdout() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Hello, world!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--hexdump",
+            "--width",
+            "8",
+        ])
+        .assert()
+        .success()
+        .stdout(
+            "00000000  48 65 6c 6c 6f 2c 20 77  |Hello, w|\n\
+             00000008  6f 72 6c 64 21           |orld!|\n",
+        );
+
+    assert!(!decoded_text_path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_headerless_with_length() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Hello, world!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--headerless",
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("--headerless --length 13"));
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--headerless",
+            "--length",
+            "13",
+        ])
+        .assert()
+        .success();
+
+    let decoded_text = fs::read_to_string(decoded_text_path)?;
+    assert_eq!(decoded_text, "Hello, world!");
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_with_key_and_compression() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Encrypted and compressed!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "my_secret_key",
+            "--compress",
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--key",
+            "my_secret_key",
+            "--decompress",
+        ])
+        .assert()
+        .success();
+
+    let decoded_text = fs::read_to_string(decoded_text_path)?;
+    assert_eq!(decoded_text, "Encrypted and compressed!");
+
+    Ok(())
+}
+
+#[test]
+fn test_canonicalize_output_reports_absolute_path() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    fs::create_dir(temp_dir.path().join("nested"))?;
+
+    fs::write(&data_path, "Hello, world!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    let output = Command::cargo_bin("mindbender")?
+        .current_dir(temp_dir.path().join("nested"))
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            "../encoded.png",
+            "--canonicalize-output",
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let stdout = String::from_utf8(output)?;
+    let reported = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Output: "))
+        .expect("Output path was not reported");
+    let reported = std::path::Path::new(reported);
+
+    assert!(reported.is_absolute());
+    assert!(reported.exists());
+    assert!(!reported.to_string_lossy().contains(".."));
+    assert_eq!(
+        reported,
+        fs::canonicalize(temp_dir.path().join("encoded.png"))?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_verify_against_matching_file() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Hello, world!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--verify-against",
+            data_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Decoded message matches"));
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_verify_against_differing_file() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let reference_path = temp_dir.path().join("reference.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Hello, world!")?;
+    fs::write(&reference_path, "Hello, world?")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--verify-against",
+            reference_path.to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Verification error: first difference at byte 12",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_verify_against_binary_file() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let reference_path = temp_dir.path().join("reference.bin");
+    let carrier_path = temp_dir.path().join("raw.png");
+
+    // A length-prefixed raw LSB message holding bytes that aren't UTF-8
+    let message = b"\x00\xff\xfe binary \x80";
+    let framed = [&(message.len() as u32).to_be_bytes()[..], message].concat();
+    let bits = framed
+        .iter()
+        .flat_map(|&byte| (0..8).rev().map(move |i| (byte >> i) & 1));
+    let mut carrier = image::load_from_memory(include_bytes!("example/carrier.png"))?.to_rgb8();
+    for (channel, bit) in carrier.iter_mut().zip(bits) {
+        *channel = (*channel & !1) | bit;
+    }
+    carrier.save(&carrier_path)?;
+    fs::write(&reference_path, message)?;
+
+    // The extracted bytes are compared, not text with the invalid bytes replaced
+    for lossy_utf8 in [false, true] {
+        let mut command = Command::cargo_bin("mindbender")?;
+        command.args([
+            "decode",
+            carrier_path.to_str().unwrap(),
+            "--message-format",
+            "raw-lsb",
+            "--verify-against",
+            reference_path.to_str().unwrap(),
+        ]);
+        if lossy_utf8 {
+            command.arg("--lossy-utf8");
+        }
+        command
+            .assert()
+            .success()
+            .stdout(predicates::str::contains("Decoded message matches"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_with_decoy() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let decoy_path = temp_dir.path().join("decoy.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let real_output_path = temp_dir.path().join("real.txt");
+    let decoy_output_path = temp_dir.path().join("decoy_decoded.txt");
+
+    fs::write(&data_path, "The real secret")?;
+    fs::write(&decoy_path, "Grocery list: eggs, milk")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "my_secret_key",
+            "--decoy",
+            decoy_path.to_str().unwrap(),
+            "--duress-key",
+            "duress_key",
+        ])
+        .assert()
+        .success();
+
+    for (key, output_path) in [
+        ("my_secret_key", &real_output_path),
+        ("duress_key", &decoy_output_path),
+    ] {
+        Command::cargo_bin("mindbender")?
+            .args([
+                "decode",
+                encoded_image_path.to_str().unwrap(),
+                "--output-path",
+                output_path.to_str().unwrap(),
+                "--key",
+                key,
+            ])
+            .assert()
+            .success();
+    }
+
+    assert_eq!(fs::read_to_string(&real_output_path)?, "The real secret");
+    assert_eq!(
+        fs::read_to_string(&decoy_output_path)?,
+        "Grocery list: eggs, milk"
+    );
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "wrong_key",
+        ])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn test_log_file_appends_line_after_encode() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let log_path = temp_dir.path().join("activity.log");
+
+    fs::write(&data_path, "Hello, world!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+    fs::write(&log_path, "previous entry\n")?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--log-file",
+            log_path.to_str().unwrap(),
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    let log = fs::read_to_string(&log_path)?;
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], "previous entry");
+    assert!(lines[1].contains("\tencode\t"));
+    assert!(lines[1].contains(&format!("carrier={}", carrier_path.display())));
+    assert!(lines[1].contains("payload=13"));
+    assert!(lines[1].ends_with("\tok"));
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_to_lossy_output_is_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.jpg");
+
+    fs::write(&data_path, "Hello, world!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Output format is lossy and would destroy the hidden data",
+        ));
+
+    assert!(!encoded_image_path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_progress_to_file_records_phases() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let progress_path = temp_dir.path().join("progress.jsonl");
+
+    fs::write(&data_path, "Hello, world!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--progress-to-file",
+            progress_path.to_str().unwrap(),
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    let progress = fs::read_to_string(&progress_path)?;
+    let records: Vec<&str> = progress.lines().collect();
+    assert!(records.len() > 1);
+    assert!(records[0].contains(r#""phase":"Loading carrier image...""#));
+    assert!(records
+        .iter()
+        .all(|record| record.contains(r#""timestamp":"#)));
+    assert!(records.last().unwrap().contains(r#""percent":100"#));
+
+    Ok(())
+}
+
+#[test]
+fn test_dump_header_matches_encoded_fields() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Hello, world!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--stride",
+            "3",
+            "--depth",
+            "2",
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args(["dump-header", encoded_image_path.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "Raw:       4d 42 03 02 03 02 00 00 00 0d",
+        ))
+        .stdout(predicates::str::contains("Magic:     MB"))
+        .stdout(predicates::str::contains("Stride:    3"))
+        .stdout(predicates::str::contains("Bit depth: 2 bits per channel"))
+        .stdout(predicates::str::contains("Length:    13 bytes"))
+        .stdout(predicates::str::contains(
+            "Checksum:  315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3",
+        ));
+
+    Command::cargo_bin("mindbender")?
+        .args(["dump-header", carrier_path.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("No header found"));
+
+    Ok(())
+}
+
+#[test]
+fn test_encrypted_header_has_no_checksum() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Hello, world!")?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "secret",
+        ])
+        .assert()
+        .success();
+
+    // SHA-256 of "Hello, world!", which would let anyone confirm a guessed message
+    Command::cargo_bin("mindbender")?
+        .args(["dump-header", encoded_image_path.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Checksum:  none"))
+        .stdout(
+            predicates::str::contains(
+                "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3",
+            )
+            .not(),
+        );
+
+    Ok(())
+}
+
+#[test]
+fn test_capacity_reports_longest_message() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    let output = Command::cargo_bin("mindbender")?
+        .args([
+            "--size-units",
+            "bytes",
+            "capacity",
+            "tests/example/carrier.png",
+        ])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let bytes = |label: &str| -> usize {
+        let line = stdout.lines().find(|line| line.starts_with(label)).unwrap();
+        line[label.len()..]
+            .split_whitespace()
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+    let (raw, usable, encrypted) = (
+        bytes("Raw capacity:"),
+        bytes("Usable capacity:"),
+        bytes("Encrypted message:"),
+    );
+    assert!(raw > usable && usable > encrypted && encrypted > 0);
+
+    let encode = |length: usize, key: Option<&str>| -> Result<bool, Box<dyn std::error::Error>> {
+        fs::write(&data_path, "x".repeat(length))?;
+        let mut command = Command::cargo_bin("mindbender")?;
+        command.args([
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ]);
+        if let Some(key) = key {
+            command.args(["--key", key]);
+        }
+        Ok(command.output()?.status.success())
+    };
+    assert!(encode(usable, None)?);
+    assert!(!encode(usable + 1, None)?);
+    assert!(encode(encrypted, Some("capacity key"))?);
+    assert!(!encode(encrypted + 1, Some("capacity key"))?);
+
+    Ok(())
+}
+
+#[test]
+fn test_capacity_of_grayscale_carrier_as_json() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let capacity = |carrier: &str| -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        let output = Command::cargo_bin("mindbender")?
+            .args(["--format", "json", "capacity", carrier])
+            .output()?;
+        assert!(output.status.success());
+        Ok(serde_json::from_slice(&output.stdout)?)
+    };
+
+    let gray = capacity("tests/example/carrier_gray.png")?;
+    assert_eq!(gray["operation"], "capacity");
+    let usable = gray["usable_bytes"].as_u64().unwrap() as usize;
+    assert!(usable > 0);
+    assert!(
+        gray["raw_bytes"].as_u64().unwrap() * 3
+            <= capacity("tests/example/carrier.png")?["raw_bytes"]
+                .as_u64()
+                .unwrap()
+    );
+
+    for (length, fits) in [(usable, true), (usable + 1, false)] {
+        fs::write(&data_path, "x".repeat(length))?;
+        let encoded = Command::cargo_bin("mindbender")?
+            .args([
+                "encode",
+                data_path.to_str().unwrap(),
+                "tests/example/carrier_gray.png",
+                "--output-path",
+                encoded_image_path.to_str().unwrap(),
+            ])
+            .output()?;
+        assert_eq!(encoded.status.success(), fits, "{} bytes", length);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_reads_message_from_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_output_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            "-",
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .write_stdin("secret from a pipe\n")
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_output_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&decoded_output_path)?,
+        "secret from a pipe\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_writes_message_to_stdout() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Pipe me somewhere")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .current_dir(temp_dir.path())
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            "-",
+        ])
+        .assert()
+        .success()
+        .stdout("Pipe me somewhere");
+
+    assert!(!temp_dir.path().join("-").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_message_with_null_bytes() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_output_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "before\0after\0")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_output_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(&decoded_output_path)?, b"before\0after\0");
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_with_chacha_cipher() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "ChaCha secret!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "my_secret_key",
+            "--cipher",
+            "chacha",
+        ])
+        .assert()
+        .success();
+
+    // The cipher is detected from the message's tag
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--key",
+            "my_secret_key",
+        ])
+        .assert()
+        .success();
+
+    let decoded_text = fs::read_to_string(&decoded_text_path)?;
+    assert_eq!(decoded_text, "ChaCha secret!");
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--key",
+            "my_secret_key",
+            "--cipher",
+            "aes",
+        ])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_with_high_kdf_cost() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Expensive secret!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "my_secret_key",
+            "--kdf-cost",
+            "high",
+        ])
+        .assert()
+        .success();
+
+    // The cost is recorded with the ciphertext, so decoding needs no flag
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--key",
+            "my_secret_key",
+        ])
+        .assert()
+        .success();
+
+    let decoded_text = fs::read_to_string(&decoded_text_path)?;
+    assert_eq!(decoded_text, "Expensive secret!");
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_with_scatter() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Scattered secret!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "my_secret_key",
+            "--scatter",
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--key",
+            "my_secret_key",
+            "--scatter",
+        ])
+        .assert()
+        .success();
+
+    let decoded_text = fs::read_to_string(&decoded_text_path)?;
+    assert_eq!(decoded_text, "Scattered secret!");
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--key",
+            "my_secret_key",
+        ])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn test_no_command_prints_usage() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("mindbender")?
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Usage:"))
+        .stderr(predicates::str::contains("not available yet"));
+
+    Ok(())
+}
+
+#[test]
+fn test_config_file_enables_compression() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let config_path = temp_dir.path().join("mindbender.toml");
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&config_path, "compress = true\n")?;
+    fs::write(&data_path, "Compressed by configuration")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--config",
+            config_path.to_str().unwrap(),
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    // The payload was compressed even though --compress was not given
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Data is compressed but decompression was not requested",
+        ));
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--config",
+            config_path.to_str().unwrap(),
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--decompress",
+        ])
+        .assert()
+        .success();
+
+    let decoded_text = fs::read_to_string(&decoded_text_path)?;
+    assert_eq!(decoded_text, "Compressed by configuration");
+
+    Ok(())
+}
+
+#[test]
+fn test_no_compress_overrides_config_file() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let config_path = temp_dir.path().join("mindbender.toml");
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&config_path, "compress = true\n")?;
+    fs::write(&data_path, "Stored as is")?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--config",
+            config_path.to_str().unwrap(),
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--no-compress",
+        ])
+        .assert()
+        .success();
+
+    // The config's compress only applies to encoding, so an uncompressed message still decodes
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--config",
+            config_path.to_str().unwrap(),
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    let decoded_text = fs::read_to_string(&decoded_text_path)?;
+    assert_eq!(decoded_text, "Stored as is");
+
+    Ok(())
+}
+
+#[test]
+fn test_malformed_config_file_is_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let config_path = temp_dir.path().join("mindbender.toml");
+    fs::write(&config_path, "compress = maybe\n")?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--config",
+            config_path.to_str().unwrap(),
+            "decode",
+            "missing.png",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Configuration error"));
+
+    Ok(())
+}
+
+#[test]
+fn test_debug_flag_logs_diagnostics() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Hello, world!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    let encode = |debug: Option<&str>| -> Result<String, Box<dyn std::error::Error>> {
+        let mut command = Command::cargo_bin("mindbender")?;
+        command.args(debug);
+        let output = command
+            .args([
+                "encode",
+                data_path.to_str().unwrap(),
+                carrier_path.to_str().unwrap(),
+                "--output-path",
+                encoded_image_path.to_str().unwrap(),
+            ])
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stderr)?)
+    };
+
+    let quiet = encode(None)?;
+    let verbose = encode(Some("-dd"))?;
+
+    assert!(!quiet.contains("INFO") && !quiet.contains("DEBUG"));
+    assert!(verbose.contains("INFO") && verbose.contains("Message is 13 bytes"));
+    assert!(verbose.contains("DEBUG") && verbose.contains("Loading carrier image took"));
+    assert!(!verbose.contains("TRACE"));
+
+    Ok(())
+}
+
+#[test]
+fn test_lossy_carrier_leaves_no_temporary_file() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.jpg");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Message in lossy image!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.jpeg"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    assert!(encoded_image_path.exists());
+    assert!(!temp_dir.path().join("encoded.png.png").exists());
+    assert_eq!(fs::read_dir(temp_dir.path())?.count(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_reports_decodable_message() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Secret message!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "my_secret_key",
+            "--compress",
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .current_dir(temp_dir.path())
+        .args([
+            "verify",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "my_secret_key",
+            "--decompress",
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "contains a decodable message (15 bytes)",
+        ));
+
+    // Nothing is written, not even the default decode output
+    assert!(!temp_dir.path().join("decoded.txt").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_plain_carrier_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let carrier_path = temp_dir.path().join("carrier.png");
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args(["verify", carrier_path.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Decoding error"));
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_with_incorrect_key_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Secret message!")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "correct_key",
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "verify",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "wrong_key",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Decryption error"));
+
+    Ok(())
+}
+
+#[test]
+fn test_keep_metadata_copies_png_text_chunks() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let stripped_path = temp_dir.path().join("stripped.png");
+    let kept_path = temp_dir.path().join("kept.png");
+
+    fs::write(&data_path, "Hello, world!")?;
+    let carrier = image::load_from_memory(include_bytes!("example/carrier.png"))?.to_rgb8();
+    let mut encoder = png::Encoder::new(
+        fs::File::create(&carrier_path)?,
+        carrier.width(),
+        carrier.height(),
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.add_text_chunk("Comment".to_string(), "holiday photo".to_string())?;
+    encoder.write_header()?.write_image_data(carrier.as_raw())?;
+
+    let has_comment = |path: &std::path::Path| -> Result<bool, Box<dyn std::error::Error>> {
+        let bytes = fs::read(path)?;
+        Ok(bytes
+            .windows(21)
+            .any(|window| window == b"Comment\0holiday photo"))
+    };
+    let encode = |output_path: &std::path::Path, keep_metadata: bool| {
+        let mut command = Command::cargo_bin("mindbender").unwrap();
+        command.args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            output_path.to_str().unwrap(),
+        ]);
+        if keep_metadata {
+            command.arg("--keep-metadata");
+        }
+        command.assert().success();
+    };
+
+    encode(&stripped_path, false);
+    encode(&kept_path, true);
+
+    assert!(has_comment(&carrier_path)?);
+    assert!(!has_comment(&stripped_path)?);
+    assert!(has_comment(&kept_path)?);
+
+    Command::cargo_bin("mindbender")?
+        .args(["verify", kept_path.to_str().unwrap()])
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_with_use_alpha() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let output_path = temp_dir.path().join("output.png");
+    let decoded_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Hello, alpha!")?;
+    image::load_from_memory(include_bytes!("example/carrier.png"))?
+        .to_rgba8()
+        .save(&carrier_path)?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            output_path.to_str().unwrap(),
+            "--use-alpha",
+        ])
+        .assert()
+        .success();
+
+    let output = image::open(&output_path)?;
+    assert!(output.color().has_alpha());
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            output_path.to_str().unwrap(),
+            "--output-path",
+            decoded_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(&decoded_path)?, "Hello, alpha!");
+
+    Ok(())
+}
+
+#[test]
+fn test_use_alpha_on_rgb_carrier_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let output_path = temp_dir.path().join("output.png");
+
+    let carrier_path = temp_dir.path().join("carrier.png");
+
+    fs::write(&data_path, "Hello, alpha!")?;
+    image::load_from_memory(include_bytes!("example/carrier.png"))?
+        .to_rgb8()
+        .save(&carrier_path)?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            output_path.to_str().unwrap(),
+            "--use-alpha",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("no alpha channel"));
+
+    Ok(())
+}
+
+#[test]
+fn test_lossless_webp_carrier_is_not_converted() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.webp");
+    let output_path = temp_dir.path().join("output.png");
+    let decoded_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Message in lossless WebP!")?;
+    image::load_from_memory(include_bytes!("example/carrier.png"))?
+        .to_rgb8()
+        .save(&carrier_path)?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            output_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("lossy").not());
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            output_path.to_str().unwrap(),
+            "--output-path",
+            decoded_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&decoded_path)?,
+        "Message in lossless WebP!"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_with_each_compression_algorithm() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Compressed message! ".repeat(8))?;
+
+    for (algorithm, level) in [("zlib", "9"), ("zstd", "19"), ("brotli", "5")] {
+        Command::cargo_bin("mindbender")?
+            .args([
+                "encode",
+                data_path.to_str().unwrap(),
+                "tests/example/carrier.png",
+                "--output-path",
+                encoded_image_path.to_str().unwrap(),
+                "--compression",
+                algorithm,
+                "--compression-level",
+                level,
+            ])
+            .assert()
+            .success();
+
+        Command::cargo_bin("mindbender")?
+            .args([
+                "decode",
+                encoded_image_path.to_str().unwrap(),
+                "--output-path",
+                decoded_text_path.to_str().unwrap(),
+                "--decompress",
+            ])
+            .assert()
+            .success();
+
+        assert_eq!(
+            fs::read_to_string(&decoded_text_path)?,
+            "Compressed message! ".repeat(8),
+            "{}",
+            algorithm
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_with_out_of_range_compression_level_fails() -> Result<(), Box<dyn std::error::Error>>
+{
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Compressed message!")?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--compression-level",
+            "10",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Compression level 10 is out of range for zlib",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_compression_of_tiny_message_is_skipped() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Hello")?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--compress",
+        ])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("storing it uncompressed"));
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            "-",
+            "--decompress",
+        ])
+        .assert()
+        .success()
+        .stdout("Hello");
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_with_key_from_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Message with piped key!")?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key-stdin",
+        ])
+        .write_stdin("piped secret\n")
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            "-",
+            "--key",
+            "piped secret",
+        ])
+        .assert()
+        .success()
+        .stdout("Message with piped key!");
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "verify",
+            encoded_image_path.to_str().unwrap(),
+            "--key-stdin",
+        ])
+        .write_stdin("wrong secret\n")
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn test_key_stdin_conflicts_with_message_from_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            "-",
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key-stdin",
+        ])
+        .write_stdin("secret\nmessage\n")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("cannot both be read from stdin"));
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_with_key_file() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let key_path = temp_dir.path().join("key.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Message with key file!")?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "generate-key",
+            "--length",
+            "32",
+            "--output",
+            key_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    // Editors usually end the file with a newline, which is not part of the key
+    let key = fs::read_to_string(&key_path)?;
+    fs::write(&key_path, format!("{}\n", key))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key-file",
+            key_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            "-",
+            "--key",
+            &key,
+        ])
+        .assert()
+        .success()
+        .stdout("Message with key file!");
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            "-",
+            "--key-file",
+            key_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout("Message with key file!");
+
+    Ok(())
+}
+
+#[test]
+fn test_detect_reports_clean_carrier() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("mindbender")?
+        .args(["detect", "tests/example/carrier.png"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "Probability of hidden data: 0.0%",
+        ))
+        .stdout(predicates::str::contains("No sign of LSB embedding"));
+
+    Command::cargo_bin("mindbender")?
+        .args(["detect", "tests/example/not_an_image.txt"])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn test_json_output_format() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Message for scripts! ".repeat(8))?;
+
+    let output = Command::cargo_bin("mindbender")?
+        .args([
+            "--format",
+            "json",
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.jpeg",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--compress",
+        ])
+        .output()?;
+    assert!(output.status.success());
+    let encoded: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(encoded["operation"], "encode");
+    assert_eq!(encoded["output"], encoded_image_path.to_str().unwrap());
+    assert_eq!(encoded["compressed"], true);
+    assert!(encoded["bytes"].as_u64().unwrap() < 168);
+
+    let output = Command::cargo_bin("mindbender")?
+        .args([
+            "--format",
+            "json",
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            "-",
+            "--decompress",
+        ])
+        .output()?;
+    assert!(output.status.success());
+    let decoded: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(decoded["operation"], "decode");
+    assert_eq!(decoded["bytes"], 168);
+    assert_eq!(decoded["message"], "Message for scripts! ".repeat(8));
+
+    let output = Command::cargo_bin("mindbender")?
+        .args([
+            "--format",
+            "json",
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--decompress",
+            "--key",
+            "wrong",
+        ])
+        .output()?;
+    assert!(!output.status.success());
+    let error: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert!(error["error"]
+        .as_str()
+        .unwrap()
+        .contains("Decryption error"));
+
+    Ok(())
+}
+
+#[test]
+fn test_batch_encode_skips_too_small_carrier() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("watermark.txt");
+    let carrier_dir = temp_dir.path().join("carriers");
+    let output_dir = temp_dir.path().join("encoded");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "© Photographer")?;
+    fs::create_dir(&carrier_dir)?;
+    fs::write(
+        carrier_dir.join("photo.png"),
+        include_bytes!("example/carrier.png"),
+    )?;
+    fs::write(
+        carrier_dir.join("thumbnail.png"),
+        include_bytes!("example/carrier_small.png"),
+    )?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "batch-encode",
+            data_path.to_str().unwrap(),
+            carrier_dir.to_str().unwrap(),
+            output_dir.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "Encoded 1 of 2 carriers (1 skipped, 0 failed)",
+        ))
+        .stderr(
+            predicates::str::contains("Skipping").and(predicates::str::contains("thumbnail.png")),
+        );
+
+    assert!(!output_dir.join("thumbnail-encoded.png").exists());
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            output_dir.join("photo-encoded.png").to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(decoded_text_path)?, "© Photographer");
+
+    Ok(())
+}
+
+#[test]
+fn test_batch_encode_carriers_sharing_a_stem() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("watermark.txt");
+    let carrier_dir = temp_dir.path().join("carriers");
+    let output_dir = temp_dir.path().join("encoded");
+
+    fs::write(&data_path, "© Photographer")?;
+    fs::create_dir(&carrier_dir)?;
+    let carrier = image::load_from_memory(include_bytes!("example/carrier.png"))?;
+    carrier.save(carrier_dir.join("photo.png"))?;
+    carrier.to_rgb8().save(carrier_dir.join("photo.bmp"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "batch-encode",
+            data_path.to_str().unwrap(),
+            carrier_dir.to_str().unwrap(),
+            output_dir.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Encoded 2 of 2 carriers"));
+
+    for name in ["photo-encoded.png", "photo-1-encoded.png"] {
+        let decoded_text_path = temp_dir.path().join(format!("{}.txt", name));
+        Command::cargo_bin("mindbender")?
+            .args([
+                "decode",
+                output_dir.join(name).to_str().unwrap(),
+                "--output-path",
+                decoded_text_path.to_str().unwrap(),
+            ])
+            .assert()
+            .success();
+
+        assert_eq!(fs::read_to_string(decoded_text_path)?, "© Photographer");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_split_and_decode_join() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let output_path = temp_dir.path().join("shard.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    // 10 KB does not fit in any one of the carriers
+    let message: String = (0..10_240)
+        .map(|i| (b'a' + (i % 26) as u8) as char)
+        .collect();
+    fs::write(&data_path, &message)?;
+    let carrier_paths: Vec<_> = (1..=3)
+        .map(|i| temp_dir.path().join(format!("carrier{}.png", i)))
+        .collect();
+    for path in &carrier_paths {
+        image::RgbImage::from_fn(100, 100, |x, y| image::Rgb([x as u8, y as u8, 128]))
+            .save(path)?;
+    }
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_paths[0].to_str().unwrap(),
+            "--output-path",
+            output_path.to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Image too small"));
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_paths[0].to_str().unwrap(),
+            "--split",
+            carrier_paths[1].to_str().unwrap(),
+            carrier_paths[2].to_str().unwrap(),
+            "--output-path",
+            output_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    let shard = |n: usize| temp_dir.path().join(format!("shard-{}.png", n));
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            shard(2).to_str().unwrap(),
+            "--join",
+            shard(3).to_str().unwrap(),
+            shard(1).to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&decoded_text_path)?, message);
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            shard(1).to_str().unwrap(),
+            "--join",
+            shard(3).to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Missing shard(s) 2 of 3"));
+
+    Command::cargo_bin("mindbender")?
+        .args(["decode", shard(1).to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("--join"));
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_lossy_utf8_replaces_truncated_character() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "caf\u{e9}")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.png"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--headerless",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    // Four bytes stop halfway through the two-byte 'é'
+    let decode = |lossy: bool| -> Result<assert_cmd::assert::Assert, Box<dyn std::error::Error>> {
+        let mut command = Command::cargo_bin("mindbender")?;
+        command.args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--headerless",
+            "--length",
+            "4",
+            "--output-path",
+            "-",
+        ]);
+        if lossy {
+            command.arg("--lossy-utf8");
+        }
+        Ok(command.assert())
+    };
+
+    decode(false)?
+        .failure()
+        .stderr(predicates::str::contains("Invalid UTF-8"));
+    decode(true)?
+        .success()
+        .stdout("caf\u{fffd}")
+        .stderr(predicates::str::contains("Replaced 1 byte(s)"));
+
+    Ok(())
+}
+
+#[test]
+fn test_bind_carrier_rejects_resized_image() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let output_path = temp_dir.path().join("encoded.png");
+    let cropped_path = temp_dir.path().join("cropped.png");
+
+    fs::write(&data_path, "Only for this carrier")?;
+    image::RgbImage::from_fn(60, 60, |x, y| image::Rgb([x as u8, y as u8, 128]))
+        .save(&carrier_path)?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            output_path.to_str().unwrap(),
+            "--key",
+            "secret",
+            "--bind-carrier",
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            output_path.to_str().unwrap(),
+            "--output-path",
+            "-",
+            "--key",
+            "secret",
+            "--bind-carrier",
+        ])
+        .assert()
+        .success()
+        .stdout("Only for this carrier");
+
+    // Dropping the last row keeps every embedded bit but changes the dimensions
+    image::open(&output_path)?
+        .crop_imm(0, 0, 60, 59)
+        .save(&cropped_path)?;
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            cropped_path.to_str().unwrap(),
+            "--output-path",
+            "-",
+            "--key",
+            "secret",
+            "--bind-carrier",
+        ])
+        .assert()
+        .failure();
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            output_path.to_str().unwrap(),
+            "--output-path",
+            "-",
+            "--key",
+            "secret",
+        ])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn test_fill_randomizes_every_unused_bit() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Short message")?;
+    // Every least significant bit of the carrier starts out as 0
+    image::RgbImage::from_pixel(100, 100, image::Rgb([128, 64, 32])).save(&carrier_path)?;
+
+    let ones_ratio = |fill: bool| -> Result<f64, Box<dyn std::error::Error>> {
+        let output_path = temp_dir.path().join(format!("encoded-{}.png", fill));
+        let mut args = vec![
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            output_path.to_str().unwrap(),
+        ];
+        if fill {
+            args.push("--fill");
+        }
+        Command::cargo_bin("mindbender")?
+            .args(&args)
+            .assert()
+            .success();
+
+        Command::cargo_bin("mindbender")?
+            .args([
+                "decode",
+                output_path.to_str().unwrap(),
+                "--output-path",
+                decoded_text_path.to_str().unwrap(),
+            ])
+            .assert()
+            .success();
+        assert_eq!(fs::read_to_string(&decoded_text_path)?, "Short message");
+
+        let samples = image::open(&output_path)?.to_rgb8().into_raw();
+        let ones = samples.iter().filter(|sample| *sample & 1 == 1).count();
+        Ok(ones as f64 / samples.len() as f64)
+    };
+
+    assert!(ones_ratio(false)? < 0.05);
+    let filled = ones_ratio(true)?;
+    assert!((0.45..0.55).contains(&filled), "{}", filled);
+
+    Ok(())
+}
+
+#[test]
+fn test_inspect_reports_embedded_message() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let output_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Inspect me")?;
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            output_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args(["inspect", "tests/example/carrier.png"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Dimensions: 40x40"))
+        .stdout(predicates::str::contains("Png (lossless)"))
+        .stdout(predicates::str::contains("No embedded message found"));
+
+    Command::cargo_bin("mindbender")?
+        .args(["--format", "json", "inspect", output_path.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("\"has_payload\":true"));
+
+    Ok(())
+}
+
+#[test]
+fn test_grayscale_carrier_stays_grayscale() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let output_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Shades of gray")?;
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier_gray.png",
+            "--output-path",
+            output_path.to_str().unwrap(),
+            "--key",
+            "secret",
+        ])
+        .assert()
+        .success();
+
+    assert_eq!(image::open(&output_path)?.color(), image::ColorType::L8);
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            output_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--key",
+            "secret",
+        ])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&decoded_text_path)?, "Shades of gray");
+
+    Ok(())
+}
+
+#[test]
+fn test_dry_run_writes_nothing() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Nothing to see here")?;
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--dry-run",
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "secret",
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Dry run: would write"));
+    assert!(!encoded_image_path.exists());
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--dry-run",
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Dry run: would write 19 B"));
+    assert!(!decoded_text_path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_dry_run_still_reports_failures() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Far too long for a single pixel")?;
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--dry-run",
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier_small.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Image too small"));
+    assert!(!encoded_image_path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_detects_flipped_message_byte() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.png");
+    let output_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "a".repeat(32))?;
+    image::RgbImage::from_fn(40, 40, |x, y| image::Rgb([x as u8, y as u8, 128]))
+        .save(&carrier_path)?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            output_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    // The header with its checksum fills the first 112 pixels; the eighth channel after
+    // them holds the low bit of the first message byte, turning `a` into a valid `` ` ``
+    let mut image = image::open(&output_path)?.to_rgb8();
+    let samples = image.as_flat_samples_mut().samples;
+    samples[112 * 3 + 7] ^= 1;
+    image.save(&output_path)?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            output_path.to_str().unwrap(),
+            "--output-path",
+            "-",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("checksum mismatch"));
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_animated_gif_keeps_frames() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.gif");
+    let output_path = temp_dir.path().join("encoded.gif");
+
+    fs::write(&data_path, "Hidden in the frames")?;
+    let palette: Vec<u8> = (0..=255u8).flat_map(|i| [i, i / 2, 255 - i]).collect();
+    let mut encoder = gif::Encoder::new(fs::File::create(&carrier_path)?, 40, 40, &palette)?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    for n in 0..3u8 {
+        let buffer: Vec<u8> = (0..1600u32)
+            .map(|i| (i as u8).wrapping_add(n * 40))
+            .collect();
+        let mut frame = gif::Frame::from_indexed_pixels(40, 40, buffer, None);
+        frame.delay = 20;
+        encoder.write_frame(&frame)?;
+    }
+    drop(encoder);
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            output_path.to_str().unwrap(),
+            "--key",
+            "secret",
+        ])
+        .assert()
+        .success();
+
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(fs::File::open(&output_path)?)?;
+    let mut frames = 0;
+    while let Some(frame) = decoder.read_next_frame()? {
+        assert_eq!((frame.width, frame.height, frame.delay), (40, 40, 20));
+        frames += 1;
+    }
+    assert_eq!(frames, 3);
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            output_path.to_str().unwrap(),
+            "--output-path",
+            "-",
+            "--key",
+            "secret",
+        ])
+        .assert()
+        .success()
+        .stdout("Hidden in the frames");
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_16_bit_png_keeps_depth() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier16.png");
+    let output_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Scanned at full depth")?;
+    let carrier = image::ImageBuffer::from_fn(64, 64, |x, y| {
+        image::Rgb([(x * 1000) as u16, (y * 1000) as u16, ((x + y) * 500) as u16])
+    });
+    carrier.save(&carrier_path)?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            output_path.to_str().unwrap(),
+            "--key",
+            "secret",
+        ])
+        .assert()
+        .success();
+
+    let encoded = image::open(&output_path)?;
+    assert_eq!(encoded.color(), image::ColorType::Rgb16);
+    let encoded = encoded.into_rgb16();
+    for (before, after) in carrier.as_raw().iter().zip(encoded.as_raw()) {
+        assert!(before.abs_diff(*after) <= 1);
+    }
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            output_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--key",
+            "secret",
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&decoded_text_path)?,
+        "Scanned at full depth"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_require_strong_key_rejects_weak_key() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let output_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Guarded by a good key")?;
+    let encode = |key: &str| -> Result<assert_cmd::assert::Assert, Box<dyn std::error::Error>> {
+        Ok(Command::cargo_bin("mindbender")?
+            .args([
+                "encode",
+                data_path.to_str().unwrap(),
+                "tests/example/carrier.png",
+                "--output-path",
+                output_path.to_str().unwrap(),
+                "--key",
+                key,
+                "--require-strong-key",
+            ])
+            .assert())
+    };
+
+    encode("abc")?
+        .failure()
+        .stderr(predicates::str::contains("Key is weak"));
+    assert!(!output_path.exists());
+
+    encode("sunset-harbor-42")?
+        .success()
+        .stdout(predicates::str::contains("Key strength: strong"));
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_reports_whether_message_is_encrypted() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encrypted_path = temp_dir.path().join("encrypted.png");
+    let plain_path = temp_dir.path().join("plain.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Locked or not")?;
+    for (output_path, key) in [(&encrypted_path, Some("secret")), (&plain_path, None)] {
+        let mut command = Command::cargo_bin("mindbender")?;
+        command.args([
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            output_path.to_str().unwrap(),
+        ]);
+        if let Some(key) = key {
+            command.args(["--key", key]);
+        }
+        command.assert().success();
+    }
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encrypted_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "this message is encrypted; provide --key",
+        ));
+    assert!(!decoded_text_path.exists());
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            plain_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--key",
+            "secret",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "this message is not encrypted; decode without --key",
+        ));
+    assert!(!decoded_text_path.exists());
+
+    Command::cargo_bin("mindbender")?
+        .args(["dump-header", encrypted_path.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Encrypted: yes"));
+
+    Ok(())
+}
+
+#[test]
+fn test_output_format_overrides_extension() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let output_path = temp_dir.path().join("encoded.png");
+    let bmp_path = temp_dir.path().join("encoded.bmp");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Stored as a bitmap")?;
+    let encode = |format: &str| -> Result<assert_cmd::assert::Assert, Box<dyn std::error::Error>> {
+        Ok(Command::cargo_bin("mindbender")?
+            .args([
+                "encode",
+                data_path.to_str().unwrap(),
+                "tests/example/carrier.png",
+                "--output-path",
+                output_path.to_str().unwrap(),
+                "--output-format",
+                format,
+            ])
+            .assert())
+    };
+
+    encode("jpeg")?.failure().stderr(predicates::str::contains(
+        "Output format jpeg is lossy and would destroy the hidden data",
+    ));
+
+    encode("bmp")?.success();
+    assert!(!output_path.exists());
+    assert_eq!(
+        image::ImageReader::open(&bmp_path)?
+            .with_guessed_format()?
+            .format(),
+        Some(image::ImageFormat::Bmp)
+    );
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            bmp_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&decoded_text_path)?,
+        "Stored as a bitmap"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_raw_lsb_image() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let carrier_path = temp_dir.path().join("raw.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    // Another tool's layout: a 32-bit big-endian length, then the message, one bit per
+    // R, G and B channel in raster order, most significant bit first
+    let message = b"From another tool";
+    let framed = [&(message.len() as u32).to_be_bytes()[..], message].concat();
+    let bits = framed
+        .iter()
+        .flat_map(|&byte| (0..8).rev().map(move |i| (byte >> i) & 1));
+    let mut carrier = image::load_from_memory(include_bytes!("example/carrier.png"))?.to_rgb8();
+    for (channel, bit) in carrier.iter_mut().zip(bits) {
+        *channel = (*channel & !1) | bit;
+    }
+    carrier.save(&carrier_path)?;
+
+    // The framing flag sits alongside the global output --format without clashing
+    let output = Command::cargo_bin("mindbender")?
+        .args([
+            "--format",
+            "json",
+            "decode",
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--message-format",
+            "raw-lsb",
+        ])
+        .output()?;
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(json["operation"], "decode");
+    assert_eq!(fs::read(&decoded_text_path)?, message);
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            carrier_path.to_str().unwrap(),
+            "--message-format",
+            "raw-lsb",
+            "--key",
+            "my_secret_key",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("cannot be decrypted"));
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_raw_lsb_binary_message() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let carrier_path = temp_dir.path().join("raw.png");
+    let decoded_path = temp_dir.path().join("decoded.bin");
+
+    // The same length-prefixed layout as above, holding bytes that aren't UTF-8
+    let message = b"\x89PNG\r\n\x1a\n\xff\xfe";
+    let framed = [&(message.len() as u32).to_be_bytes()[..], message].concat();
+    let bits = framed
+        .iter()
+        .flat_map(|&byte| (0..8).rev().map(move |i| (byte >> i) & 1));
+    let mut carrier = image::load_from_memory(include_bytes!("example/carrier.png"))?.to_rgb8();
+    for (channel, bit) in carrier.iter_mut().zip(bits) {
+        *channel = (*channel & !1) | bit;
+    }
+    carrier.save(&carrier_path)?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            decoded_path.to_str().unwrap(),
+            "--message-format",
+            "raw-lsb",
+        ])
+        .assert()
+        .success();
+    assert_eq!(fs::read(&decoded_path)?, message);
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            "-",
+            "--message-format",
+            "raw-lsb",
+        ])
+        .assert()
+        .success()
+        .stdout(&message[..]);
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_bundled_files() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let notes_dir = temp_dir.path().join("notes");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let unpacked_dir = temp_dir.path().join("unpacked");
+
+    fs::create_dir(&notes_dir)?;
+    fs::write(notes_dir.join("monday.txt"), "Buy milk")?;
+    fs::write(notes_dir.join("tuesday.txt"), "Call the bank\nThen lunch")?;
+    fs::write(notes_dir.join("ideas.md"), "Not a .txt file")?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            notes_dir.join("*.txt").to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "secret",
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            unpacked_dir.to_str().unwrap(),
+            "--key",
+            "secret",
+        ])
+        .assert()
+        .success();
+
+    for name in ["monday.txt", "tuesday.txt"] {
+        assert_eq!(
+            fs::read(unpacked_dir.join(name))?,
+            fs::read(notes_dir.join(name))?
+        );
+    }
+    assert!(!unpacked_dir.join("ideas.md").exists());
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            notes_dir.join("*.pdf").to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("No files match"));
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_decode_in_region() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Tucked in the corner")?;
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--region",
+            "20,20,20,20",
+        ])
+        .assert()
+        .success();
+
+    let original = image::open("tests/example/carrier.png")?.to_rgba8();
+    let encoded = image::open(&encoded_image_path)?.to_rgba8();
+    for (x, y, pixel) in encoded.enumerate_pixels() {
+        if x < 20 || y < 20 {
+            assert_eq!(pixel, original.get_pixel(x, y));
+        }
+    }
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "dump-header",
+            encoded_image_path.to_str().unwrap(),
+            "--region",
+            "20,20,20,20",
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Region:    20x20 at (20, 20)"));
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--region",
+            "20,20,20,20",
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&decoded_text_path)?,
+        "Tucked in the corner"
+    );
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--region",
+            "30,30,20,20",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("does not fit"));
+
+    Ok(())
+}
+
+#[test]
+fn test_diff_map_is_confined_to_region() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let diff_map_path = temp_dir.path().join("changes.png");
+
+    fs::write(&data_path, "Only the corner changes")?;
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--region",
+            "20,20,20,20",
+            "--diff-map",
+            diff_map_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    let map = image::open(&diff_map_path)?.to_rgb8();
+    let changed: Vec<(u32, u32)> = map
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel.0 == [255, 0, 0])
+        .map(|(x, y, _)| (x, y))
+        .collect();
+    assert!(!changed.is_empty());
+    assert!(changed
+        .iter()
+        .all(|&(x, y)| (20..40).contains(&x) && (20..40).contains(&y)));
+
+    Ok(())
+}
+
+#[test]
+fn test_quiet_suppresses_warnings_and_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let carrier_path = temp_dir.path().join("carrier.jpg");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    fs::write(&data_path, "Nothing to see here")?;
+    fs::write(&carrier_path, include_bytes!("example/carrier.jpeg"))?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--quiet",
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "short",
+            "--raw-key",
+        ])
+        .assert()
+        .success()
+        .stdout("")
+        .stderr("");
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "-q",
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            "-",
+            "--key",
+            "short",
+            "--raw-key",
+        ])
+        .assert()
+        .success()
+        .stdout("Nothing to see here")
+        .stderr("");
+
+    // A dry run's "would write" line is part of the summary too
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--quiet",
+            "--dry-run",
+            "encode",
+            data_path.to_str().unwrap(),
+            carrier_path.to_str().unwrap(),
+            "--output-path",
+            temp_dir.path().join("dry.png").to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout("")
+        .stderr("");
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--quiet",
+            "--dry-run",
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            temp_dir.path().join("dry.txt").to_str().unwrap(),
+            "--key",
+            "short",
+            "--raw-key",
+        ])
+        .assert()
+        .success()
+        .stdout("")
+        .stderr("");
+
+    let carrier_dir = temp_dir.path().join("carriers");
+    fs::create_dir(&carrier_dir)?;
+    fs::copy(&carrier_path, carrier_dir.join("carrier.jpg"))?;
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--quiet",
+            "--dry-run",
+            "batch-encode",
+            data_path.to_str().unwrap(),
+            carrier_dir.to_str().unwrap(),
+            temp_dir.path().join("batch").to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout("")
+        .stderr("");
+
+    Ok(())
+}
+
+#[test]
+fn test_generate_key_encodings() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let key_path = temp_dir.path().join("key.bin");
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "generate-key",
+            "--length",
+            "20",
+            "--encoding",
+            "raw",
+            "--output",
+            key_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(fs::read(&key_path)?.len(), 20);
+
+    let output = Command::cargo_bin("mindbender")?
+        .args(["generate-key", "--length", "20", "--encoding", "hex"])
+        .output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    let hex = stdout.trim().strip_prefix("Generated key: ").unwrap();
+    assert_eq!(hex.len(), 40);
+    assert!(hex.chars().all(|c| c.is_ascii_hexdigit()));
+
+    Command::cargo_bin("mindbender")?
+        .args(["--format", "json", "generate-key", "--encoding", "raw"])
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains("A raw key is binary"));
+
+    Ok(())
+}
+
+#[test]
+fn test_raw_key_file_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let key_path = temp_dir.path().join("key.bin");
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Locked with random bytes")?;
+    Command::cargo_bin("mindbender")?
+        .args([
+            "generate-key",
+            "--encoding",
+            "raw",
+            "--output",
+            key_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    // As a passphrase, and as the cipher key itself
+    for raw_key in [&[][..], &["--raw-key"][..]] {
+        Command::cargo_bin("mindbender")?
+            .args([
+                "encode",
+                data_path.to_str().unwrap(),
+                "tests/example/carrier.png",
+                "--output-path",
+                encoded_image_path.to_str().unwrap(),
+                "--key-file",
+                key_path.to_str().unwrap(),
+            ])
+            .args(raw_key)
+            .assert()
+            .success();
+
+        Command::cargo_bin("mindbender")?
+            .args([
+                "decode",
+                encoded_image_path.to_str().unwrap(),
+                "--output-path",
+                decoded_text_path.to_str().unwrap(),
+                "--key-file",
+                key_path.to_str().unwrap(),
+            ])
+            .args(raw_key)
+            .assert()
+            .success();
+        assert_eq!(
+            fs::read_to_string(&decoded_text_path)?,
+            "Locked with random bytes"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_streaming_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.tiff");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Read a strip at a time")?;
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "strip key",
+            "--streaming",
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--key",
+            "strip key",
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&decoded_text_path)?,
+        "Read a strip at a time"
+    );
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.jpeg",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--streaming",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Only PNG and TIFF carriers can be streamed",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_timings_report_lists_phases() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let data_path = temp_dir.path().join("data.txt");
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    fs::write(&data_path, "Where does the time go?")?;
+    let encoded = Command::cargo_bin("mindbender")?
+        .args([
+            "--timings",
+            "encode",
+            data_path.to_str().unwrap(),
+            "tests/example/carrier.png",
+            "--output-path",
+            encoded_image_path.to_str().unwrap(),
+            "--key",
+            "timing key",
+            "--compress",
+        ])
+        .assert()
+        .success();
+    let stderr = String::from_utf8(encoded.get_output().stderr.clone())?;
+    assert!(stderr.contains("Timings:"));
+    for phase in ["load", "encrypt", "compress", "embed", "save"] {
+        assert!(
+            stderr.lines().any(
+                |line| line.trim_start().starts_with(phase) && line.trim_end().ends_with(" ms")
+            ),
+            "no {} timing in {:?}",
+            phase,
+            stderr
+        );
+    }
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "--timings",
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--key",
+            "timing key",
+            "--decompress",
+        ])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("extract").and(predicates::str::contains("decrypt")));
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+            "--key",
+            "timing key",
+            "--decompress",
+        ])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("Timings:").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_baseline_image_starting_with_magic() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let decoded_text_path = temp_dir.path().join("decoded.txt");
+
+    // The format of releases before the header: one bit per channel, most significant bit
+    // first, ending with a null byte
+    let baseline = |message: &[u8], path: &std::path::Path| -> image::ImageResult<()> {
+        let mut carrier = image::RgbImage::from_pixel(32, 32, image::Rgb([128, 64, 200]));
+        let bits = message
+            .iter()
+            .chain(&[0])
+            .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1));
+        for (channel, bit) in carrier.iter_mut().zip(bits) {
+            *channel = (*channel & !1) | bit;
+        }
+        carrier.save(path)
+    };
+    let decode = |path: &std::path::Path, legacy_delimiter: bool| {
+        let mut command = Command::cargo_bin("mindbender").unwrap();
+        command.args([
+            "decode",
+            path.to_str().unwrap(),
+            "--output-path",
+            decoded_text_path.to_str().unwrap(),
+        ]);
+        if legacy_delimiter {
+            command.arg("--legacy-delimiter");
+        }
+        command.assert()
+    };
+
+    let text_path = temp_dir.path().join("text.png");
+    baseline(b"MBA meeting moved to noon", &text_path)?;
+    decode(&text_path, false).success();
+    assert_eq!(
+        fs::read_to_string(&decoded_text_path)?,
+        "MBA meeting moved to noon"
+    );
+
+    // Control characters keep it from reading as legacy text, so only the flag recovers it
+    let control_path = temp_dir.path().join("control.png");
+    baseline(b"MB\x05\x01 status", &control_path)?;
+    decode(&control_path, false).failure();
+    decode(&control_path, true).success();
+    assert_eq!(fs::read(&decoded_text_path)?, b"MB\x05\x01 status");
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_hexdump_non_utf8_payload() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let encoded_image_path = temp_dir.path().join("encoded.png");
+
+    // A legacy image, one bit per channel ending with a null byte, holding bytes that
+    // aren't UTF-8
+    let mut carrier = image::RgbImage::from_pixel(32, 32, image::Rgb([128, 64, 200]));
+    let bits = b"\xff\xfe\x80 bin\x00"
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1));
+    for (channel, bit) in carrier.iter_mut().zip(bits) {
+        *channel = (*channel & !1) | bit;
+    }
+    carrier.save(&encoded_image_path)?;
+
+    Command::cargo_bin("mindbender")?
+        .args([
+            "decode",
+            encoded_image_path.to_str().unwrap(),
+            "--legacy-delimiter",
+            "--hexdump",
+            "--width",
+            "8",
+        ])
+        .assert()
+        .success()
+        .stdout("00000000  ff fe 80 20 62 69 6e     |... bin|\n");
+
+    Ok(())
+}
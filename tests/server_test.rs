@@ -0,0 +1,100 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+const OP_ENCODE: u8 = 0x01;
+const OP_DECODE: u8 = 0x02;
+const FLAG_COMPRESS: u8 = 0x01;
+const STATUS_OK: u8 = 0x00;
+
+struct Server {
+    child: Child,
+    _stdout: BufReader<ChildStdout>,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn start_server() -> Result<(Server, String), Box<dyn std::error::Error>> {
+    let mut child = Command::new(assert_cmd::cargo::cargo_bin("mindbender"))
+        .args(["serve", "--listen", "127.0.0.1:0"])
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line)?;
+    let address = line
+        .trim()
+        .strip_prefix("Listening on ")
+        .ok_or("Unexpected server banner")?
+        .to_string();
+
+    Ok((
+        Server {
+            child,
+            _stdout: stdout,
+        },
+        address,
+    ))
+}
+
+fn write_frame(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    buffer.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    buffer.extend_from_slice(bytes);
+}
+
+fn send(address: &str, request: &[u8]) -> Result<(u8, Vec<u8>), Box<dyn std::error::Error>> {
+    let mut stream = TcpStream::connect(address)?;
+    stream.write_all(request)?;
+
+    let mut status = [0u8; 1];
+    stream.read_exact(&mut status)?;
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length)?;
+    let mut body = vec![0u8; u32::from_be_bytes(length) as usize];
+    stream.read_exact(&mut body)?;
+
+    Ok((status[0], body))
+}
+
+#[test]
+fn test_serve_encode_decode_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let (_server, address) = start_server()?;
+    let carrier = include_bytes!("example/carrier.png");
+
+    let mut request = vec![OP_ENCODE, FLAG_COMPRESS];
+    write_frame(&mut request, b"my_secret_key");
+    write_frame(&mut request, carrier);
+    write_frame(&mut request, b"Hello over TCP!");
+    let (status, encoded) = send(&address, &request)?;
+    assert_eq!(status, STATUS_OK, "{}", String::from_utf8_lossy(&encoded));
+
+    let mut request = vec![OP_DECODE, FLAG_COMPRESS];
+    write_frame(&mut request, b"my_secret_key");
+    write_frame(&mut request, &encoded);
+    let (status, decoded) = send(&address, &request)?;
+    assert_eq!(status, STATUS_OK, "{}", String::from_utf8_lossy(&decoded));
+    assert_eq!(decoded, b"Hello over TCP!");
+
+    Ok(())
+}
+
+#[test]
+fn test_serve_reports_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let (_server, address) = start_server()?;
+
+    let mut request = vec![OP_DECODE, 0];
+    write_frame(&mut request, b"");
+    write_frame(&mut request, b"not an image");
+    let (status, body) = send(&address, &request)?;
+
+    assert_ne!(status, STATUS_OK);
+    assert!(String::from_utf8(body)?.contains("Image error"));
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+//! Peak memory of `encode --streaming` against the in-memory encode, on a carrier
+//! generated at test time
+//!
+//! This file holds a single test, so that nothing else allocates while it measures.
+
+use mindbender::core::operations::{
+    decode_message, encode, DecodeOptions, EncodeOptions, SilentProgress,
+};
+use mindbender::core::streaming::{write_strips, Samples};
+use std::alloc::{GlobalAlloc, Layout as AllocLayout, System};
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// System allocator that records the most memory ever held at once
+struct PeakAllocator;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAllocator {
+    unsafe fn alloc(&self, layout: AllocLayout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: AllocLayout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAllocator = PeakAllocator;
+
+/// Bytes allocated on top of what was already held, at the peak of running `f`
+fn peak_allocation<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let baseline = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    let result = f();
+    (result, PEAK.load(Ordering::Relaxed) - baseline)
+}
+
+#[test]
+fn test_streaming_encode_lowers_peak_memory() {
+    const WIDTH: u32 = 3000;
+    const HEIGHT: u32 = 2000;
+    let carrier_bytes = WIDTH as usize * HEIGHT as usize * 3;
+
+    let dir = tempfile::tempdir().unwrap();
+    let carrier = dir.path().join("carrier.tiff");
+    let data = dir.path().join("message.txt");
+    let in_memory_output = dir.path().join("in-memory.tiff");
+    let streamed_output = dir.path().join("streamed.tiff");
+    let path = |path: &std::path::Path| path.to_str().unwrap().to_string();
+
+    // Written a row at a time, so the large carrier is never held in memory here either
+    let rows = (0..HEIGHT).map(|y| {
+        Ok((0..WIDTH * 3)
+            .map(|i| (i / 3 + y) as u8 ^ (i % 3 * 85) as u8)
+            .collect())
+    });
+    write_strips(&carrier, (WIDTH, HEIGHT), Samples::Rgb, rows).unwrap();
+    let message = "A small note in a very large picture. ".repeat(100);
+    fs::write(&data, &message).unwrap();
+
+    let encode_to = |output: &std::path::Path, streaming: bool| {
+        let options = EncodeOptions {
+            streaming,
+            ..EncodeOptions::default()
+        };
+        encode(
+            &path(&data),
+            &path(&carrier),
+            &path(output),
+            &options,
+            &SilentProgress,
+        )
+        .unwrap()
+    };
+    let (_, in_memory_peak) = peak_allocation(|| encode_to(&in_memory_output, false));
+    let (summary, streamed_peak) = peak_allocation(|| encode_to(&streamed_output, true));
+    println!(
+        "Peak allocation: {} bytes in memory, {} bytes streamed, for a {} byte carrier",
+        in_memory_peak, streamed_peak, carrier_bytes
+    );
+
+    assert!(in_memory_peak > carrier_bytes);
+    assert!(streamed_peak < carrier_bytes / 4);
+    assert_eq!(summary.payload_bytes, message.len());
+
+    let decoded = decode_message(
+        &path(&streamed_output),
+        &DecodeOptions::default(),
+        &SilentProgress,
+    )
+    .unwrap();
+    assert_eq!(decoded, message);
+    assert_eq!(
+        image::open(&streamed_output).unwrap().into_rgb8(),
+        image::open(&in_memory_output).unwrap().into_rgb8()
+    );
+}